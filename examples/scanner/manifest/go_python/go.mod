@@ -0,0 +1,5 @@
+module github.com/offscale/go_python
+
+go 1.12
+
+require github.com/pkg/errors v0.8.1
@@ -0,0 +1,7 @@
+defmodule ElixirProject.MixProject do
+  use Mix.Project
+
+  def project do
+    [app: :elixir_project, version: "0.1.0", elixir: "~> 1.9"]
+  end
+end
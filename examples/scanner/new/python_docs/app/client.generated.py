@@ -0,0 +1 @@
+# generated by the API client generator
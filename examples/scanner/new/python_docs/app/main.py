@@ -0,0 +1,3 @@
+from app.util import greet
+
+print(greet())
@@ -0,0 +1 @@
+$(function () { $(".toc").show(); });
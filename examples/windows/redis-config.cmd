@@ -0,0 +1,7 @@
+@echo off
+sc.exe create redis-config binPath= "\"C:\Program Files\Redis\redis-server.exe\" --requirepass \"p@ss word%%\"" start= auto DisplayName= "redis-config 5.0.4" depend= "postgresql/redis" obj= ".\redis"
+sc.exe description redis-config "redis-config 5.0.4"
+sc.exe failure redis-config reset= 86400 actions= restart/5000/restart/5000/restart/5000
+sc.exe failureflag redis-config 1
+reg.exe add "HKLM\SYSTEM\CurrentControlSet\Services\redis-config" /v Environment /t REG_MULTI_SZ /d "GREETING=hello \"world\"\0RDBMS_URI=postgresql://app@localhost:5432/app" /f
+rem sc.exe cannot set the working directory C:\Redis, install with the XML wrapper instead
@@ -0,0 +1,1239 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    download, ports::Protocol, CommandLine, Downloads, Exposes, Install, OffSetup, System,
+};
+
+/// Distributions base images are translated to platforms of, by image name
+const DISTRIBUTIONS: &[(&str, &str)] = &[
+    ("ubuntu", "ubuntu"),
+    ("debian", "debian"),
+    ("centos", "centos"),
+    ("fedora", "fedora"),
+    ("alpine", "alpine"),
+    ("archlinux", "arch"),
+    ("archlinux/base", "arch"),
+    ("redhat/ubi8", "redhat"),
+    ("registry.access.redhat.com/ubi8", "redhat"),
+];
+
+/// Release versions of the codenames base images are tagged with
+const CODENAMES: &[(&str, &str)] = &[
+    ("trusty", "14.04"),
+    ("xenial", "16.04"),
+    ("bionic", "18.04"),
+    ("focal", "20.04"),
+    ("jammy", "22.04"),
+    ("stretch", "9"),
+    ("buster", "10"),
+    ("bullseye", "11"),
+    ("bookworm", "12"),
+];
+
+/// Where downloads without a destination are saved
+const DOWNLOAD_DIRECTORY: &str = "/tmp";
+
+/// An instruction of a Dockerfile, with its continuation lines joined
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    /// Line the instruction starts on, from 1
+    pub line: usize,
+    /// Uppercased, eg: RUN
+    pub keyword: String,
+    pub arguments: String,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.keyword, self.arguments)
+    }
+}
+
+/// An instruction, or part of one, left out of the configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct Untranslated {
+    pub line: usize,
+    pub instruction: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Untranslated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {}: {}",
+            self.line, self.instruction, self.reason
+        )
+    }
+}
+
+/// A configuration translated from a Dockerfile, with what it could not translate
+#[derive(Clone, Debug, PartialEq)]
+pub struct Translation {
+    pub config: Value,
+    pub untranslated: Vec<Untranslated>,
+}
+
+/// The instructions of a Dockerfile, without comments and blank lines
+pub fn parse(content: &str) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let mut current: Option<(usize, String)> = None;
+    for (idx, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        // comments may sit between continuation lines too
+        if trimmed.starts_with('#') || (trimmed.is_empty() && current.is_none()) {
+            continue;
+        }
+        let (start, mut text) = current.take().unwrap_or((idx + 1, String::new()));
+        match trimmed.strip_suffix('\\') {
+            Some(continued) => {
+                text.push_str(continued.trim());
+                text.push(' ');
+                current = Some((start, text));
+            }
+            None => {
+                text.push_str(trimmed);
+                instructions.extend(instruction(start, &text));
+            }
+        }
+    }
+    if let Some((start, text)) = current {
+        instructions.extend(instruction(start, &text));
+    }
+    instructions
+}
+
+fn instruction(line: usize, text: &str) -> Option<Instruction> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let (keyword, arguments) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    Some(Instruction {
+        line,
+        keyword: keyword.to_uppercase(),
+        arguments: arguments.trim().into(),
+    })
+}
+
+/// Translate the final stage of a Dockerfile into the configuration of the project `name`
+pub fn translate(instructions: &[Instruction], name: &str) -> Translation {
+    let mut image = Image::default();
+    let last_from = instructions
+        .iter()
+        .rposition(|instruction| instruction.keyword == "FROM");
+    for (idx, instruction) in instructions.iter().enumerate() {
+        match last_from {
+            Some(last) if idx < last && instruction.keyword == "FROM" => {
+                image.report(
+                    instruction,
+                    "build stage, only the final stage is translated",
+                );
+            }
+            Some(last) if idx < last && instruction.keyword != "ARG" => {}
+            _ => image.apply(instruction),
+        }
+    }
+    if last_from.is_none() {
+        image.untranslated.push(Untranslated {
+            line: 1,
+            instruction: "FROM".into(),
+            reason: "no base image, so no platform".into(),
+        });
+    }
+    Translation {
+        config: image.config(name),
+        untranslated: image.untranslated,
+    }
+}
+
+/// What the final stage builds, as it is translated
+#[derive(Default)]
+struct Image {
+    /// ARG defaults and ENV values, substituted in later instructions
+    variables: HashMap<String, String>,
+    platform: Option<String>,
+    platform_version: Option<String>,
+    version: Option<String>,
+    packages: Map<String, Value>,
+    downloads: Vec<Value>,
+    commands: Vec<String>,
+    tcp: Vec<Value>,
+    udp: Vec<Value>,
+    healthcheck: Option<Map<String, Value>>,
+    env: Vec<String>,
+    working_directory: Option<String>,
+    user: Option<String>,
+    entrypoint: Option<Value>,
+    command: Option<Value>,
+    untranslated: Vec<Untranslated>,
+}
+
+impl Image {
+    fn report(&mut self, instruction: &Instruction, reason: &str) {
+        self.untranslated.push(Untranslated {
+            line: instruction.line,
+            instruction: instruction.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    fn apply(&mut self, instruction: &Instruction) {
+        let arguments = substitute(&instruction.arguments, &self.variables);
+        match instruction.keyword.as_str() {
+            "ARG" => {
+                for (key, value) in assignments(&arguments) {
+                    self.variables.entry(key).or_insert(value);
+                }
+            }
+            "FROM" => self.from(instruction, &arguments),
+            "RUN" => self.run(instruction, &arguments),
+            "ADD" => self.add(instruction, &arguments),
+            "EXPOSE" => {
+                for port in words(&arguments) {
+                    let (number, protocol) = port.split_once('/').unwrap_or((&port, "tcp"));
+                    let value = number
+                        .parse::<u16>()
+                        .map(Value::from)
+                        .unwrap_or_else(|_| Value::from(number));
+                    match protocol {
+                        "udp" => self.udp.push(value),
+                        _ => self.tcp.push(value),
+                    }
+                }
+            }
+            "ENV" => {
+                for (key, value) in assignments(&arguments) {
+                    self.env
+                        .retain(|variable| !variable.starts_with(&format!("{}=", key)));
+                    self.env.push(format!("{}={}", key, value));
+                    self.variables.insert(key, value);
+                }
+            }
+            "LABEL" => {
+                let labels = assignments(&arguments);
+                match labels.iter().find(|(key, _)| key == "version") {
+                    Some((_, version)) if labels.len() == 1 => self.version = Some(version.clone()),
+                    Some((_, version)) => {
+                        self.version = Some(version.clone());
+                        self.report(instruction, "labels other than version are not translated");
+                    }
+                    None => {
+                        self.report(instruction, "labels other than version are not translated")
+                    }
+                }
+            }
+            "WORKDIR" => {
+                let dir = match &self.working_directory {
+                    Some(current) if !arguments.starts_with('/') => {
+                        format!("{}/{}", current.trim_end_matches('/'), arguments)
+                    }
+                    _ => arguments,
+                };
+                self.working_directory = Some(dir);
+            }
+            "USER" => {
+                let user = arguments.split(':').next().unwrap_or_default();
+                self.user = Some(user.into());
+            }
+            "ENTRYPOINT" => self.entrypoint = Some(command_line(&arguments)),
+            "CMD" => self.command = Some(command_line(&arguments)),
+            "HEALTHCHECK" => self.healthcheck(instruction, &arguments),
+            "COPY" => self.report(instruction, "copies files from the build context"),
+            _ => self.report(instruction, "has no equivalent"),
+        }
+    }
+
+    fn from(&mut self, instruction: &Instruction, arguments: &str) {
+        let image = match words(arguments)
+            .into_iter()
+            .find(|word| !word.starts_with("--"))
+        {
+            Some(image) => image,
+            None => return self.report(instruction, "no base image"),
+        };
+        if image == "scratch" {
+            return self.report(instruction, "empty base image, so no platform");
+        }
+        let (repository, tag) = split_image(&image);
+        let known = DISTRIBUTIONS
+            .iter()
+            .find(|(name, _)| *name == repository)
+            .map(|(_, platform)| platform.to_string());
+        let platform = known.clone().unwrap_or_else(|| {
+            repository
+                .rsplit('/')
+                .next()
+                .unwrap_or(repository)
+                .to_string()
+        });
+        if known.is_none() {
+            self.report(
+                instruction,
+                &format!(
+                    "{} is not a known distribution, its packages are listed under the {} platform",
+                    repository, platform
+                ),
+            );
+        }
+        self.platform = Some(platform);
+        self.platform_version = tag.filter(|tag| *tag != "latest").map(release);
+    }
+
+    fn run(&mut self, instruction: &Instruction, arguments: &str) {
+        if arguments.starts_with('[') {
+            return self.report(instruction, "exec form RUN is not translated");
+        }
+        let mut commands = vec![];
+        for command in arguments.split("&&").map(str::trim) {
+            if command.contains(['|', '>', ';']) {
+                commands.push(command.to_string());
+                continue;
+            }
+            let words = words(command);
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+            if is_housekeeping(&words) {
+                continue;
+            }
+            if let Some((manager, packages)) = install(&words) {
+                let list = self
+                    .packages
+                    .entry(manager)
+                    .or_insert_with(|| Value::Array(vec![]));
+                if let Value::Array(list) = list {
+                    list.extend(packages);
+                }
+                continue;
+            }
+            match download(&words, self.working_directory.as_deref()) {
+                Some(download) => self.push_download(instruction, download),
+                None => commands.push(command.to_string()),
+            }
+        }
+        if !commands.is_empty() {
+            self.commands.push(commands.join(" && "));
+        }
+    }
+
+    fn add(&mut self, instruction: &Instruction, arguments: &str) {
+        let paths: Vec<String> = match serde_json::from_str::<Vec<String>>(arguments) {
+            Ok(paths) => paths,
+            Err(_) => words(arguments)
+                .into_iter()
+                .filter(|word| !word.starts_with("--"))
+                .collect(),
+        };
+        let (sources, dest) = match paths.split_last() {
+            Some((dest, sources)) if !sources.is_empty() => (sources, dest),
+            _ => return self.report(instruction, "expected sources and a destination"),
+        };
+        for source in sources {
+            if !is_url(source) {
+                self.report(instruction, "copies files from the build context");
+                continue;
+            }
+            let mut download = Map::new();
+            download.insert("uri".into(), source.as_str().into());
+            if dest.ends_with('/') {
+                download.insert("dest".into(), dest.trim_end_matches('/').into());
+            } else {
+                let (dir, filename) = dest.rsplit_once('/').unwrap_or(("", dest));
+                download.insert("filename".into(), filename.into());
+                if !dir.is_empty() {
+                    download.insert("dest".into(), dir.into());
+                }
+            }
+            self.push_download(instruction, download);
+        }
+    }
+
+    fn push_download(&mut self, instruction: &Instruction, mut download: Map<String, Value>) {
+        let uri = download["uri"].as_str().unwrap_or_default().to_string();
+        self.untranslated.push(Untranslated {
+            line: instruction.line,
+            instruction: instruction.to_string(),
+            reason: format!("the sha512 of {} is unknown, fill it in", uri),
+        });
+        download.insert("sha512".into(), "".into());
+        self.downloads.push(Value::Object(download));
+    }
+
+    fn healthcheck(&mut self, instruction: &Instruction, arguments: &str) {
+        if arguments.trim() == "NONE" {
+            self.healthcheck = None;
+            return;
+        }
+        let (options, command) = match arguments.split_once("CMD") {
+            Some(split) => split,
+            None => return self.report(instruction, "expected HEALTHCHECK [OPTIONS] CMD command"),
+        };
+        let command = match command_line(command.trim()) {
+            Value::Array(args) => args
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+            command => command.as_str().unwrap_or_default().into(),
+        };
+        let mut readiness = Map::new();
+        readiness.insert("command".into(), command.into());
+        for option in words(options) {
+            let (key, value) = match option.trim_start_matches("--").split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => continue,
+            };
+            let value = match key.as_str() {
+                "interval" | "timeout" => seconds(&value).map(Value::from),
+                "retries" => value.parse::<u32>().ok().map(Value::from),
+                _ => None,
+            };
+            match value {
+                Some(value) => {
+                    readiness.insert(key, value);
+                }
+                None => self.report(instruction, &format!("option {} is not translated", option)),
+            }
+        }
+        self.healthcheck = Some(readiness);
+    }
+
+    fn config(&self, name: &str) -> Value {
+        let mut config = Map::new();
+        config.insert("name".into(), name.into());
+        config.insert(
+            "version".into(),
+            self.version
+                .clone()
+                .unwrap_or_else(|| "0.1.0".into())
+                .into(),
+        );
+
+        if let Some(platform_name) = &self.platform {
+            let mut platform = Map::new();
+            platform.insert(
+                "versions".into(),
+                json!(self.platform_version.iter().collect::<Vec<_>>()),
+            );
+            if !self.packages.is_empty() {
+                platform.insert("system".into(), Value::Object(self.packages.clone()));
+            }
+            if !self.downloads.is_empty() {
+                platform.insert("download_directory".into(), DOWNLOAD_DIRECTORY.into());
+                platform.insert("download".into(), self.downloads.clone().into());
+            }
+            if !self.commands.is_empty() {
+                platform.insert("source".into(), json!({ "install": self.commands }));
+            }
+            config.insert(
+                "dependencies".into(),
+                json!({ "platforms": { platform_name.as_str(): platform } }),
+            );
+        }
+
+        let mut ports = Map::new();
+        if !self.tcp.is_empty() {
+            ports.insert("tcp".into(), self.tcp.clone().into());
+        }
+        if !self.udp.is_empty() {
+            ports.insert("udp".into(), self.udp.clone().into());
+        }
+        if let Some(readiness) = &self.healthcheck {
+            ports.insert("readiness".into(), Value::Object(readiness.clone()));
+        }
+        if !ports.is_empty() {
+            config.insert("exposes".into(), json!({ "ports": ports }));
+        }
+
+        let command = match (&self.entrypoint, &self.command) {
+            (Some(Value::Array(entrypoint)), Some(Value::Array(arguments))) => Some(Value::Array(
+                entrypoint.iter().chain(arguments).cloned().collect(),
+            )),
+            (Some(entrypoint), _) => Some(entrypoint.clone()),
+            (None, command) => command.clone(),
+        };
+        if let Some(command) = command {
+            let mut start = Map::new();
+            start.insert("command".into(), command);
+            if let Some(dir) = &self.working_directory {
+                start.insert("working_directory".into(), dir.as_str().into());
+            }
+            if !self.env.is_empty() {
+                start.insert("env".into(), self.env.clone().into());
+            }
+            if let Some(user) = &self.user {
+                start.insert("user".into(), user.as_str().into());
+            }
+            config.insert("start".into(), Value::Object(start));
+        }
+        Value::Object(config)
+    }
+}
+
+/// Repository and tag of an image, without its registry's default prefixes
+pub(super) fn split_image(image: &str) -> (&str, Option<&str>) {
+    let image = image.split('@').next().unwrap_or_default();
+    let image = image.trim_start_matches("docker.io/");
+    let image = image.trim_start_matches("library/");
+    match image.rfind(':') {
+        Some(colon) if !image[colon..].contains('/') => {
+            (&image[..colon], Some(&image[colon + 1..]))
+        }
+        _ => (image, None),
+    }
+}
+
+/// `tag` as a release version, eg: bionic and 18.04-slim are 18.04
+fn release(tag: &str) -> String {
+    let tag = tag.split('-').next().unwrap_or(tag);
+    CODENAMES
+        .iter()
+        .find(|(codename, _)| *codename == tag)
+        .map_or(tag, |(_, version)| version)
+        .into()
+}
+
+/// Commands keeping images small, which installing natively has no use for
+fn is_housekeeping(words: &[&str]) -> bool {
+    match words {
+        [] => true,
+        ["apt-get", rest @ ..] | ["apt", rest @ ..] => rest
+            .iter()
+            .any(|word| ["update", "clean", "autoclean", "autoremove"].contains(word)),
+        ["apk", "update", ..] => true,
+        ["yum", rest @ ..] | ["dnf", rest @ ..] => {
+            rest.contains(&"clean") || rest.contains(&"makecache")
+        }
+        ["pacman", "-Sy"] | ["pacman", "-Syy"] => true,
+        ["rm", rest @ ..] => rest.iter().any(|path| {
+            path.starts_with("/var/lib/apt/lists")
+                || path.starts_with("/var/cache/apk")
+                || path.starts_with("/var/cache/yum")
+                || path.starts_with("/var/cache/dnf")
+        }),
+        _ => false,
+    }
+}
+
+/// The package manager and packages of an install command
+fn install(words: &[&str]) -> Option<(String, Vec<Value>)> {
+    let (manager, verb, rest) = match words {
+        ["apt-get", rest @ ..] | ["apt", rest @ ..] => ("apt", "install", rest),
+        ["apk", rest @ ..] => ("apk", "add", rest),
+        ["yum", rest @ ..] => ("yum", "install", rest),
+        ["dnf", rest @ ..] => ("dnf", "install", rest),
+        ["pacman", rest @ ..] => ("pacman", "-S", rest),
+        _ => return None,
+    };
+    // options taking a value, eg: apk add --virtual .build-deps
+    let with_value = ["--virtual", "-t", "-o", "--repository", "-X"];
+    let mut arguments = vec![];
+    let mut skip = false;
+    for word in rest {
+        if skip {
+            skip = false;
+        } else if with_value.contains(word) {
+            skip = true;
+        } else if manager == "pacman" && word.starts_with("-S") {
+            arguments.push("-S");
+        } else if !word.starts_with('-') {
+            arguments.push(word);
+        }
+    }
+    match arguments.split_first() {
+        Some((first, packages)) if *first == verb && !packages.is_empty() => Some((
+            manager.into(),
+            packages
+                .iter()
+                .map(|package| package_value(manager, package))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// `name`, or a table of name and version for `name=version` on apt and apk
+fn package_value(manager: &str, package: &str) -> Value {
+    match package.split_once('=') {
+        Some((name, version)) if manager == "apt" || manager == "apk" => {
+            json!({ "name": name, "version": version })
+        }
+        _ => package.into(),
+    }
+}
+
+/// The download of a curl or wget command, saved to `working_directory` unless told otherwise
+fn download(words: &[&str], working_directory: Option<&str>) -> Option<Map<String, Value>> {
+    let output_flags: &[&str] = match words.first() {
+        Some(&"curl") => &["-o", "--output"],
+        Some(&"wget") => &["-O", "--output-document"],
+        _ => return None,
+    };
+    let uri = words.iter().find(|word| is_url(word))?;
+    let output = words
+        .windows(2)
+        .find(|pair| output_flags.contains(&pair[0]))
+        .map(|pair| pair[1]);
+    let mut download = Map::new();
+    download.insert("uri".into(), (*uri).into());
+    match output.map(|output| output.rsplit_once('/').unwrap_or(("", output))) {
+        Some((dir, filename)) => {
+            download.insert("filename".into(), filename.into());
+            let dir = if dir.is_empty() {
+                working_directory
+            } else {
+                Some(dir)
+            };
+            if let Some(dir) = dir {
+                download.insert("dest".into(), dir.into());
+            }
+        }
+        None => {
+            if let Some(dir) = working_directory {
+                download.insert("dest".into(), dir.into());
+            }
+        }
+    }
+    Some(download)
+}
+
+fn is_url(text: &str) -> bool {
+    ["http://", "https://", "ftp://"]
+        .iter()
+        .any(|scheme| text.starts_with(scheme))
+}
+
+/// A list of arguments for the exec form, eg: ["redis-server"], or a string for the shell form
+fn command_line(arguments: &str) -> Value {
+    match serde_json::from_str::<Vec<String>>(arguments) {
+        Ok(args) => args.into(),
+        Err(_) => arguments.into(),
+    }
+}
+
+/// Seconds of a duration, eg: 30s, 1m or 1m30s
+pub(super) fn seconds(duration: &str) -> Option<u64> {
+    let mut total = 0;
+    let mut number = String::new();
+    for c in duration.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' | 's' => {
+                let unit = match c {
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                total += number.parse::<u64>().ok()? * unit;
+                number.clear();
+            }
+            _ => return None,
+        }
+    }
+    if number.is_empty() {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+/// `KEY=value` pairs, or the legacy `KEY value` form of ENV
+fn assignments(arguments: &str) -> Vec<(String, String)> {
+    let words = words(arguments);
+    match words.first() {
+        Some(first) if !first.contains('=') => {
+            let value = arguments.trim()[first.len()..].trim();
+            vec![(first.clone(), unquote(value))]
+        }
+        _ => words
+            .into_iter()
+            .map(|word| match word.split_once('=') {
+                Some((key, value)) => (key.into(), value.into()),
+                None => (word, String::new()),
+            })
+            .collect(),
+    }
+}
+
+fn unquote(text: &str) -> String {
+    words(text).join(" ")
+}
+
+/// Split on whitespace outside of quotes, removing the quotes
+fn words(text: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut in_word = false;
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(word.split_off(0));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Replace `$NAME`, `${NAME}` and `${NAME:-default}` with the values of `variables`. References to
+/// unknown variables are left for the shell, with every other `$`, escaped as `$$` so that loading
+/// the imported config does not interpolate them.
+fn substitute(text: &str, variables: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[..dollar]);
+        let reference = &rest[dollar..];
+        rest = &rest[dollar + 1..];
+        let (expression, after) = if rest.starts_with('{') {
+            match rest.find('}') {
+                Some(end) => (&rest[1..end], &rest[end + 1..]),
+                None => ("", rest),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        if expression.is_empty() {
+            out.push_str("$$");
+            continue;
+        }
+        let (name, default) = expression.split_once(":-").unwrap_or((expression, ""));
+        match variables.get(name) {
+            Some(value) => out.push_str(value),
+            // left for the shell, which may know it at run time
+            None if default.is_empty() => {
+                out.push('$');
+                out.push_str(&reference[..reference.len() - after.len()]);
+            }
+            None => out.push_str(default),
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A Dockerfile rendered from a platform, with what it could not express
+#[derive(Clone, Debug, PartialEq)]
+pub struct Export {
+    pub dockerfile: String,
+    pub unexported: Vec<String>,
+}
+
+/// Render the Dockerfile of `config` on `platform`, eg: ubuntu. The output only depends on the
+/// configuration, so it can be checked in and diffed.
+pub fn render(config: &OffSetup, platform: &str) -> Result<Export, String> {
+    let settings = config
+        .dependencies
+        .as_ref()
+        .and_then(|d| d.platforms.as_ref())
+        .and_then(|platforms| platforms.get(platform))
+        .ok_or_else(|| format!("dependencies.platforms has no {} platform", platform))?;
+    let image = DISTRIBUTIONS
+        .iter()
+        .find(|(_, name)| *name == platform)
+        .map(|(image, _)| image)
+        .ok_or_else(|| format!("{} has no base image", platform))?;
+    let tag = settings
+        .versions
+        .iter()
+        .find(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+        .map_or("latest", String::as_str);
+
+    let mut out = Dockerfile::default();
+    out.line("# syntax=docker/dockerfile:1");
+    out.line(&format!(
+        "# {} {}, generated by `offsetup export dockerfile --platform {}`",
+        config.name, config.version, platform
+    ));
+    match settings.arch.as_deref().and_then(docker_arch) {
+        Some(arch) => out.line(&format!("FROM --platform={} {}:{}", arch, image, tag)),
+        None => out.line(&format!("FROM {}:{}", image, tag)),
+    }
+
+    out.gap();
+    for command in settings.pre_install.iter().flatten() {
+        out.line(&format!("RUN {}", command));
+    }
+    let systems = settings
+        .system
+        .iter()
+        .chain(settings.source.iter().flat_map(|s| s.system.iter()));
+    out.packages(systems);
+    out.downloads(&settings.download, &settings.download_directory);
+    if let Some(source) = &settings.source {
+        out.downloads(&source.download, &source.download_directory);
+        match &source.install {
+            Some(Install::Commands(commands)) if !commands.is_empty() => {
+                out.gap();
+                if let Some(dir) = &source.download_directory {
+                    out.line(&format!("WORKDIR {}", dir));
+                }
+                out.line(&format!("RUN {}", commands.join(" \\\n && ")));
+            }
+            Some(Install::Ref { reference }) => out.unexported.push(format!(
+                "source.install: $ref {} is not resolved",
+                reference
+            )),
+            _ => {}
+        }
+    }
+    for name in settings.services.iter().flatten().map(|(name, _)| name) {
+        out.unexported.push(format!(
+            "services.{}: containers run no service manager",
+            name
+        ));
+    }
+
+    out.gap();
+    let ports: Vec<String> = config
+        .exposes
+        .iter()
+        .flat_map(Exposes::entries)
+        .filter(|(_, port)| port.is_public())
+        .map(|(protocol, port)| {
+            let numbers = match port.end {
+                Some(end) => format!("{}-{}", port.port, end),
+                None => port.port.to_string(),
+            };
+            match protocol {
+                Protocol::Tcp => numbers,
+                Protocol::Udp => format!("{}/udp", numbers),
+            }
+        })
+        .collect();
+    if !ports.is_empty() {
+        out.line(&format!("EXPOSE {}", ports.join(" ")));
+    }
+    if let Some(start) = &config.start {
+        if let Some(dir) = &start.working_directory {
+            out.line(&format!("WORKDIR {}", dir));
+        }
+        for variable in start.env.iter().flatten() {
+            let (key, value) = variable.split_once('=').unwrap_or((variable, ""));
+            if config.secrets.reveals(value) {
+                // image layers are readable by anyone pulling the image
+                out.unexported.push(format!(
+                    "start.env: {} is secret, pass it when running the container, eg: docker run -e {}",
+                    key, key
+                ));
+                continue;
+            }
+            out.line(&format!("ENV {}={}", key, env_value(value)));
+        }
+        if let Some(user) = &start.user {
+            out.line(&format!("USER {}", user));
+        }
+        match &start.command {
+            CommandLine::Shell(command) => out.line(&format!("CMD {}", command)),
+            CommandLine::Args(args) => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| Value::from(&**arg).to_string())
+                    .collect();
+                out.line(&format!("CMD [{}]", args.join(", ")))
+            }
+        }
+    }
+
+    Ok(Export {
+        dockerfile: out.content.trim_end().to_string() + "\n",
+        unexported: out.unexported,
+    })
+}
+
+/// A Dockerfile being written, one blank line between groups of instructions
+#[derive(Default)]
+struct Dockerfile {
+    content: String,
+    unexported: Vec<String>,
+}
+
+impl Dockerfile {
+    fn line(&mut self, line: &str) {
+        self.content.push_str(line);
+        self.content.push('\n');
+    }
+
+    fn gap(&mut self) {
+        if !self.content.ends_with("\n\n") {
+            self.content.push('\n');
+        }
+    }
+
+    /// One RUN per package manager, its downloads cached between builds
+    fn packages<'a>(&mut self, systems: impl Iterator<Item = &'a System>) {
+        let mut installs: Vec<(&ImageInstall, Vec<String>)> = vec![];
+        for system in systems {
+            for (manager, packages) in system.managers() {
+                let install = match ImageInstall::of(manager) {
+                    Some(install) => install,
+                    None => {
+                        self.unexported
+                            .push(format!("system.{}: cannot install in a container", manager));
+                        continue;
+                    }
+                };
+                let mut specs = vec![];
+                for package in packages.resolve() {
+                    match package.install_spec(manager) {
+                        Ok(spec) => specs.push(spec),
+                        Err(e) => self.unexported.push(format!("system.{}: {}", manager, e)),
+                    }
+                }
+                match installs
+                    .iter_mut()
+                    .find(|(other, _)| other.program == install.program)
+                {
+                    Some((_, all)) => all.extend(specs),
+                    None => installs.push((install, specs)),
+                }
+            }
+        }
+        for (install, mut specs) in installs {
+            specs.dedup();
+            if specs.is_empty() {
+                continue;
+            }
+            self.gap();
+            let mounts: Vec<String> = install
+                .caches
+                .iter()
+                .map(|cache| {
+                    format!(
+                        "--mount=type=cache,target={},sharing=locked \\\n    ",
+                        cache
+                    )
+                })
+                .collect();
+            self.line(&format!(
+                "RUN {}{} {}",
+                mounts.concat(),
+                install.command,
+                specs.join(" ")
+            ));
+        }
+    }
+
+    /// ADD each file, then check its sha512 and extract it when asked to
+    fn downloads(&mut self, downloads: &Option<Downloads>, directory: &Option<String>) {
+        let directory = Path::new(directory.as_deref().unwrap_or(DOWNLOAD_DIRECTORY));
+        for download in downloads.iter().flat_map(Downloads::as_slice) {
+            let destination = download.destination(directory);
+            let path = destination.display();
+            self.gap();
+            self.line(&format!("ADD {} {}", download.uri.unparse(), path));
+            let mut commands = vec![format!(
+                "echo \"{}  {}\" | sha512sum -c -",
+                download.sha512.to_lowercase(),
+                path
+            )];
+            if download.extract.unwrap_or(false) {
+                commands.push(download::extract_command(&destination).join(" "));
+            }
+            self.line(&format!("RUN {}", commands.join(" \\\n && ")));
+        }
+    }
+}
+
+/// How a package manager installs packages in a base image
+struct ImageInstall {
+    program: &'static str,
+    /// Followed by the packages
+    command: &'static str,
+    /// Directories kept between builds
+    caches: &'static [&'static str],
+}
+
+/// apt, apt_get and aptitude share the apt-get install
+const IMAGE_INSTALLS: &[ImageInstall] = &[
+    ImageInstall {
+        program: "apt-get",
+        // the images delete downloaded packages, which would leave the cache empty
+        command: "rm -f /etc/apt/apt.conf.d/docker-clean \\\n && apt-get update \\\n && apt-get install -y --no-install-recommends",
+        caches: &["/var/cache/apt", "/var/lib/apt"],
+    },
+    ImageInstall {
+        program: "apk",
+        command: "apk add",
+        caches: &["/var/cache/apk"],
+    },
+    ImageInstall {
+        program: "dnf",
+        command: "dnf install -y",
+        caches: &["/var/cache/dnf"],
+    },
+    ImageInstall {
+        program: "yum",
+        command: "yum install -y",
+        caches: &["/var/cache/yum"],
+    },
+    ImageInstall {
+        program: "pacman",
+        command: "pacman -Sy --noconfirm",
+        caches: &["/var/cache/pacman/pkg"],
+    },
+];
+
+impl ImageInstall {
+    fn of(manager: &str) -> Option<&'static ImageInstall> {
+        let program = match manager {
+            "apt" | "apt_get" | "aptitude" => "apt-get",
+            other => other,
+        };
+        IMAGE_INSTALLS
+            .iter()
+            .find(|install| install.program == program)
+    }
+}
+
+/// Docker platform of a CPU architecture, eg: x86_64 is linux/amd64
+fn docker_arch(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" | "amd64" => Some("linux/amd64"),
+        "aarch64" | "arm64" => Some("linux/arm64"),
+        "armv7" | "armv7l" | "armhf" => Some("linux/arm/v7"),
+        "i386" | "i686" | "x86" => Some("linux/386"),
+        _ => None,
+    }
+}
+
+/// An ENV value, quoted when it has to be
+fn env_value(value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || "\"'\\$".contains(c)) {
+        Value::from(value).to_string().replace('$', "\\$")
+    } else {
+        value.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn translate_example(name: &str) -> Translation {
+        let content = fs::read_to_string(format!("examples/docker/{}", name)).unwrap();
+        translate(&parse(&content), "example")
+    }
+
+    #[test]
+    fn joins_continuation_lines() {
+        let instructions = parse(
+            "# syntax\nFROM alpine:3.10\n\nRUN apk add \\\n    # the server\n    redis \\\n    curl\nCMD [\"redis-server\"]\n",
+        );
+        assert_eq!(
+            vec![
+                (2, "FROM", "alpine:3.10"),
+                (4, "RUN", "apk add redis curl"),
+                (8, "CMD", "[\"redis-server\"]"),
+            ],
+            instructions
+                .iter()
+                .map(|i| (i.line, i.keyword.as_str(), i.arguments.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn translates_ubuntu_dockerfile() {
+        let translation = translate_example("Dockerfile.ubuntu");
+        let expected: Value =
+            serde_json::from_str(&fs::read_to_string("examples/docker/ubuntu.json").unwrap())
+                .unwrap();
+        assert_eq!(expected, translation.config);
+        assert_eq!(
+            vec![
+                "line 1: FROM golang:1.12 AS builder: build stage, only the final stage is translated",
+                "line 7: LABEL version=\"1.2.0\" maintainer=\"dev@example.com\": labels other than version are not translated",
+                "line 13: RUN curl -fsSL -o /usr/local/bin/dumb-init https://github.com/Yelp/dumb-init/releases/download/v1.2.2/dumb-init_1.2.2_amd64 && chmod +x /usr/local/bin/dumb-init: the sha512 of https://github.com/Yelp/dumb-init/releases/download/v1.2.2/dumb-init_1.2.2_amd64 is unknown, fill it in",
+                "line 15: ADD https://example.com/config.tar.gz /opt/app/: the sha512 of https://example.com/config.tar.gz is unknown, fill it in",
+                "line 16: COPY --from=builder /go/bin/app /opt/app/app: copies files from the build context",
+                "line 18: VOLUME /var/lib/app: has no equivalent",
+            ],
+            translation
+                .untranslated
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn translates_alpine_dockerfile() {
+        let translation = translate_example("Dockerfile.alpine");
+        let config = &translation.config;
+        let alpine = &config["dependencies"]["platforms"]["alpine"];
+        assert_eq!(json!(["3.10"]), alpine["versions"]);
+        assert_eq!(
+            json!(["redis", { "name": "tini", "version": "0.18.0-r0" }]),
+            alpine["system"]["apk"]
+        );
+        assert_eq!(json!({ "tcp": [6379] }), config["exposes"]["ports"]);
+        assert_eq!(
+            json!({ "command": ["tini", "--", "redis-server", "--protected-mode", "no"], "user": "redis" }),
+            config["start"]
+        );
+        assert!(translation.untranslated.is_empty());
+    }
+
+    #[test]
+    fn maps_base_images_to_platforms() {
+        for (from, platform, versions) in &[
+            ("FROM ubuntu:bionic", "ubuntu", json!(["18.04"])),
+            ("FROM debian:buster-slim", "debian", json!(["10"])),
+            ("FROM docker.io/library/centos:7", "centos", json!(["7"])),
+            ("FROM --platform=linux/amd64 fedora", "fedora", json!([])),
+            ("FROM archlinux:latest", "arch", json!([])),
+        ] {
+            let config = translate(&parse(from), "a").config;
+            assert_eq!(
+                versions, &config["dependencies"]["platforms"][platform]["versions"],
+                "{}",
+                from
+            );
+        }
+        let translation = translate(&parse("FROM python:3.7"), "a");
+        assert!(translation.config["dependencies"]["platforms"]["python"].is_object());
+        assert_eq!(1, translation.untranslated.len());
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let variables: HashMap<String, String> = vec![("VERSION".to_string(), "5.0.4".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            "redis-5.0.4 5.0.4 $$HOME $${HOME} x $$$$",
+            substitute(
+                "redis-$VERSION ${VERSION} $HOME ${HOME} ${MISSING:-x} $$",
+                &variables
+            )
+        );
+        assert_eq!(Some(90), seconds("1m30s"));
+        assert_eq!(None, seconds("soon"));
+    }
+
+    #[test]
+    fn shell_variables_survive_loading() {
+        let translation = translate(
+            &parse("FROM ubuntu:18.04\nRUN echo $HOME_DIR ${HOME_DIR} $$\n"),
+            "example",
+        );
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(
+                &translation.config.to_string(),
+                config::FileFormat::Json,
+            ))
+            .unwrap();
+        let (config, _) = crate::interpolate::interpolate(config, &|_| None).unwrap();
+        let loaded: Value = config.try_into().unwrap();
+        assert!(
+            loaded.to_string().contains("echo $HOME_DIR ${HOME_DIR} $$"),
+            "{}",
+            loaded
+        );
+    }
+
+    fn export_example(platform: &str) -> Result<Export, String> {
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::with_name("examples/docker/offsetup.yml"))
+            .unwrap();
+        render(&config.try_into().unwrap(), platform)
+    }
+
+    #[test]
+    fn renders_ubuntu_dockerfile() {
+        let export = export_example("ubuntu").unwrap();
+        assert_eq!(
+            fs::read_to_string("examples/docker/Dockerfile.export").unwrap(),
+            export.dockerfile
+        );
+        assert_eq!(Vec::<String>::new(), export.unexported);
+        assert_eq!(export, export_example("ubuntu").unwrap());
+    }
+
+    #[test]
+    fn leaves_secrets_out() {
+        env::set_var("LEAVES_SECRETS_OUT_DB_PASSWORD", "hunter2");
+        env::set_var("LEAVES_SECRETS_OUT_PORT", "8080");
+        let yaml = "name: app\nversion: '1'\ndependencies:\n  platforms:\n    ubuntu:\n      versions: ['18.04']\nstart:\n  command: ./app\n  env:\n    - DB_PASSWORD=${LEAVES_SECRETS_OUT_DB_PASSWORD}\n    - GREETING=hello\n    - PORT=${LEAVES_SECRETS_OUT_PORT}\n";
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .unwrap();
+        let export = render(
+            &crate::load(config, Path::new("offsetup.yml")).unwrap(),
+            "ubuntu",
+        )
+        .unwrap();
+        assert!(!export.dockerfile.contains("hunter2"));
+        assert!(export.dockerfile.contains("ENV GREETING=hello"));
+        assert!(
+            export.dockerfile.contains("PORT=8080"),
+            "{}",
+            export.dockerfile
+        );
+        assert_eq!(
+            vec!["start.env: DB_PASSWORD is secret, pass it when running the container, eg: docker run -e DB_PASSWORD"],
+            export.unexported
+        );
+    }
+
+    #[test]
+    fn unzips_zip_downloads() {
+        let yaml = "name: app\nversion: '1'\ndependencies:\n  platforms:\n    ubuntu:\n      versions: ['18.04']\n      source:\n        download_directory: /opt/downloads\n        download:\n          uri: http://example.com/app.zip\n          sha512: abc\n          extract: true\n";
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .unwrap();
+        let export = render(&config.try_into().unwrap(), "ubuntu").unwrap();
+        assert!(
+            export
+                .dockerfile
+                .contains("unzip -o -q /opt/downloads/app.zip -d /opt/downloads"),
+            "{}",
+            export.dockerfile
+        );
+    }
+
+    #[test]
+    fn reports_what_it_cannot_export() {
+        let export = export_example("alpine").unwrap();
+        assert!(export.dockerfile.contains("FROM alpine:3.10\n"));
+        assert_eq!(
+            vec!["system.snap: cannot install in a container"],
+            export.unexported
+        );
+        assert_eq!(
+            Err("mac has no base image".to_string()),
+            export_example("mac")
+        );
+        assert_eq!(
+            Err("dependencies.platforms has no fedora platform".to_string()),
+            export_example("fedora")
+        );
+    }
+}
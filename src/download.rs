@@ -0,0 +1,279 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use sha2::{Digest, Sha512};
+
+use crate::{runner::CommandRunner, Download};
+
+/// How many files are fetched at once
+pub const DOWNLOAD_CONCURRENCY: usize = 4;
+
+/// What happened to one file of a batch, reported as soon as it happens
+#[derive(Clone, Debug, PartialEq)]
+pub enum Progress {
+    Started { filename: String },
+    Verified { filename: String, path: PathBuf },
+    Failed { filename: String, error: String },
+}
+
+impl Download {
+    /// `filename`, defaulting to the last segment of the URI
+    pub fn filename(&self) -> String {
+        match &self.filename {
+            Some(filename) => filename.clone(),
+            None => self
+                .uri
+                .path
+                .rsplit('/')
+                .find(|segment| !segment.is_empty())
+                .unwrap_or("download")
+                .into(),
+        }
+    }
+
+    /// Where the file is saved: `dest` when set, otherwise `directory`
+    pub fn destination(&self, directory: &Path) -> PathBuf {
+        let dir = self.dest.as_ref().map_or(directory, Path::new);
+        dir.join(self.filename())
+    }
+}
+
+/// Fetch every download with at most `concurrency` at a time, verifying each one independently
+/// so a single bad file does not stop the others. Results are in the order of `downloads`.
+pub fn fetch_all(
+    downloads: &[&Download],
+    directory: &Path,
+    concurrency: usize,
+    runner: &(dyn CommandRunner + Sync),
+    progress: &(dyn Fn(Progress) + Sync),
+) -> Vec<io::Result<PathBuf>> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<io::Result<PathBuf>>>> =
+        Mutex::new(downloads.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, downloads.len().max(1)) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                let download = match downloads.get(idx) {
+                    Some(download) => download,
+                    None => break,
+                };
+                let filename = download.filename();
+                progress(Progress::Started {
+                    filename: filename.clone(),
+                });
+                let result = fetch(download, directory, runner);
+                progress(match &result {
+                    Ok(path) => Progress::Verified {
+                        filename,
+                        path: path.clone(),
+                    },
+                    Err(e) => Progress::Failed {
+                        filename,
+                        error: e.to_string(),
+                    },
+                });
+                results.lock().unwrap()[idx] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every download is fetched by a worker"))
+        .collect()
+}
+
+/// Download, verify then extract a single file
+pub fn fetch(
+    download: &Download,
+    directory: &Path,
+    runner: &dyn CommandRunner,
+) -> io::Result<PathBuf> {
+    let path = download.destination(directory);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let uri = download.uri.unparse();
+    let destination = path.to_string_lossy();
+    run(runner, "curl", &["-fsSL", "-o", &destination, &uri])?;
+
+    let checksum = sha512_file(&path)?;
+    if !checksum.eq_ignore_ascii_case(&download.sha512) {
+        fs::remove_file(&path)?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "sha512 mismatch for {}: expected {}, got {}",
+                uri, download.sha512, checksum
+            ),
+        ));
+    }
+
+    if download.extract == Some(true) {
+        extract(&path, runner)?;
+    }
+    Ok(path)
+}
+
+fn run(runner: &dyn CommandRunner, program: &str, args: &[&str]) -> io::Result<()> {
+    let output = runner.run(program, args)?;
+    if output.success {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`{} {}` failed: {}",
+            program,
+            args.join(" "),
+            output.stderr.trim()
+        )))
+    }
+}
+
+/// Hex encoded SHA-512 checksum of the file at `path`
+pub fn sha512_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha512::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Extract an archive next to itself
+fn extract(archive: &Path, runner: &dyn CommandRunner) -> io::Result<()> {
+    let command = extract_command(archive);
+    let args: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+    run(runner, &command[0], &args)
+}
+
+/// Program and arguments extracting an archive next to itself: unzip for .zip files, tar otherwise
+pub fn extract_command(archive: &Path) -> Vec<String> {
+    let dir = archive
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .to_string_lossy();
+    let file = archive.to_string_lossy();
+    let command = if file.ends_with(".zip") {
+        vec!["unzip", "-o", "-q", &file, "-d", &dir]
+    } else {
+        vec!["tar", "-xf", &file, "-C", &dir]
+    };
+    command.into_iter().map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{fake::FakeRunner, CommandOutput};
+    use config::{Config, File, FileFormat};
+    use std::env;
+
+    fn download(yaml: &str) -> Download {
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(yaml, FileFormat::Yaml))
+            .unwrap();
+        config.try_into().unwrap()
+    }
+
+    /// Fetches by writing the last segment of the URI as the file's content
+    struct CurlRunner;
+
+    impl CommandRunner for CurlRunner {
+        fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+            assert_eq!("curl", program);
+            let content = args[3].rsplit('/').next().unwrap();
+            fs::write(args[2], content)?;
+            Ok(CommandOutput {
+                success: content != "missing",
+                stdout: String::new(),
+                stderr: "404".into(),
+            })
+        }
+    }
+
+    const SHA512_A: &str = "1f40fc92da241694750979ee6cf582f2d5d7d28e18335de05abc54d0560e0f5302860c652bf08d560252aa5e74210546f369fbbbce8c12cfc7957b2652fe9a75";
+
+    #[test]
+    fn can_name_downloads() {
+        let uri = "uri: https://www.7-zip.org/a/7z1900-x64.msi\nsha512: a\n";
+        assert_eq!("7z1900-x64.msi", download(uri).filename());
+        assert_eq!(
+            "7zip.msi",
+            download(&format!("{}filename: 7zip.msi\n", uri)).filename()
+        );
+        assert_eq!(
+            PathBuf::from("/opt/7zip/7z1900-x64.msi"),
+            download(&format!("{}dest: /opt/7zip\n", uri)).destination(Path::new("/tmp"))
+        );
+    }
+
+    #[test]
+    fn verifies_each_download_independently() {
+        let directory = env::temp_dir().join("offsetup-download-test");
+        let good = download(&format!(
+            "uri: https://example.com/a\nsha512: {}\n",
+            SHA512_A
+        ));
+        let corrupt = download(&format!(
+            "uri: https://example.com/b\nsha512: {}\n",
+            SHA512_A
+        ));
+        let missing = download(&format!(
+            "uri: https://example.com/missing\nsha512: {}\n",
+            SHA512_A
+        ));
+        let events = Mutex::new(vec![]);
+
+        let results = fetch_all(
+            &[&good, &corrupt, &missing],
+            &directory,
+            2,
+            &CurlRunner,
+            &|event| events.lock().unwrap().push(event),
+        );
+
+        assert_eq!(directory.join("a"), *results[0].as_ref().unwrap());
+        assert!(results[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("sha512 mismatch"));
+        assert!(!directory.join("b").exists(), "corrupt files are removed");
+        assert!(results[2].as_ref().unwrap_err().to_string().contains("404"));
+
+        let events = events.into_inner().unwrap();
+        assert_eq!(6, events.len());
+        assert!(events.contains(&Progress::Verified {
+            filename: "a".into(),
+            path: directory.join("a"),
+        }));
+        assert!(events.contains(&Progress::Started {
+            filename: "missing".into()
+        }));
+    }
+
+    #[test]
+    fn can_extract_archives() {
+        let path = env::temp_dir().join("offsetup-extract-test").join("a.zip");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let command = format!(
+            "unzip -o -q {} -d {}",
+            path.display(),
+            path.parent().unwrap().display()
+        );
+        let runner = FakeRunner::new().respond(&command, "");
+
+        extract(&path, &runner).unwrap();
+        assert_eq!(vec![command], runner.calls());
+    }
+}
@@ -0,0 +1,590 @@
+use std::{fmt, path::Path};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    plan::{step, Plan, Step},
+    ports::Protocol,
+    runner::CommandRunner,
+};
+
+/// Firewall the rules are written for
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Nftables,
+    Iptables,
+    Ufw,
+    Firewalld,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Nftables => "nftables",
+            Backend::Iptables => "iptables",
+            Backend::Ufw => "ufw",
+            Backend::Firewalld => "firewalld",
+        })
+    }
+}
+
+/// Accept connections to `port`, from `source` only when given
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub protocol: Protocol,
+    pub port: u16,
+    /// CIDR, eg: 10.0.0.0/8
+    pub source: Option<String>,
+}
+
+impl Rule {
+    fn is_ipv6(&self) -> bool {
+        self.source
+            .as_ref()
+            .is_some_and(|source| source.contains(':'))
+    }
+}
+
+/// A rule for each port and source, any source when `sources` is empty
+pub fn rules(ports: &[(Protocol, u16)], sources: &[String]) -> Vec<Rule> {
+    ports
+        .iter()
+        .flat_map(|&(protocol, port)| {
+            let sources: Vec<Option<String>> = if sources.is_empty() {
+                vec![None]
+            } else {
+                sources.iter().cloned().map(Some).collect()
+            };
+            sources.into_iter().map(move |source| Rule {
+                protocol,
+                port,
+                source,
+            })
+        })
+        .collect()
+}
+
+/// The firewall managing the host. ufw and firewalld are front-ends of the others, so they are
+/// preferred while running.
+pub fn detect(runner: &dyn CommandRunner) -> Option<Backend> {
+    let succeeds = |program: &str, args: &[&str], expected: &str| {
+        runner
+            .run(program, args)
+            .is_ok_and(|output| output.success && output.stdout.contains(expected))
+    };
+    if succeeds("ufw", &["status"], "Status: active") {
+        Some(Backend::Ufw)
+    } else if succeeds("firewall-cmd", &["--state"], "running") {
+        Some(Backend::Firewalld)
+    } else if succeeds("nft", &["--version"], "nftables") {
+        Some(Backend::Nftables)
+    } else if succeeds("iptables", &["--version"], "iptables") {
+        Some(Backend::Iptables)
+    } else {
+        None
+    }
+}
+
+/// A base chain of the host's nftables ruleset filtering input, eg: `inet filter input`
+#[derive(Clone, Debug, PartialEq)]
+pub struct NftChain {
+    pub family: String,
+    pub table: String,
+    pub name: String,
+    /// Handles of its rules jumping to the chain of the project
+    pub jumps: Vec<u64>,
+}
+
+/// The base chains filtering input in the tables of `nft -j list ruleset` output, but those of
+/// offsetup. Every base chain hooked on input sees each packet and a drop in any of them is final,
+/// so the rules of `project` must be jumped to from each of these to take effect.
+pub fn nft_input_chains(ruleset: &str, project: &str) -> Vec<NftChain> {
+    let objects: Vec<Value> = serde_json::from_str::<Value>(ruleset)
+        .ok()
+        .and_then(|ruleset| ruleset["nftables"].as_array().cloned())
+        .unwrap_or_default();
+    let target = nft_table(project);
+    let mut chains: Vec<NftChain> = objects
+        .iter()
+        .map(|object| &object["chain"])
+        .filter(|chain| chain["type"] == "filter" && chain["hook"] == "input")
+        .filter(|chain| {
+            !chain["table"]
+                .as_str()
+                .unwrap_or_default()
+                .starts_with("offsetup_")
+        })
+        .map(|chain| NftChain {
+            family: chain["family"].as_str().unwrap_or_default().into(),
+            table: chain["table"].as_str().unwrap_or_default().into(),
+            name: chain["name"].as_str().unwrap_or_default().into(),
+            jumps: vec![],
+        })
+        .collect();
+    for rule in objects.iter().map(|object| &object["rule"]) {
+        let jumps = rule["expr"].as_array().is_some_and(|expressions| {
+            expressions
+                .iter()
+                .any(|expression| expression["jump"]["target"] == target.as_str())
+        });
+        let chain = chains.iter_mut().find(|chain| {
+            rule["family"] == chain.family.as_str()
+                && rule["table"] == chain.table.as_str()
+                && rule["chain"] == chain.name.as_str()
+        });
+        if let (true, Some(chain), Some(handle)) = (jumps, chain, rule["handle"].as_u64()) {
+            chain.jumps.push(handle);
+        }
+    }
+    chains
+}
+
+/// The host's input chains, from its ruleset, none when nft cannot list it
+fn host_chains(project: &str, runner: &dyn CommandRunner) -> Vec<NftChain> {
+    match runner.run("nft", &["-j", "list", "ruleset"]) {
+        Ok(output) if output.success => nft_input_chains(&output.stdout, project),
+        _ => vec![],
+    }
+}
+
+/// `rule` as an nftables statement, eg: `ip saddr 10.0.0.0/8 tcp dport 80 accept`
+fn nft_rule(rule: &Rule) -> String {
+    let mut out = String::new();
+    if let Some(source) = &rule.source {
+        let family = if rule.is_ipv6() { "ip6" } else { "ip" };
+        out.push_str(&format!("{} saddr {} ", family, source));
+    }
+    out.push_str(&format!("{} dport {} accept", rule.protocol, rule.port));
+    out
+}
+
+/// nftables table of `project`, deleted then created again so that applying twice is harmless.
+/// For hosts without input chains of their own, that could drop what it accepts.
+pub fn nft_ruleset(project: &str, rules: &[Rule]) -> String {
+    let table = format!("inet {}", nft_table(project));
+    let mut out = format!("table {}\ndelete table {}\n\n", table, table);
+    out.push_str(&format!("table {} {{\n", table));
+    out.push_str("\tchain input {\n");
+    out.push_str("\t\ttype filter hook input priority 0; policy accept;\n");
+    for rule in rules {
+        out.push_str(&format!("\t\t{}\n", nft_rule(rule)));
+    }
+    out.push_str("\t}\n}\n");
+    out
+}
+
+/// A chain of `project` in the table of each of `chains`, jumped to first from it. The chain is
+/// flushed and stale jumps deleted, so that applying twice is harmless. Rules restricted to sources
+/// of another family than the table's are left out.
+pub fn nft_host_ruleset(project: &str, rules: &[Rule], chains: &[NftChain]) -> String {
+    let name = nft_table(project);
+    let mut out = String::new();
+    for chain in chains {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let table = format!("{} {}", chain.family, chain.table);
+        out.push_str(&format!(
+            "table {} {{\n\tchain {} {{\n\t}}\n}}\n",
+            table, name
+        ));
+        out.push_str(&format!("flush chain {} {}\n", table, name));
+        for handle in &chain.jumps {
+            out.push_str(&format!(
+                "delete rule {} {} handle {}\n",
+                table, chain.name, handle
+            ));
+        }
+        out.push_str(&format!("\ntable {} {{\n\tchain {} {{\n", table, name));
+        for rule in rules {
+            let excluded = match chain.family.as_str() {
+                "ip" => rule.is_ipv6(),
+                "ip6" => rule.source.is_some() && !rule.is_ipv6(),
+                _ => false,
+            };
+            if !excluded {
+                out.push_str(&format!("\t\t{}\n", nft_rule(rule)));
+            }
+        }
+        out.push_str("\t}\n}\n");
+        out.push_str(&format!(
+            "insert rule {} {} jump {}\n",
+            table, chain.name, name
+        ));
+    }
+    out
+}
+
+/// `iptables-restore --noflush` input filling the chain of `project`, `ipv6` for ip6tables.
+/// Rules restricted to sources of the other family are left out.
+pub fn iptables_restore(project: &str, rules: &[Rule], ipv6: bool) -> String {
+    let chain = iptables_chain(project);
+    let mut out = format!("*filter\n:{} - [0:0]\n", chain);
+    for rule in rules {
+        if rule.source.is_some() && rule.is_ipv6() != ipv6 {
+            continue;
+        }
+        out.push_str(&format!("-A {} -p {}", chain, rule.protocol));
+        if let Some(source) = &rule.source {
+            out.push_str(&format!(" -s {}", source));
+        }
+        out.push_str(&format!(" --dport {} -j ACCEPT\n", rule.port));
+    }
+    out.push_str("COMMIT\n");
+    out
+}
+
+/// How to open the ports of `project` with `backend`, writing rule files to `dir`. nftables rules
+/// go in the host's input chains when it has some, read with `runner`.
+pub fn apply(
+    backend: Backend,
+    project: &str,
+    rules: &[Rule],
+    dir: &Path,
+    runner: &dyn CommandRunner,
+) -> Plan {
+    match backend {
+        Backend::Nftables => {
+            let path = dir.join(format!("{}.nft", nft_table(project)));
+            let chains = host_chains(project, runner);
+            let ruleset = match chains.as_slice() {
+                [] => nft_ruleset(project, rules),
+                chains => nft_host_ruleset(project, rules, chains),
+            };
+            Plan {
+                steps: vec![step(&["nft", "-f", &path.to_string_lossy()], false)],
+                files: vec![(path, ruleset)],
+                private_files: vec![],
+            }
+        }
+        Backend::Iptables => {
+            let chain = iptables_chain(project);
+            let mut plan = Plan::default();
+            for (program, ipv6) in &[("iptables", false), ("ip6tables", true)] {
+                let path = dir.join(format!("{}.{}", chain, if *ipv6 { "v6" } else { "v4" }));
+                let restore = format!("{}-restore", program);
+                plan.steps.extend(vec![
+                    step(&[&restore, "--noflush", &path.to_string_lossy()], false),
+                    // jump to the chain once, however many times rules are applied
+                    step(&[program, "-D", "INPUT", "-j", &chain], true),
+                    step(&[program, "-I", "INPUT", "-j", &chain], false),
+                ]);
+                plan.files
+                    .push((path, iptables_restore(project, rules, *ipv6)));
+            }
+            plan
+        }
+        Backend::Ufw => Plan {
+            files: vec![],
+            private_files: vec![],
+            steps: rules
+                .iter()
+                .map(|rule| {
+                    let mut args = ufw_rule(rule);
+                    args.insert(0, "ufw".into());
+                    args.extend(vec!["comment".into(), format!("offsetup {}", project)]);
+                    Step {
+                        args,
+                        may_fail: false,
+                    }
+                })
+                .collect(),
+        },
+        Backend::Firewalld => Plan {
+            files: vec![],
+            private_files: vec![],
+            steps: firewalld(rules, "add"),
+        },
+    }
+}
+
+/// How to close the ports `apply` opened, reading the host's nftables ruleset with `runner`
+pub fn remove(backend: Backend, project: &str, rules: &[Rule], runner: &dyn CommandRunner) -> Plan {
+    let steps = match backend {
+        Backend::Nftables => {
+            let name = nft_table(project);
+            let mut steps = vec![];
+            for chain in host_chains(project, runner) {
+                let (family, table) = (chain.family.as_str(), chain.table.as_str());
+                for handle in &chain.jumps {
+                    let handle = handle.to_string();
+                    let args = ["nft", "delete", "rule", family, table, &chain.name];
+                    steps.push(step(&[&args[..], &["handle", &handle]].concat(), true));
+                }
+                steps.push(step(
+                    &["nft", "delete", "chain", family, table, &name],
+                    true,
+                ));
+            }
+            steps.push(step(&["nft", "delete", "table", "inet", &name], true));
+            steps
+        }
+        Backend::Iptables => {
+            let chain = iptables_chain(project);
+            ["iptables", "ip6tables"]
+                .iter()
+                .flat_map(|program| {
+                    vec![
+                        step(&[program, "-D", "INPUT", "-j", &chain], true),
+                        step(&[program, "-F", &chain], true),
+                        step(&[program, "-X", &chain], true),
+                    ]
+                })
+                .collect()
+        }
+        Backend::Ufw => rules
+            .iter()
+            .map(|rule| {
+                let mut args = ufw_rule(rule);
+                args.insert(0, "delete".into());
+                args.insert(0, "ufw".into());
+                Step {
+                    args,
+                    may_fail: true,
+                }
+            })
+            .collect(),
+        Backend::Firewalld => firewalld(rules, "remove"),
+    };
+    Plan {
+        files: vec![],
+        private_files: vec![],
+        steps,
+    }
+}
+
+/// `allow ...` arguments of ufw for `rule`
+fn ufw_rule(rule: &Rule) -> Vec<String> {
+    vec![
+        "allow".into(),
+        "proto".into(),
+        rule.protocol.to_string(),
+        "from".into(),
+        rule.source.clone().unwrap_or_else(|| "any".into()),
+        "to".into(),
+        "any".into(),
+        "port".into(),
+        rule.port.to_string(),
+    ]
+}
+
+/// Permanent firewalld rules, `action` being add or remove, then a reload to enforce them
+fn firewalld(rules: &[Rule], action: &str) -> Vec<Step> {
+    let mut steps: Vec<Step> = rules
+        .iter()
+        .map(|rule| {
+            let change = match &rule.source {
+                None => format!("--{}-port={}/{}", action, rule.port, rule.protocol),
+                Some(source) => format!(
+                    "--{}-rich-rule=rule family=\"{}\" source address=\"{}\" port port=\"{}\" protocol=\"{}\" accept",
+                    action,
+                    if rule.is_ipv6() { "ipv6" } else { "ipv4" },
+                    source,
+                    rule.port,
+                    rule.protocol
+                ),
+            };
+            step(&["firewall-cmd", "--permanent", &change], action == "remove")
+        })
+        .collect();
+    steps.push(step(&["firewall-cmd", "--reload"], false));
+    steps
+}
+
+/// `project` reduced to the characters rule names allow
+fn slug(project: &str, separator: char) -> String {
+    project
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c
+            } else {
+                separator
+            }
+        })
+        .collect()
+}
+
+fn nft_table(project: &str) -> String {
+    format!("offsetup_{}", slug(project, '_'))
+}
+
+/// iptables chain names are at most 28 characters long
+fn iptables_chain(project: &str) -> String {
+    format!("offsetup-{}", slug(project, '-'))
+        .chars()
+        .take(28)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{plan::run, runner::fake::FakeRunner};
+    use std::fs;
+
+    fn example_rules() -> Vec<Rule> {
+        rules(
+            &[(Protocol::Tcp, 80), (Protocol::Udp, 53)],
+            &["10.0.0.0/8".into(), "fd00::/8".into()],
+        )
+    }
+
+    fn snapshot(name: &str) -> String {
+        fs::read_to_string(Path::new("examples/firewall").join(name)).unwrap()
+    }
+
+    fn commands(plan: &Plan) -> String {
+        plan.steps
+            .iter()
+            .map(|step| format!("{}\n", step))
+            .collect()
+    }
+
+    #[test]
+    fn one_rule_per_port_and_source() {
+        assert_eq!(4, example_rules().len());
+        assert_eq!(
+            vec![Rule {
+                protocol: Protocol::Tcp,
+                port: 443,
+                source: None
+            }],
+            rules(&[(Protocol::Tcp, 443)], &[])
+        );
+    }
+
+    #[test]
+    fn renders_rules_for_every_backend() {
+        let rules = example_rules();
+        let dir = Path::new("/var/lib/offsetup");
+        assert_eq!(snapshot("ruleset.nft"), nft_ruleset("My Project", &rules));
+        assert_eq!(
+            snapshot("rules.v4"),
+            iptables_restore("My Project", &rules, false)
+        );
+        assert_eq!(
+            snapshot("rules.v6"),
+            iptables_restore("My Project", &rules, true)
+        );
+        for (backend, name) in &[
+            (Backend::Nftables, "nftables"),
+            (Backend::Iptables, "iptables"),
+            (Backend::Ufw, "ufw"),
+            (Backend::Firewalld, "firewalld"),
+        ] {
+            let text = format!(
+                "{}\n{}",
+                commands(&apply(
+                    *backend,
+                    "My Project",
+                    &rules,
+                    dir,
+                    &FakeRunner::new()
+                )),
+                commands(&remove(*backend, "My Project", &rules, &FakeRunner::new()))
+            );
+            assert_eq!(snapshot(&format!("{}.txt", name)), text, "{}", name);
+        }
+    }
+
+    #[test]
+    fn jumps_from_the_host_input_chains() {
+        let ruleset = r#"{"nftables": [
+            {"metainfo": {"version": "0.9.3", "json_schema_version": 1}},
+            {"table": {"family": "inet", "name": "filter", "handle": 1}},
+            {"chain": {"family": "inet", "table": "filter", "name": "input", "handle": 1,
+                "type": "filter", "hook": "input", "prio": 0, "policy": "drop"}},
+            {"chain": {"family": "inet", "table": "filter", "name": "output", "handle": 2,
+                "type": "filter", "hook": "output", "prio": 0, "policy": "accept"}},
+            {"chain": {"family": "inet", "table": "filter", "name": "offsetup_my_project",
+                "handle": 3}},
+            {"rule": {"family": "inet", "table": "filter", "chain": "input", "handle": 7,
+                "expr": [{"jump": {"target": "offsetup_my_project"}}]}},
+            {"rule": {"family": "inet", "table": "filter", "chain": "input", "handle": 8,
+                "expr": [{"accept": null}]}},
+            {"table": {"family": "ip", "name": "offsetup_other", "handle": 4}},
+            {"chain": {"family": "ip", "table": "offsetup_other", "name": "input", "handle": 1,
+                "type": "filter", "hook": "input", "prio": 0, "policy": "accept"}}
+        ]}"#;
+        let chains = nft_input_chains(ruleset, "My Project");
+        assert_eq!(
+            vec![NftChain {
+                family: "inet".into(),
+                table: "filter".into(),
+                name: "input".into(),
+                jumps: vec![7],
+            }],
+            chains
+        );
+        assert_eq!(
+            snapshot("ruleset-host.nft"),
+            nft_host_ruleset("My Project", &example_rules(), &chains)
+        );
+
+        let runner = FakeRunner::new().respond("nft -j list ruleset", ruleset);
+        let plan = apply(
+            Backend::Nftables,
+            "My Project",
+            &example_rules(),
+            Path::new("/var/lib/offsetup"),
+            &runner,
+        );
+        assert_eq!(snapshot("ruleset-host.nft"), plan.files[0].1);
+        assert_eq!(
+            "nft delete rule inet filter input handle 7\n\
+             nft delete chain inet filter offsetup_my_project\n\
+             nft delete table inet offsetup_my_project\n",
+            commands(&remove(Backend::Nftables, "My Project", &[], &runner))
+        );
+    }
+
+    #[test]
+    fn detects_running_firewall() {
+        assert_eq!(None, detect(&FakeRunner::new()));
+        let runner = FakeRunner::new()
+            .respond("ufw status", "Status: inactive\n")
+            .respond("nft --version", "nftables v0.9.3 (Topsy)\n")
+            .respond("iptables --version", "iptables v1.8.4 (nf_tables)\n");
+        assert_eq!(Some(Backend::Nftables), detect(&runner));
+        let runner = runner.respond("firewall-cmd --state", "running\n");
+        assert_eq!(Some(Backend::Firewalld), detect(&runner));
+    }
+
+    #[test]
+    fn stops_at_the_first_failing_step() {
+        let rules = rules(&[(Protocol::Tcp, 80)], &[]);
+        let runner = FakeRunner::new()
+            .fail(
+                "ufw delete allow proto tcp from any to any port 80",
+                "Could not delete non-existent rule",
+            )
+            .respond("firewall-cmd --permanent --add-port=80/tcp", "success")
+            .fail("firewall-cmd --reload", "FirewallD is not running");
+        assert!(run(&remove(Backend::Ufw, "a", &rules, &runner), &runner).is_ok());
+
+        let error = run(
+            &apply(Backend::Firewalld, "a", &rules, Path::new(""), &runner),
+            &runner,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.starts_with("`firewall-cmd --reload` failed"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            vec![
+                "ufw delete allow proto tcp from any to any port 80",
+                "firewall-cmd --permanent --add-port=80/tcp",
+                "firewall-cmd --reload",
+            ],
+            runner.calls()
+        );
+    }
+}
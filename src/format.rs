@@ -0,0 +1,266 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use config::{Config, ConfigError, File, FileFormat};
+use serde_json::{Map, Number, Value};
+use yaml_rust::{yaml::Hash, Yaml, YamlEmitter, YamlLoader};
+
+/// Extensions of the supported formats, in the order files are looked for
+pub const EXTENSIONS: &[&str] = &["yml", "yaml", "toml", "json", "json5"];
+
+/// A configuration file format, chosen by the file's extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Yaml,
+    Toml,
+    Json,
+    Json5,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Format, ConfigError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        match extension.to_lowercase().as_str() {
+            "yml" | "yaml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "json" => Ok(Format::Json),
+            "json5" => Ok(Format::Json5),
+            _ => Err(ConfigError::Message(format!(
+                "cannot tell the format of {:?}, use one of the .yml, .yaml, .toml, .json or .json5 extensions",
+                path
+            ))),
+        }
+    }
+
+    /// Whether the text of files in this format can be searched for the line of a key
+    pub fn is_yaml_compatible(self) -> bool {
+        self == Format::Yaml || self == Format::Json
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+            Format::Json => "JSON",
+            Format::Json5 => "JSON5",
+        })
+    }
+}
+
+/// `<dir>/<stem>.<extension>` for the first of `EXTENSIONS` that exists
+pub fn find(dir: &Path, stem: &str) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{}.{}", stem, extension)))
+        .find(|path| path.is_file())
+}
+
+/// The config file in `dir`: the first of `offsetup.{yml,yaml,toml,json,json5}` that exists
+pub fn discover(dir: &Path) -> Result<PathBuf, ConfigError> {
+    find(dir, "offsetup").ok_or_else(|| {
+        ConfigError::Message(format!(
+            "no configuration file found in {:?}, looked for offsetup.{{{}}}",
+            dir,
+            EXTENSIONS.join(",")
+        ))
+    })
+}
+
+/// Merge the file at `path` into `config`, in the format its extension names
+pub fn merge(config: &mut Config, path: &Path) -> Result<(), ConfigError> {
+    let name = path.to_string_lossy();
+    match Format::from_path(path)? {
+        Format::Yaml => config.merge(File::new(&name, FileFormat::Yaml))?,
+        Format::Toml => config.merge(File::new(&name, FileFormat::Toml))?,
+        Format::Json => config.merge(File::new(&name, FileFormat::Json))?,
+        Format::Json5 => {
+            // the loader has no JSON5 parser, so it reads the equivalent JSON
+            let json = serde_json::to_string(&parse(path)?).expect("JSON values serialize");
+            config.merge(File::from_str(&json, FileFormat::Json))?
+        }
+    };
+    Ok(())
+}
+
+/// Read the file at `path` as it is written, without the loader lowercasing its keys
+pub fn parse(path: &Path) -> Result<Value, ConfigError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| ConfigError::Message(format!("cannot read {:?}: {}", path, e)))?;
+    let invalid = |e: &dyn fmt::Display| ConfigError::Message(format!("{:?}: {}", path, e));
+    match Format::from_path(path)? {
+        Format::Yaml => {
+            let documents = YamlLoader::load_from_str(&content).map_err(|e| invalid(&e))?;
+            documents
+                .first()
+                .map_or(Ok(Value::Null), from_yaml)
+                .map_err(|e| invalid(&e))
+        }
+        Format::Toml => toml::from_str(&content).map_err(|e| invalid(&e)),
+        Format::Json => serde_json::from_str(&content).map_err(|e| invalid(&e)),
+        Format::Json5 => json5::from_str(&content).map_err(|e| invalid(&e)),
+    }
+}
+
+/// Write `value` in `format`. TOML has no null, so null values are left out of it.
+pub fn render(value: &Value, format: Format) -> Result<String, ConfigError> {
+    let message = |e: &dyn fmt::Display| ConfigError::Message(format!("{}: {}", format, e));
+    match format {
+        Format::Yaml => {
+            let mut out = String::new();
+            YamlEmitter::new(&mut out)
+                .dump(&to_yaml(value))
+                .map_err(|e| message(&format!("{:?}", e)))?;
+            let out = out.trim_start_matches("---").trim_start_matches('\n');
+            Ok(format!("{}\n", out))
+        }
+        Format::Toml => {
+            let value = toml::Value::try_from(without_nulls(value)).map_err(|e| message(&e))?;
+            toml::to_string(&value).map_err(|e| message(&e))
+        }
+        Format::Json | Format::Json5 => serde_json::to_string_pretty(value)
+            .map(|json| format!("{}\n", json))
+            .map_err(|e| message(&e)),
+    }
+}
+
+fn from_yaml(yaml: &Yaml) -> Result<Value, String> {
+    Ok(match yaml {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(b) => Value::Bool(*b),
+        Yaml::Integer(i) => Value::from(*i),
+        Yaml::Real(real) => real
+            .parse()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("{} is not a number", real))?,
+        Yaml::String(s) => Value::String(s.clone()),
+        Yaml::Array(items) => Value::Array(items.iter().map(from_yaml).collect::<Result<_, _>>()?),
+        Yaml::Hash(hash) => {
+            let mut table = Map::new();
+            for (key, value) in hash {
+                let key = match key {
+                    Yaml::String(s) | Yaml::Real(s) => s.clone(),
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    key => return Err(format!("unsupported key {:?}", key)),
+                };
+                table.insert(key, from_yaml(value)?);
+            }
+            Value::Object(table)
+        }
+        Yaml::Alias(_) | Yaml::BadValue => return Err(format!("unsupported value {:?}", yaml)),
+    })
+}
+
+fn to_yaml(value: &Value) -> Yaml {
+    match value {
+        Value::Null => Yaml::Null,
+        Value::Bool(b) => Yaml::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        Value::String(s) => Yaml::String(s.clone()),
+        Value::Array(items) => Yaml::Array(items.iter().map(to_yaml).collect()),
+        Value::Object(table) => {
+            let mut hash = Hash::new();
+            for (key, value) in table {
+                hash.insert(Yaml::String(key.clone()), to_yaml(value));
+            }
+            Yaml::Hash(hash)
+        }
+    }
+}
+
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(without_nulls).collect()),
+        Value::Object(table) => Value::Object(
+            table
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), without_nulls(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn chooses_format_by_extension() {
+        assert_eq!(
+            Format::Yaml,
+            Format::from_path(Path::new("offsetup.yml")).unwrap()
+        );
+        assert_eq!(
+            Format::Toml,
+            Format::from_path(Path::new("a/offsetup.TOML")).unwrap()
+        );
+        assert_eq!(
+            Format::Json5,
+            Format::from_path(Path::new("offsetup.json5")).unwrap()
+        );
+        assert!(Format::from_path(Path::new("offsetup")).is_err());
+        assert!(Format::from_path(Path::new("offsetup.ini")).is_err());
+    }
+
+    #[test]
+    fn discovers_default_files_in_order() {
+        let dir = env::temp_dir().join("offsetup-discover-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        assert!(discover(&dir).is_err());
+
+        fs::write(dir.join("offsetup.json"), "{}").unwrap();
+        assert_eq!(dir.join("offsetup.json"), discover(&dir).unwrap());
+        fs::write(dir.join("offsetup.toml"), "").unwrap();
+        assert_eq!(dir.join("offsetup.toml"), discover(&dir).unwrap());
+    }
+
+    #[test]
+    fn can_convert_between_formats() {
+        let dir = env::temp_dir().join("offsetup-convert-test");
+        fs::create_dir_all(&dir).unwrap();
+        let original = parse(Path::new("examples/redis.yml")).unwrap();
+        let mut loaded = Config::default();
+        merge(&mut loaded, Path::new("examples/redis.yml")).unwrap();
+        let loaded: Value = loaded.try_into().unwrap();
+
+        for (name, format) in &[
+            ("redis.yml", Format::Yaml),
+            ("redis.toml", Format::Toml),
+            ("redis.json", Format::Json),
+            ("redis.json5", Format::Json5),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, render(&original, *format).unwrap()).unwrap();
+            assert_eq!(original, parse(&path).unwrap(), "{}", name);
+
+            let mut config = Config::default();
+            merge(&mut config, &path).unwrap();
+            assert_eq!(loaded, config.try_into::<Value>().unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn toml_leaves_nulls_out() {
+        let value = serde_json::json!({ "name": "a", "exposes": null, "list": [1, 2] });
+        assert_eq!(
+            "name = \"a\"\nlist = [1, 2]\n",
+            render(&value, Format::Toml).unwrap()
+        );
+    }
+}
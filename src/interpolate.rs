@@ -0,0 +1,405 @@
+use std::{cell::RefCell, cmp::Reverse, collections::BTreeSet, fmt, fs};
+
+use config::{Config, ConfigError, File, FileFormat};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Key of a single-key table replaced by the content of the file it names, eg:
+/// `password: { secret_file: /run/secrets/db_password }`
+pub const SECRET_FILE: &str = "secret_file";
+
+/// A sensitive value, eg: a password. Redacted from debug output, so from dry-run plans too.
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    #[allow(dead_code)]
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(\"********\")")
+    }
+}
+
+impl JsonSchema for Secret {
+    fn schema_name() -> String {
+        "Secret".into()
+    }
+
+    /// A string, or a table naming the file to read it from, as `interpolate` resolves
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        serde_json::from_value(json!({
+            "description": "A sensitive value, given inline or read from a file",
+            "anyOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "properties": { SECRET_FILE: { "type": "string" } },
+                    "required": [SECRET_FILE],
+                    "additionalProperties": false
+                }
+            ]
+        }))
+        .expect("valid schema")
+    }
+}
+
+/// Words of keys and variable names holding secrets, eg: `password` or `API_TOKEN`
+const SECRET_WORDS: &[&str] = &["password", "passwd", "secret", "token"];
+
+/// Values of secret files, of required `${VAR:?}` variables and of variables given to keys named
+/// as secrets, wherever they landed
+#[derive(Clone, Default, PartialEq)]
+pub struct Secrets(BTreeSet<String>);
+
+impl Secrets {
+    /// Mask every secret value in `s`, eg: in a dry-run plan
+    pub fn redact(&self, s: &str) -> String {
+        let mut values: Vec<&String> = self.0.iter().collect();
+        // longest first, so that a secret containing another one is masked whole
+        values.sort_by_key(|value| Reverse(value.len()));
+        values.into_iter().fold(s.to_string(), |s, value| {
+            s.replace(value.as_str(), "********")
+        })
+    }
+
+    /// Whether `s` is a secret value
+    pub fn reveals(&self, s: &str) -> bool {
+        self.0.contains(s)
+    }
+
+    fn insert(&mut self, value: &str) {
+        if !value.is_empty() {
+            self.0.insert(value.into());
+        }
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Secrets({})", self.0.len())
+    }
+}
+
+/// Expand environment variables in every string of `config`, and replace `secret_file` tables by
+/// the file's content. Every unset required variable and unreadable file is reported, by key path.
+/// Returns the values substituted, so that they can be redacted wherever they are printed.
+pub fn interpolate(
+    config: Config,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<(Config, Secrets), ConfigError> {
+    let mut tree: Value = config.try_into()?;
+    let mut errors = vec![];
+    let mut secrets = Secrets::default();
+    interpolate_value(&mut tree, &mut vec![], lookup, &mut secrets, &mut errors);
+    if !errors.is_empty() {
+        return Err(ConfigError::Message(errors.join("\n")));
+    }
+    // read back as a file, as setting keys would parse them as paths and reject eg: `$ref`
+    let json = serde_json::to_string(&tree).expect("JSON values serialize");
+    let mut config = Config::default();
+    config.merge(File::from_str(&json, FileFormat::Json))?;
+    Ok((config, secrets))
+}
+
+fn interpolate_value(
+    value: &mut Value,
+    path: &mut Vec<String>,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    secrets: &mut Secrets,
+    errors: &mut Vec<String>,
+) {
+    if let Some(file) = secret_file(value) {
+        match fs::read_to_string(&file) {
+            Ok(content) => {
+                let content = content.trim_end_matches(['\r', '\n']);
+                secrets.insert(content);
+                *value = Value::String(content.into())
+            }
+            Err(e) => errors.push(format!(
+                "{}: cannot read {} {:?}: {}",
+                path.join("."),
+                SECRET_FILE,
+                file,
+                e
+            )),
+        }
+        return;
+    }
+    match value {
+        Value::String(s) => {
+            // the key, or the variable a `KEY=value` entry of a list sets
+            let key = path
+                .iter()
+                .rev()
+                .find(|key| key.parse::<usize>().is_err())
+                .map(String::as_str);
+            let assigned = s.split_once('=').map(|(name, _)| name);
+            let secret_key = key.into_iter().chain(assigned).any(is_secret);
+            let substituted = RefCell::new(vec![]);
+            let expanded = expand(s, lookup, &|value, required| {
+                if required || secret_key {
+                    substituted.borrow_mut().push(value.to_string());
+                }
+            });
+            substituted
+                .into_inner()
+                .iter()
+                .for_each(|value| secrets.insert(value));
+            match expanded {
+                Ok(expanded) => *s = expanded,
+                Err(e) => errors.push(format!("{}: {}", path.join("."), e)),
+            }
+        }
+        Value::Array(items) => {
+            for (idx, item) in items.iter_mut().enumerate() {
+                path.push(idx.to_string());
+                interpolate_value(item, path, lookup, secrets, errors);
+                path.pop();
+            }
+        }
+        Value::Object(table) => {
+            for (key, item) in table.iter_mut() {
+                path.push(key.clone());
+                interpolate_value(item, path, lookup, secrets, errors);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn secret_file(value: &Value) -> Option<String> {
+    let table = value.as_object()?;
+    if table.len() != 1 {
+        return None;
+    }
+    table.get(SECRET_FILE)?.as_str().map(String::from)
+}
+
+/// Whether a key or a variable names a secret, eg: db_password or API_TOKEN
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_WORDS.iter().any(|word| name.contains(word))
+}
+
+/// Expand `${VAR}`, `${VAR:-default}` and `${VAR:?error}` in `s`; `$$` is a literal `$`.
+/// Variables without a default are required: referencing an unset one is an error. A bare `$VAR` is
+/// left as is, for the shell of the commands the config holds to expand. `record` is passed each
+/// value read from the environment, and whether its variable was required with `${VAR:?}`.
+pub fn expand(
+    s: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    record: &dyn Fn(&str, bool),
+) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(idx) = rest.find('$') {
+        result.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format!("unterminated `${{` in {:?}", s))?;
+            result.push_str(&expand_braced(&braced[..end], lookup, record)?);
+            rest = &braced[end + 1..];
+        } else {
+            result.push('$');
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn expand_braced(
+    expression: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    record: &dyn Fn(&str, bool),
+) -> Result<String, String> {
+    if let Some(idx) = expression.find(":-") {
+        let name = &expression[..idx];
+        return Ok(match lookup(name).filter(|value| !value.is_empty()) {
+            Some(value) => {
+                record(&value, false);
+                value
+            }
+            None => expression[idx + 2..].into(),
+        });
+    }
+    if let Some(idx) = expression.find(":?") {
+        let name = &expression[..idx];
+        let message = &expression[idx + 2..];
+        let value =
+            lookup(name)
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| match message {
+                    "" => unset(name),
+                    message => format!("{} ({})", unset(name), message),
+                })?;
+        record(&value, true);
+        return Ok(value);
+    }
+    let value = lookup(expression).ok_or_else(|| unset(expression))?;
+    record(&value, false);
+    Ok(value)
+}
+
+fn unset(name: &str) -> String {
+    format!("environment variable `{}` is not set", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, env};
+
+    fn expand(s: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+        super::expand(s, lookup, &|_, _| {})
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        let vars: HashMap<&str, &str> = [("USER", "offscale"), ("EMPTY", ""), ("PORT", "6379")]
+            .iter()
+            .cloned()
+            .collect();
+        vars.get(name).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn can_expand_variables() {
+        assert_eq!(Ok("offscale".into()), expand("${USER}", &lookup));
+        assert_eq!(
+            Ok("postgres://offscale@db:6379".into()),
+            expand("postgres://${USER}@db:${PORT}", &lookup)
+        );
+        assert_eq!(Ok("db".into()), expand("${HOST:-db}", &lookup));
+        assert_eq!(Ok("db".into()), expand("${EMPTY:-db}", &lookup));
+        assert_eq!(Ok("offscale".into()), expand("${USER:?}", &lookup));
+        assert_eq!(Ok("cost: $5 $".into()), expand("cost: $$5 $", &lookup));
+        assert_eq!(Ok("${USER}".into()), expand("$${USER}", &lookup));
+    }
+
+    #[test]
+    fn leaves_shell_variables_alone() {
+        assert_eq!(
+            Ok("echo $HOME_DIR".into()),
+            expand("echo $HOME_DIR", &lookup)
+        );
+        assert_eq!(
+            Ok("PATH=/opt/bin:$PATH".into()),
+            expand("PATH=/opt/bin:$PATH", &lookup)
+        );
+    }
+
+    #[test]
+    fn unset_required_variables_are_errors() {
+        assert_eq!(
+            Err("environment variable `PASSWORD` is not set".into()),
+            expand("${PASSWORD}", &lookup)
+        );
+        assert_eq!(
+            Err("environment variable `EMPTY` is not set (set it in .env)".into()),
+            expand("${EMPTY:?set it in .env}", &lookup)
+        );
+        assert!(expand("${USER", &lookup).is_err());
+    }
+
+    #[test]
+    fn can_interpolate_config() {
+        let secret = env::temp_dir().join("offsetup-secret-test");
+        fs::write(&secret, "hunter2\n").unwrap();
+        let yaml = format!(
+            "name: ${{USER}}\n$ref: '#/a'\nversion: '1'\nports: [1, 2]\npassword:\n  secret_file: {}\nnested:\n  - url: ${{URL:-http://localhost}}\n",
+            secret.display()
+        );
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(&yaml, FileFormat::Yaml))
+            .unwrap();
+
+        let (config, secrets) = interpolate(config, &lookup).unwrap();
+        assert_eq!("offscale", config.get_str("name").unwrap());
+        let tree: Value = config.clone().try_into().unwrap();
+        assert_eq!("#/a", tree["$ref"], "keys are not parsed as paths");
+        assert_eq!("hunter2", config.get_str("password").unwrap());
+        assert_eq!("http://localhost", config.get_str("nested[0].url").unwrap());
+        assert_eq!(vec![1, 2], config.get::<Vec<u16>>("ports").unwrap());
+        assert_eq!(
+            "user offscale with ********, at http://localhost",
+            secrets.redact("user offscale with hunter2, at http://localhost"),
+            "values of secret files are secret, plain variables and defaults are not"
+        );
+    }
+
+    #[test]
+    fn records_secret_variables() {
+        let yaml = "name: ${USER}\nport: ${PORT}\ndb_password: ${USER}\nowner: ${USER:?}\nenv:\n  - MODE=${PORT}\n  - API_TOKEN=${PORT}\n";
+        let secrets = |yaml: &str| {
+            let mut config = Config::default();
+            config
+                .merge(File::from_str(yaml, FileFormat::Yaml))
+                .unwrap();
+            interpolate(config, &lookup).unwrap().1
+        };
+        let all = secrets(yaml);
+        assert!(all.reveals("offscale"), "required and password values");
+        assert!(all.reveals("6379"), "values of token variables");
+        assert!(!all.reveals("offscale:6379"), "whole values are compared");
+
+        let plain = secrets("name: ${USER}\nport: ${PORT}\nenv:\n  - MODE=${PORT}\n");
+        assert_eq!(Secrets::default(), plain);
+        assert_eq!("/home/offscale:6379", plain.redact("/home/offscale:6379"));
+    }
+
+    #[test]
+    fn can_interpolate_example_files() {
+        let mut config = Config::default();
+        config.merge(File::with_name("examples/redis.yml")).unwrap();
+        let (config, _) = interpolate(config, &lookup).unwrap();
+        let tree: Value = config.clone().try_into().unwrap();
+        assert_eq!(
+            "#/x-shared/source/install",
+            tree["dependencies"]["platforms"]["ubuntu"]["source"]["install"]["$ref"]
+        );
+        if let Err(e) = config.try_into::<crate::OffSetup>() {
+            panic!("redis.yml should load once interpolated: {}", e)
+        }
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secret = Secret("hunter2".into());
+        assert_eq!("hunter2", secret.expose());
+        assert!(!format!("{:?}", secret).contains("hunter2"));
+
+        let mut secrets = Secrets::default();
+        secrets.insert("hunter");
+        secrets.insert("hunter2");
+        secrets.insert("");
+        assert_eq!("-p ******** ", secrets.redact("-p hunter2 "));
+        assert!(!format!("{:?}", secrets).contains("hunter"));
+    }
+
+    #[test]
+    fn errors_name_the_key_path() {
+        let yaml = "a:\n  b:\n    - ok\n    - ${MISSING}\nc:\n  secret_file: /nonexistent/secret\n";
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(yaml, FileFormat::Yaml))
+            .unwrap();
+
+        let message = interpolate(config, &lookup).unwrap_err().to_string();
+        assert!(message.contains("a.b.1: environment variable `MISSING` is not set"));
+        assert!(message.contains("c: cannot read secret_file \"/nonexistent/secret\""));
+    }
+}
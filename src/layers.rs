@@ -0,0 +1,502 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use config::{Config, ConfigError, File, FileFormat};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{download, format, runner::CommandRunner, schema, shared, Include};
+
+/// Stem of the optional file, next to the config file, overriding it on this machine only
+pub const LOCAL_STEM: &str = "offsetup.local";
+
+/// Prefix of the environment variables overriding configuration values
+pub const ENV_PREFIX: &str = "OFFSETUP_";
+/// Separates the keys of nested tables in environment variable names
+pub const ENV_SEPARATOR: &str = "__";
+/// Variables with the prefix that configure offsetup itself rather than the project: the `--debug`
+/// and `--verbose` flags, and the state directory of `shared::state_dir`
+const ENV_RESERVED: &[&str] = &["OFFSETUP_DEBUG", "OFFSETUP_VERBOSITY", "OFFSETUP_STATE_DIR"];
+/// Origin of the values set by environment variables
+const ENV_ORIGIN: &str = "environment";
+
+/// One source of configuration values, merged over the layers before it
+pub struct Layer {
+    /// The file the values were read from, or `environment`
+    pub origin: String,
+    pub config: Config,
+}
+
+impl Layer {
+    fn file(path: &Path) -> Result<Layer, ConfigError> {
+        let mut config = Config::new();
+        format::merge(&mut config, path)?;
+        Ok(Layer {
+            origin: path.to_string_lossy().into(),
+            config,
+        })
+    }
+}
+
+/// The layers of the configuration at `path`, lowest precedence first:
+/// 1. the files it `include`s, each one after the files it includes itself
+/// 2. the config file
+/// 3. `config/<profile>` next to it. Without `profile`, `$RUN_MODE` or else `development`, if found
+/// 4. `offsetup.local` next to it, for overrides on this machine that are not committed
+/// 5. the `OFFSETUP_` environment variables, see `environment`
+pub fn layers(
+    path: &Path,
+    profile: Option<&str>,
+    runner: &dyn CommandRunner,
+) -> Result<Vec<Layer>, ConfigError> {
+    let mut layers = vec![];
+    with_includes(path, runner, &mut vec![], &mut layers)?;
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let profiles = dir.join("config");
+    match profile {
+        Some(profile) => {
+            let file = format::find(&profiles, profile).ok_or_else(|| {
+                ConfigError::Message(format!(
+                    "profile `{}` not found, looked for {:?}",
+                    profile,
+                    profiles.join(format!("{}.{{{}}}", profile, format::EXTENSIONS.join(",")))
+                ))
+            })?;
+            layers.push(Layer::file(&file)?);
+        }
+        None => {
+            let run_mode = env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
+            if let Some(file) = format::find(&profiles, &run_mode) {
+                layers.push(Layer::file(&file)?);
+            }
+        }
+    }
+
+    if let Some(file) = format::find(dir, LOCAL_STEM) {
+        layers.push(Layer::file(&file)?);
+    }
+
+    layers.push(Layer {
+        origin: ENV_ORIGIN.into(),
+        config: environment(env::vars())?,
+    });
+    Ok(layers)
+}
+
+/// Push the layers of the files `path` includes, then the one of `path`
+fn with_includes(
+    path: &Path,
+    runner: &dyn CommandRunner,
+    including: &mut Vec<PathBuf>,
+    layers: &mut Vec<Layer>,
+) -> Result<(), ConfigError> {
+    let canonical = fs::canonicalize(path)
+        .map_err(|e| ConfigError::Message(format!("cannot read {:?}: {}", path, e)))?;
+    if including.contains(&canonical) {
+        return Err(ConfigError::Message(format!(
+            "{:?} includes itself through {:?}",
+            path, including
+        )));
+    }
+
+    let includes = match format::parse(path)?.get("include") {
+        Some(includes) => Vec::<Include>::deserialize(includes)
+            .map_err(|e| ConfigError::Message(format!("{:?}: include: {}", path, e)))?,
+        None => vec![],
+    };
+    including.push(canonical);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for include in &includes {
+        let file = match include {
+            Include::Path(file) => dir.join(file),
+            Include::Download(download) => fetch(download, runner)?,
+        };
+        with_includes(&file, runner, including, layers)?;
+    }
+    including.pop();
+
+    layers.push(Layer::file(path)?);
+    Ok(())
+}
+
+/// The file at a pinned URI, downloaded once into the state directory
+fn fetch(download: &crate::Download, runner: &dyn CommandRunner) -> Result<PathBuf, ConfigError> {
+    let dir = shared::state_dir().join("includes");
+    let path = download.destination(&dir);
+    let cached = download::sha512_file(&path)
+        .map(|checksum| checksum.eq_ignore_ascii_case(&download.sha512))
+        .unwrap_or(false);
+    if cached {
+        return Ok(path);
+    }
+    download::fetch(download, &dir, runner).map_err(|e| {
+        ConfigError::Message(format!("cannot include {}: {}", download.uri.unparse(), e))
+    })
+}
+
+/// The configuration values set by `OFFSETUP_` variables among `vars`. `__` separates nested
+/// keys, eg: `OFFSETUP_DEPENDENCIES__PLATFORMS__UBUNTU__SKIP_INSTALL=true`. Variables naming a key
+/// `schema::overridable_keys` does not list are errors.
+pub fn environment(vars: impl Iterator<Item = (String, String)>) -> Result<Config, ConfigError> {
+    let keys = schema::overridable_keys();
+    let mut tree = Map::new();
+    let mut errors = vec![];
+    for (name, value) in vars {
+        if !name.starts_with(ENV_PREFIX) || ENV_RESERVED.contains(&name.as_str()) {
+            continue;
+        }
+        let key = name[ENV_PREFIX.len()..].to_lowercase();
+        let mut segments: Vec<&str> = key.split(ENV_SEPARATOR).collect();
+        let kind = match keys.iter().find(|(pattern, _)| matches(pattern, &segments)) {
+            Some((_, kind)) => kind,
+            None => {
+                errors.push(format!(
+                    "{}: {} sets unknown key `{}`, see `offsetup config env`",
+                    ENV_ORIGIN,
+                    name,
+                    segments.join(".")
+                ));
+                continue;
+            }
+        };
+        let last = segments.pop().unwrap_or_default();
+        let mut table = &mut tree;
+        for segment in segments {
+            table = match table
+                .entry(segment)
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                Value::Object(table) => table,
+                _ => {
+                    return Err(ConfigError::Message(format!(
+                        "{}: {} sets a key below a value set by another variable",
+                        ENV_ORIGIN, name
+                    )))
+                }
+            };
+        }
+        table.insert(last.into(), coerce(&value, kind == "list"));
+    }
+    if !errors.is_empty() {
+        return Err(ConfigError::Message(errors.join("\n")));
+    }
+
+    // read as a file, as setting keys would parse them as paths
+    let json = serde_json::to_string(&tree).expect("JSON values serialize");
+    let mut config = Config::new();
+    config.merge(File::from_str(&json, FileFormat::Json))?;
+    Ok(config)
+}
+
+/// Whether `key`, split in segments, is the overridable key `pattern`, eg:
+/// `dependencies.platforms.<name>.skip_install`
+fn matches(pattern: &str, key: &[&str]) -> bool {
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    pattern.len() == key.len()
+        && pattern
+            .iter()
+            .zip(key)
+            .all(|(expected, segment)| *expected == "<name>" || expected == segment)
+}
+
+/// `true` and `false` as booleans and integers as numbers. The value of a `list` key is a list of
+/// those, separated by commas, eg: `80,443`.
+fn coerce(value: &str, list: bool) -> Value {
+    let scalar = |value: &str| match value {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        value => value
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::String(value.into())),
+    };
+    if list {
+        Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(scalar)
+                .collect(),
+        )
+    } else {
+        scalar(value)
+    }
+}
+
+/// The environment variable overriding the value at `key`, eg: `exposes.ports.tcp`
+pub fn env_var(key: &str) -> String {
+    format!(
+        "{}{}",
+        ENV_PREFIX,
+        key.split('.')
+            .map(str::to_uppercase)
+            .collect::<Vec<String>>()
+            .join(ENV_SEPARATOR)
+    )
+}
+
+/// Every layer merged over the ones before it
+pub fn merge(layers: &[Layer]) -> Result<Config, ConfigError> {
+    let mut config = Config::new();
+    for layer in layers {
+        config.merge(layer.config.clone())?;
+    }
+    Ok(config)
+}
+
+/// Every value of the merged configuration, by key path, with the origin of the layer it came
+/// from. Sorted by key path; lists are values of their own, as a layer replaces them whole.
+pub fn origins(layers: &[Layer]) -> Result<Vec<(String, Value, String)>, ConfigError> {
+    let mut values: Vec<(String, Value, String)> = vec![];
+    for layer in layers {
+        let mut leaves = vec![];
+        flatten(&layer.config.clone().try_into()?, "", &mut leaves);
+        for (key, value) in leaves {
+            let nested = format!("{}.", key);
+            values.retain(|(other, ..)| *other != key && !other.starts_with(&nested));
+            values.retain(|(other, ..)| !key.starts_with(&format!("{}.", other)));
+            values.push((key, value, layer.origin.clone()));
+        }
+    }
+    values.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(values)
+}
+
+fn flatten(value: &Value, prefix: &str, leaves: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(table) if !table.is_empty() => {
+            for (key, value) in table {
+                let path = match prefix {
+                    "" => key.clone(),
+                    prefix => format!("{}.{}", prefix, key),
+                };
+                flatten(value, &path, leaves);
+            }
+        }
+        Value::Object(_) if prefix.is_empty() => {}
+        value => leaves.push((prefix.into(), value.clone())),
+    }
+}
+
+/// `value` with the keys of every table sorted, for stable output
+pub fn sorted(value: &Value) -> Value {
+    match value {
+        Value::Object(table) => {
+            let mut entries: Vec<(&String, &Value)> = table.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), sorted(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+        value => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::fake::FakeRunner;
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn project(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        write(
+            &dir,
+            "common/base.yml",
+            "version: '0'\nexposes:\n  ports:\n    tcp: [80]\n",
+        );
+        write(&dir, "common/ports.toml", "[exposes.ports]\nudp = [53]\n");
+        write(
+            &dir,
+            "offsetup.yml",
+            "include:\n  - common/base.yml\nname: app\nversion: '1'\n",
+        );
+        dir
+    }
+
+    #[test]
+    fn merges_layers_in_order() {
+        let dir = project("offsetup-layers-test");
+        write(
+            &dir,
+            "common/base.yml",
+            "include: [ports.toml]\nversion: '0'\nexposes:\n  ports:\n    tcp: [80]\n",
+        );
+        write(&dir, "config/production.json", r#"{"version": "2"}"#);
+        write(
+            &dir,
+            "offsetup.local.yml",
+            "exposes:\n  ports:\n    tcp: [8080]\n",
+        );
+
+        let path = dir.join("offsetup.yml");
+        let layers = layers(&path, Some("production"), &FakeRunner::new()).unwrap();
+        let names: Vec<String> = layers.iter().map(|layer| layer.origin.clone()).collect();
+        assert_eq!(
+            vec![
+                dir.join("common/ports.toml").to_string_lossy().to_string(),
+                dir.join("common/base.yml").to_string_lossy().to_string(),
+                path.to_string_lossy().to_string(),
+                dir.join("config/production.json")
+                    .to_string_lossy()
+                    .to_string(),
+                dir.join("offsetup.local.yml").to_string_lossy().to_string(),
+                "environment".into(),
+            ],
+            names
+        );
+
+        let config = merge(&layers).unwrap();
+        assert_eq!("app", config.get_str("name").unwrap());
+        assert_eq!("2", config.get_str("version").unwrap());
+        assert_eq!(
+            vec![8080],
+            config.get::<Vec<u16>>("exposes.ports.tcp").unwrap()
+        );
+        assert_eq!(
+            vec![53],
+            config.get::<Vec<u16>>("exposes.ports.udp").unwrap()
+        );
+
+        let values = origins(&layers).unwrap();
+        let origin = |key: &str| {
+            values
+                .iter()
+                .find(|(other, ..)| other == key)
+                .map(|(_, _, origin)| origin.clone())
+                .unwrap()
+        };
+        assert_eq!(
+            dir.join("offsetup.local.yml").to_string_lossy(),
+            origin("exposes.ports.tcp")
+        );
+        assert_eq!(
+            dir.join("common/ports.toml").to_string_lossy(),
+            origin("exposes.ports.udp")
+        );
+        assert_eq!(
+            dir.join("config/production.json").to_string_lossy(),
+            origin("version")
+        );
+    }
+
+    #[test]
+    fn can_override_nested_keys_from_the_environment() {
+        let vars = vec![
+            (
+                "OFFSETUP_DEPENDENCIES__PLATFORMS__UBUNTU__SKIP_INSTALL",
+                "true",
+            ),
+            (
+                "OFFSETUP_DEPENDENCIES__PLATFORMS__UBUNTU__VERSIONS",
+                "18.04,",
+            ),
+            ("OFFSETUP_EXPOSES__PORTS__TCP", "80, 443"),
+            ("OFFSETUP_DRY_RUN", "false"),
+            ("OFFSETUP_VERSION", "2"),
+            ("OFFSETUP_START__COMMAND", "echo a,b"),
+            ("OFFSETUP_START__ENV", "A=1"),
+            ("OFFSETUP_STATE_DIR", "/var/lib/offsetup"),
+            ("OFFSETUP_VERBOSITY", "2"),
+            ("OFFSETUP_DEBUG", "true"),
+            ("PATH", "/usr/bin"),
+        ];
+        let config = environment(
+            vars.into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        )
+        .unwrap();
+        let tree: Value = config.clone().try_into().unwrap();
+
+        assert_eq!(
+            serde_json::json!({
+                "dependencies": { "platforms": { "ubuntu": {
+                    "skip_install": true,
+                    "versions": ["18.04"],
+                } } },
+                "exposes": { "ports": { "tcp": [80, 443] } },
+                "start": { "command": "echo a,b", "env": ["A=1"] },
+                "dry_run": false,
+                "version": 2,
+            }),
+            tree
+        );
+        assert_eq!("2", config.get_str("version").unwrap());
+    }
+
+    #[test]
+    fn rejects_unknown_keys_from_the_environment() {
+        let vars = vec![
+            ("OFFSETUP_VERBOSE", "2"),
+            ("OFFSETUP_DEPENDENCIES__PLATFORMS__UBUNTU__VERSION", "18.04"),
+        ];
+        let message = match environment(
+            vars.into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        ) {
+            Ok(config) => panic!("unknown keys should be rejected: {:?}", config),
+            Err(e) => e.to_string(),
+        };
+        assert!(
+            message.contains("environment: OFFSETUP_VERBOSE sets unknown key `verbose`"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("unknown key `dependencies.platforms.ubuntu.version`"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn names_env_vars_after_keys() {
+        assert_eq!(
+            "OFFSETUP_DEPENDENCIES__PLATFORMS__UBUNTU__SKIP_INSTALL",
+            env_var("dependencies.platforms.ubuntu.skip_install")
+        );
+    }
+
+    #[test]
+    fn profiles_must_exist_when_selected() {
+        let dir = project("offsetup-profiles-test");
+        let path = dir.join("offsetup.yml");
+
+        assert_eq!(3, layers(&path, None, &FakeRunner::new()).unwrap().len());
+        let message = match layers(&path, Some("staging"), &FakeRunner::new()) {
+            Ok(_) => panic!("a missing profile should be an error"),
+            Err(e) => e.to_string(),
+        };
+        assert!(
+            message.contains("profile `staging` not found"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn rejects_include_cycles() {
+        let dir = project("offsetup-cycle-test");
+        write(&dir, "common/base.yml", "include: [../offsetup.yml]\n");
+
+        let message = match layers(&dir.join("offsetup.yml"), None, &FakeRunner::new()) {
+            Ok(_) => panic!("an include cycle should be an error"),
+            Err(e) => e.to_string(),
+        };
+        assert!(message.contains("includes itself"), "{}", message);
+    }
+}
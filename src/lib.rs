@@ -1,19 +1,58 @@
 #[macro_use]
 extern crate validator_derive;
 
-mod scanning;
-
-use std::path::PathBuf;
+mod docker;
+mod download;
+mod firewall;
+mod format;
+mod interpolate;
+mod layers;
+mod lock;
+mod plan;
+mod ports;
+mod readiness;
+pub mod runner;
+pub mod scanning;
+mod schema;
+mod service;
+mod shared;
+mod strict;
+mod validation;
+
+use std::path::{Path, PathBuf};
 use std::{
-    collections::HashMap,
-    env,
-    process::Command as SystemCommand,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    env, fmt, fs, io, iter,
+    net::IpAddr,
+    ops::RangeInclusive,
+    process::{self, Command as SystemCommand},
     string::{ParseError, ToString},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
 };
 
-use config::{Config, ConfigError, Environment, File, FileFormat};
-use scanning::platform::{Platform as CurrentPlatform, PlatformName};
-use serde::{Deserialize, Deserializer};
+use config::{Config, ConfigError};
+use format::Format;
+use interpolate::{Secret, Secrets};
+use itertools::Itertools;
+use lock::{LockedApplication, LockedPlatform, Lockfile};
+use runner::{CommandRunner, SystemRunner};
+use scanning::inventory::{Inventory, PackageDatabase};
+use scanning::platform::{
+    LangDependency, Platform as CurrentPlatform, PlatformName, PlatformScanner,
+};
+use schemars::JsonSchema;
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+        MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use shared::SharedPackages;
 use structopt::StructOpt;
 use urlparse::{urlparse, Url};
 use validator::{Validate, ValidationError};
@@ -21,85 +60,530 @@ use validator::{Validate, ValidationError};
 // Since structopt/clap does not support config file, only cli and env, we split the two between
 // 1) config for file and environment
 // 2) structopt for CLI
-#[derive(Clone, Debug, Deserialize)]
+#[allow(dead_code)]
+#[derive(Clone, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct OffSetup {
+    /// Name of the project
     name: String,
+    /// Version of the project
     version: String,
+    /// Other config files, merged beneath this one in order
+    include: Option<Vec<Include>>,
 
     dependencies: Option<Dependencies>,
     exposes: Option<Exposes>,
+    start: Option<Start>,
 
+    /// Set from the `--debug` flag
     debug: Option<bool>,
+    /// Set from the `--dry-run` flag
     dry_run: Option<bool>,
+
+    /// Values interpolated from the environment or secret files, masked wherever printed
+    #[serde(skip)]
+    #[schemars(skip)]
+    secrets: Secrets,
+}
+
+impl fmt::Debug for OffSetup {
+    /// The fields of the configuration, with every secret value masked
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unredacted = if f.alternate() {
+            format!("{:#?}", Unredacted(self))
+        } else {
+            format!("{:?}", Unredacted(self))
+        };
+        f.write_str(&self.secrets.redact(&unredacted))
+    }
+}
+
+/// Debug output of a configuration, secrets included
+struct Unredacted<'a>(&'a OffSetup);
+
+impl fmt::Debug for Unredacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let config = self.0;
+        f.debug_struct("OffSetup")
+            .field("name", &config.name)
+            .field("version", &config.version)
+            .field("include", &config.include)
+            .field("dependencies", &config.dependencies)
+            .field("exposes", &config.exposes)
+            .field("start", &config.start)
+            .field("debug", &config.debug)
+            .field("dry_run", &config.dry_run)
+            .finish()
+    }
 }
 
 impl OffSetupCli {
     fn process_command(&self, config: OffSetup, current_platform: &CurrentPlatform) -> OffSetup {
         match self.cmd {
-            Command::Init => OffSetupCli::run_new_command(&config),
-            Command::Install => OffSetupCli::run_install_command(&config, &current_platform),
+            Command::Init => OffSetupCli::run_new_command(&config, current_platform),
+            Command::Install {
+                locked,
+                systemd,
+                user,
+            } => self.run_install_command(&config, current_platform, locked, systemd, user),
             Command::Uninstall { remove_shared } => {
-                OffSetupCli::run_uninstall_command(&config, remove_shared)
+                OffSetupCli::run_uninstall_command(&config, current_platform, remove_shared)
             }
             Command::Start => OffSetupCli::run_start_command(&config),
             Command::Stop => OffSetupCli::run_stop_command(&config),
+            Command::Validate => OffSetupCli::run_validate_command(&config),
+            Command::Status => OffSetupCli::run_status_command(&config, current_platform),
+            Command::Export { ref cmd } => {
+                OffSetupCli::run_export_command(&config, cmd, current_platform)
+            }
+            Command::Schema
+            | Command::Convert { .. }
+            | Command::Config { .. }
+            | Command::Import { .. } => {
+                unreachable!("{:?} runs without loading the config", self.cmd)
+            }
         }
         config
     }
 
     pub fn run() -> (OffSetupCli, OffSetup) {
         let args: OffSetupCli = OffSetupCli::from_args();
+        if let Command::Schema = args.cmd {
+            print!("{}", schema::schema_json());
+            process::exit(0);
+        }
+        if let Command::Convert { output } = &args.cmd {
+            if let Err(e) = OffSetupCli::run_convert_command(&args, Path::new(output)) {
+                eprintln!("Failed to convert configuration file:\n{}", e);
+                process::exit(1);
+            }
+            process::exit(0);
+        }
+        if let Command::Config { cmd } = &args.cmd {
+            if let Err(e) = OffSetupCli::run_config_command(&args, cmd) {
+                eprintln!("Failed to read configuration:\n{}", e);
+                process::exit(1);
+            }
+            process::exit(0);
+        }
+        if let Command::Import { cmd } = &args.cmd {
+            if let Err(e) = OffSetupCli::run_import_command(cmd) {
+                eprintln!("Failed to import:\n{}", e);
+                process::exit(1);
+            }
+            process::exit(0);
+        }
         let config = OffSetup::with_cli(args.clone());
         let current_platform = CurrentPlatform::default();
         match config {
             Ok(c) => (args.clone(), args.process_command(c, &current_platform)),
-            Err(e) => panic!("Failed to load configuration file: {:#?}", e),
+            Err(e) => panic!("Failed to load configuration file:\n{}", e),
         }
     }
 
     /// Generate basic config based on environment and save to current directory in offsetup.yml
-    fn run_new_command(config: &OffSetup) {
+    fn run_new_command(config: &OffSetup, current_platform: &CurrentPlatform) {
         match config.dry_run {
             Some(true) => {
                 println!("DRY-RUN: output to offsetup.yml");
-                println!("...");
+                println!("{:#?}", new_applications("."));
+                if let Some(manager) = current_platform.name.package_manager() {
+                    println!(
+                        "{}.system.{}: {:?}",
+                        current_platform.name,
+                        manager,
+                        new_system_packages(".", &current_platform.name)
+                    );
+                }
             }
             _ => unimplemented!(),
         }
     }
 
-    fn run_install_command(config: &OffSetup, current_platform: &CurrentPlatform) {
+    /// Install, then lock the versions installed. With `locked`, install the locked versions instead.
+    /// With `systemd`, also write and enable a unit starting the project on boot, a user unit with
+    /// `user`.
+    fn run_install_command(
+        &self,
+        config: &OffSetup,
+        current_platform: &CurrentPlatform,
+        locked: bool,
+        systemd: bool,
+        user: bool,
+    ) {
+        preflight_ports(config);
+        let unit = if systemd {
+            let service = project_service(config, current_platform)
+                .unwrap_or_else(|| panic!("--systemd needs the start command of the project"));
+            Some(service::systemd::install(
+                &service,
+                user,
+                &service::systemd::unit_dir(user),
+            ))
+        } else {
+            None
+        };
         match config.dry_run {
             Some(true) => {
                 println!("DRY-RUN: what would be installed");
                 println!("...");
+                if let Some((backend, rules)) = firewall_rules(config, &SystemRunner) {
+                    let dir = shared::state_dir().join("firewall");
+                    for step in
+                        firewall::apply(backend, &config.name, &rules, &dir, &SystemRunner).steps
+                    {
+                        config.dry_run_print(&step.to_string());
+                    }
+                }
+                if let Some(services) = platform_services(config, current_platform) {
+                    let manager = service_manager(current_platform);
+                    let plan = service::manager::converge(&*manager, &services, &SystemRunner)
+                        .unwrap_or_else(|e| panic!("Failed to read services: {}", e));
+                    for step in plan.steps {
+                        config.dry_run_print(&step.to_string());
+                    }
+                }
+                for (path, content) in unit.iter().flat_map(|plan| plan.files.iter()) {
+                    config.dry_run_print(&format!("write {}\n{}", path.display(), content));
+                }
+                for (path, content) in unit.iter().flat_map(|plan| plan.private_files.iter()) {
+                    config.dry_run_print(&format!(
+                        "write {} with mode 0600\n{}",
+                        path.display(),
+                        content
+                    ));
+                }
+                for step in unit.iter().flat_map(|plan| plan.steps.iter()) {
+                    config.dry_run_print(&step.to_string());
+                }
             }
             _ => {
+                let lock_path = Lockfile::path(
+                    &self
+                        .config_path()
+                        .unwrap_or_else(|e| panic!("Failed to find the config file: {}", e)),
+                );
+                let mut lock = Lockfile::load(&lock_path)
+                    .unwrap_or_else(|e| panic!("Failed to read {:?}: {}", lock_path, e));
+                let platform_name = current_platform.name.to_string();
+                let pinned = if locked {
+                    match lock.platforms.get(&platform_name) {
+                        Some(pinned) => Some(pinned.clone()),
+                        None => panic!(
+                            "{:?} has nothing locked for {}, run `offsetup install` without --locked to lock it",
+                            lock_path, platform_name
+                        ),
+                    }
+                } else {
+                    None
+                };
+
+                let installed_before = Inventory::scan(Path::new("/"), &SystemRunner)
+                    .unwrap_or_else(|e| panic!("Failed to read installed packages: {}", e));
                 config
                     .dependencies
                     .iter()
-                    .for_each(|d| d.install(current_platform));
+                    .for_each(|d| d.install(current_platform, pinned.as_ref()));
+
+                if pinned.is_none() {
+                    let inventory = Inventory::scan(Path::new("/"), &SystemRunner)
+                        .unwrap_or_else(|e| panic!("Failed to read installed packages: {}", e));
+                    lock.platforms.insert(
+                        platform_name,
+                        lock_platform(config, current_platform, &inventory, &SystemRunner),
+                    );
+                    lock.save(&lock_path)
+                        .unwrap_or_else(|e| panic!("Failed to write {:?}: {}", lock_path, e));
+                }
+
+                let path = SharedPackages::default_path();
+                let mut shared = SharedPackages::load(&path)
+                    .unwrap_or_else(|e| panic!("Failed to read {:?}: {}", path, e));
+                for system in config.systems(current_platform) {
+                    register_shared_packages(&config.name, system, &installed_before, &mut shared);
+                }
+                shared
+                    .save(&path)
+                    .unwrap_or_else(|e| panic!("Failed to write {:?}: {}", path, e));
+
+                if let Some(services) = platform_services(config, current_platform) {
+                    let manager = service_manager(current_platform);
+                    service::manager::converge(&*manager, &services, &SystemRunner)
+                        .and_then(|plan| plan::run(&plan, &SystemRunner))
+                        .unwrap_or_else(|e| {
+                            panic!("Failed to set services with {}: {}", manager.name(), e)
+                        });
+                }
+
+                if let Some((backend, rules)) = firewall_rules(config, &SystemRunner) {
+                    let dir = shared::state_dir().join("firewall");
+                    plan::run(
+                        &firewall::apply(backend, &config.name, &rules, &dir, &SystemRunner),
+                        &SystemRunner,
+                    )
+                    .unwrap_or_else(|e| panic!("Failed to open ports with {}: {}", backend, e));
+                }
+
+                if let Some(unit) = &unit {
+                    plan::run(unit, &SystemRunner)
+                        .unwrap_or_else(|e| panic!("Failed to install the systemd unit: {}", e));
+                }
             }
         }
     }
 
-    fn run_uninstall_command(config: &OffSetup, _remove_shared: bool) {
+    fn run_uninstall_command(
+        config: &OffSetup,
+        current_platform: &CurrentPlatform,
+        remove_shared: bool,
+    ) {
+        let path = SharedPackages::default_path();
+        let mut shared = SharedPackages::load(&path)
+            .unwrap_or_else(|e| panic!("Failed to read {:?}: {}", path, e));
+        let removals: Vec<(&str, SystemPackage)> = config
+            .systems(current_platform)
+            .into_iter()
+            .flat_map(|system| plan_uninstall(&config.name, system, &mut shared, remove_shared))
+            .collect();
+
         match config.dry_run {
             Some(true) => {
                 println!("DRY-RUN: what would be removed");
-                println!("...");
+                removals.iter().for_each(|(manager, package)| {
+                    println!(
+                        "{}",
+                        config
+                            .secrets
+                            .redact(&format!("{}: {}", manager, package.name))
+                    )
+                });
+                if let Some((backend, rules)) = firewall_rules(config, &SystemRunner) {
+                    for step in firewall::remove(backend, &config.name, &rules, &SystemRunner).steps
+                    {
+                        config.dry_run_print(&step.to_string());
+                    }
+                }
+                for step in installed_units(config, current_platform)
+                    .iter()
+                    .flat_map(|plan| &plan.steps)
+                {
+                    config.dry_run_print(&step.to_string());
+                }
+            }
+            _ => {
+                for unit in installed_units(config, current_platform) {
+                    plan::run(&unit, &SystemRunner)
+                        .unwrap_or_else(|e| panic!("Failed to remove the systemd unit: {}", e));
+                }
+                if let Some((backend, rules)) = firewall_rules(config, &SystemRunner) {
+                    plan::run(
+                        &firewall::remove(backend, &config.name, &rules, &SystemRunner),
+                        &SystemRunner,
+                    )
+                    .unwrap_or_else(|e| panic!("Failed to close ports with {}: {}", backend, e));
+                }
+                uninstall_packages(&removals, &SystemRunner)
+                    .unwrap_or_else(|e| panic!("Failed to remove packages: {}", e));
+                shared
+                    .save(&path)
+                    .unwrap_or_else(|e| panic!("Failed to write {:?}: {}", path, e));
             }
-            _ => unimplemented!(),
         }
     }
 
     fn run_start_command(config: &OffSetup) {
+        preflight_ports(config);
         match config.dry_run {
             Some(true) => {
                 println!("DRY-RUN: what would be started");
-                println!("...");
+                match &config.start {
+                    Some(start) => println!("{}", config.secrets.redact(&start.args().join(" "))),
+                    None => println!("..."),
+                }
+                for probe in config.exposes.iter().flat_map(Exposes::probes) {
+                    config.dry_run_print(&format!("wait for {}", probe));
+                }
             }
-            _ => unimplemented!(),
+            _ => {
+                if let Some(start) = &config.start {
+                    start
+                        .spawn()
+                        .unwrap_or_else(|e| panic!("Failed to start the project: {}", e));
+                }
+                wait_ready(config, &SystemRunner)
+                    .unwrap_or_else(|failure| panic!("Not ready: {}", failure));
+                println!("{} {}: ready", config.name, config.version);
+            }
+        }
+    }
+
+    /// Print the status of each service of the current platform, and what it should be when it
+    /// differs
+    fn run_status_command(config: &OffSetup, current_platform: &CurrentPlatform) {
+        let services = match platform_services(config, current_platform) {
+            Some(found) => found,
+            None => {
+                println!("{} {}: no services", config.name, config.version);
+                return;
+            }
+        };
+        let manager = service_manager(current_platform);
+        let mut satisfied = true;
+        for (name, desired) in services {
+            let status = manager.status(name, &SystemRunner).unwrap_or_else(|e| {
+                panic!("Failed to read {} with {}: {}", name, manager.name(), e)
+            });
+            if status.satisfies(desired) {
+                println!("{}: {}", name, status);
+            } else {
+                println!("{}: {}, should be {}", name, status, desired);
+                satisfied = false;
+            }
+        }
+        if !satisfied {
+            process::exit(1);
+        }
+    }
+
+    /// Loading the configuration already ran every check, so reaching here means it is valid
+    fn run_validate_command(config: &OffSetup) {
+        println!("{} {}: configuration is valid", config.name, config.version);
+    }
+
+    /// The config file given with `-c`, or else the one found in the current directory
+    fn config_path(&self) -> Result<PathBuf, ConfigError> {
+        match &self.config_file {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => format::discover(Path::new(".")),
+        }
+    }
+
+    /// Rewrite the config file as it is written, without interpolating it, in the format of `output`
+    fn run_convert_command(&self, output: &Path) -> Result<(), ConfigError> {
+        let input = self.config_path()?;
+        let content = format::render(&format::parse(&input)?, Format::from_path(output)?)?;
+        fs::write(output, content)
+            .map_err(|e| ConfigError::Message(format!("cannot write {:?}: {}", output, e)))?;
+        println!("converted {:?} to {:?}", input, output);
+        Ok(())
+    }
+
+    /// Print the merged configuration, without interpolating it so secrets are not shown
+    fn run_config_command(&self, cmd: &ConfigCommand) -> Result<(), ConfigError> {
+        match cmd {
+            ConfigCommand::Show { origin } => {
+                let path = self.config_path()?;
+                let layers = layers::layers(&path, self.profile.as_deref(), &SystemRunner)?;
+                if *origin {
+                    for (key, value, origin) in layers::origins(&layers)? {
+                        println!("{} = {}  # {}", key, value, origin);
+                    }
+                } else {
+                    let merged: serde_json::Value = layers::merge(&layers)?.try_into()?;
+                    print!(
+                        "{}",
+                        format::render(&layers::sorted(&merged), Format::Yaml)?
+                    );
+                }
+            }
+            ConfigCommand::Env => {
+                for (key, kind) in schema::overridable_keys() {
+                    println!("{}  {} ({})", layers::env_var(&key), key, kind);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the translated configuration, then list what was left out of it
+    fn run_import_command(cmd: &ImportCommand) -> Result<(), ConfigError> {
+        let (config, untranslated, output) = match cmd {
+            ImportCommand::Dockerfile { path, output } => {
+                let path = Path::new(path);
+                let content = fs::read_to_string(path)
+                    .map_err(|e| ConfigError::Message(format!("cannot read {:?}: {}", path, e)))?;
+                let translation = docker::dockerfile::translate(
+                    &docker::dockerfile::parse(&content),
+                    &project_name(path),
+                );
+                let untranslated = translation.untranslated.iter().map(ToString::to_string);
+                (translation.config, untranslated.collect(), output)
+            }
+            ImportCommand::Compose { path, output } => {
+                let path = Path::new(path);
+                let translation =
+                    docker::compose::translate(&format::parse(path)?, &project_name(path));
+                let untranslated = translation.untranslated.iter().map(ToString::to_string);
+                (
+                    translation.config,
+                    untranslated.collect::<Vec<String>>(),
+                    output,
+                )
+            }
+        };
+        write_or_print(&config, output.as_deref().map(Path::new))?;
+        for untranslated in &untranslated {
+            eprintln!("not translated: {}", untranslated);
+        }
+        Ok(())
+    }
+
+    /// Write the rendered file, then list what was left out of it
+    fn run_export_command(
+        config: &OffSetup,
+        cmd: &ExportCommand,
+        current_platform: &CurrentPlatform,
+    ) {
+        let (content, unexported, output) = match cmd {
+            ExportCommand::Dockerfile { platform, output } => {
+                let platform = platform
+                    .clone()
+                    .unwrap_or_else(|| current_platform.name.to_string());
+                let export = docker::dockerfile::render(config, &platform)
+                    .unwrap_or_else(|e| panic!("Failed to export a Dockerfile: {}", e));
+                (export.dockerfile, export.unexported, output)
+            }
+            ExportCommand::Systemd { user, output } => {
+                let service = project_service(config, current_platform)
+                    .unwrap_or_else(|| panic!("A unit needs the start command of the project"));
+                let dir = service::systemd::unit_dir(*user);
+                let unexported = if service.environment.is_empty() {
+                    vec![]
+                } else {
+                    vec![format!(
+                        "environment: the unit reads it from {}, which `offsetup install --systemd` writes readable by its owner only",
+                        service::systemd::environment_path(&service, &dir).display()
+                    )]
+                };
+                (
+                    service::systemd::unit(&service, *user, &dir),
+                    unexported,
+                    output,
+                )
+            }
+            ExportCommand::Launchd { user, output } => {
+                let service = project_service(config, current_platform)
+                    .unwrap_or_else(|| panic!("A job needs the start command of the project"));
+                (service::launchd::plist(&service, *user), vec![], output)
+            }
+            ExportCommand::Windows { xml, output } => {
+                let service = project_service(config, current_platform)
+                    .unwrap_or_else(|| panic!("A service needs the start command of the project"));
+                let content = if *xml {
+                    service::windows::winsw_xml(&service)
+                } else {
+                    service::windows::sc_script(&service)
+                };
+                (content, vec![], output)
+            }
+        };
+        match output {
+            Some(output) => fs::write(output, &content)
+                .unwrap_or_else(|e| panic!("Failed to write {:?}: {}", output, e)),
+            None => print!("{}", content),
+        }
+        for unexported in &unexported {
+            eprintln!("not exported: {}", unexported);
         }
     }
 
@@ -137,6 +621,7 @@ pub struct OffSetupCli {
 
     // The number of occurrences of the `v/verbose` flag
     /// Verbose mode (-v, -vv, -vvv, etc.)
+    #[allow(dead_code)]
     #[structopt(
         short = "v",
         long = "verbose",
@@ -156,11 +641,16 @@ pub struct OffSetupCli {
 
     #[structopt(
         short = "c",
-        default_value = "offsetup.yml",
         raw(visible_aliases = r#"&["config", "configuration"]"#),
-        help = "Specify configuration file"
+        help = "Specify configuration file, its extension giving the format. Defaults to the first of offsetup.{yml,yaml,toml,json,json5} found"
+    )]
+    config_file: Option<String>,
+
+    #[structopt(
+        long = "profile",
+        help = "Merge config/<PROFILE>.{yml,yaml,toml,json,json5} over the config file. Defaults to $RUN_MODE, then development, when found"
     )]
-    config_file: String,
+    profile: Option<String>,
 
     #[structopt(subcommand)]
     cmd: Command,
@@ -180,7 +670,17 @@ enum Command {
         raw(visible_aliases = r#"&["-i","--install"]"#),
         help = "Install the project, and all its dependencies"
     )]
-    Install,
+    Install {
+        /// Install the versions recorded in offsetup.lock, failing when any is unavailable
+        #[structopt(long = "locked")]
+        locked: bool,
+        /// Write and enable a systemd unit starting the project on boot
+        #[structopt(long = "systemd")]
+        systemd: bool,
+        /// Install the unit for the current user, under ~/.config/systemd/user
+        #[structopt(long = "user", requires = "systemd")]
+        user: bool,
+    },
 
     #[structopt(
         name = "uninstall",
@@ -207,346 +707,1823 @@ enum Command {
         help = "Stops the project. Will have a nonzero exit code and a warning message if it's not started"
     )]
     Stop,
+
+    #[structopt(
+        name = "status",
+        help = "Reports whether the services of the current platform are enabled and running. Exits nonzero when any is not as configured"
+    )]
+    Status,
+
+    #[structopt(
+        name = "validate",
+        raw(visible_aliases = r#"&["--validate","check","--check"]"#),
+        help = "Checks the config file, reporting every unknown key and invalid value. Every other command runs these checks first"
+    )]
+    Validate,
+
+    #[structopt(
+        name = "schema",
+        raw(visible_aliases = r#"&["--schema"]"#),
+        help = "Prints the JSON Schema of the config file, for editors and CI to validate it with"
+    )]
+    Schema,
+
+    #[structopt(
+        name = "convert",
+        help = "Rewrites the config file in the format of OUTPUT's extension: .yml, .yaml, .toml, .json or .json5"
+    )]
+    Convert { output: String },
+
+    #[structopt(
+        name = "config",
+        help = "Inspects the configuration merged from every layer: included files, the config file, the profile, offsetup.local and the environment"
+    )]
+    Config {
+        #[structopt(subcommand)]
+        cmd: ConfigCommand,
+    },
+
+    #[structopt(
+        name = "import",
+        help = "Translates another tool's configuration into an offsetup config file, reporting what it cannot translate"
+    )]
+    Import {
+        #[structopt(subcommand)]
+        cmd: ImportCommand,
+    },
+
+    #[structopt(
+        name = "export",
+        help = "Renders the configuration as another tool's, listing what it cannot express"
+    )]
+    Export {
+        #[structopt(subcommand)]
+        cmd: ExportCommand,
+    },
+}
+
+#[derive(Clone, StructOpt, Debug, Deserialize)]
+enum ImportCommand {
+    #[structopt(
+        name = "dockerfile",
+        help = "Translates the final stage of a Dockerfile: its base image, packages, downloads, ports, environment and command"
+    )]
+    Dockerfile {
+        path: String,
+        /// File to write, in the format of its extension, instead of printing YAML
+        #[structopt(short = "o", long = "output")]
+        output: Option<String>,
+    },
+
+    #[structopt(
+        name = "compose",
+        help = "Translates the services of a docker-compose.yml into applications: their image, version, ports, environment, connection string and dependencies"
+    )]
+    Compose {
+        #[structopt(default_value = "docker-compose.yml")]
+        path: String,
+        /// File to write, in the format of its extension, instead of printing YAML
+        #[structopt(short = "o", long = "output")]
+        output: Option<String>,
+    },
+}
+
+#[derive(Clone, StructOpt, Debug, Deserialize)]
+enum ExportCommand {
+    #[structopt(
+        name = "dockerfile",
+        help = "Renders a Dockerfile from a platform: its base image, pre_install, packages, downloads, ports and start command"
+    )]
+    Dockerfile {
+        /// Platform to render, eg: ubuntu. Defaults to the current one
+        #[structopt(long = "platform")]
+        platform: Option<String>,
+        /// File to write instead of printing the Dockerfile
+        #[structopt(short = "o", long = "output")]
+        output: Option<String>,
+    },
+
+    #[structopt(
+        name = "systemd",
+        help = "Renders the systemd unit running the start command, after the applications the project depends on and the services the current platform starts"
+    )]
+    Systemd {
+        /// Render a user unit, for ~/.config/systemd/user
+        #[structopt(long = "user")]
+        user: bool,
+        /// File to write instead of printing the unit
+        #[structopt(short = "o", long = "output")]
+        output: Option<String>,
+    },
+
+    #[structopt(
+        name = "launchd",
+        help = "Renders the launchd property list running the start command on macOS"
+    )]
+    Launchd {
+        /// Render an agent started on login, for ~/Library/LaunchAgents
+        #[structopt(long = "user")]
+        user: bool,
+        /// File to write instead of printing the property list
+        #[structopt(short = "o", long = "output")]
+        output: Option<String>,
+    },
+
+    #[structopt(
+        name = "windows",
+        help = "Renders the sc.exe commands creating a Windows service for the start command"
+    )]
+    Windows {
+        /// Render the XML of a service wrapper such as WinSW instead, which also sets the working directory
+        #[structopt(long = "xml")]
+        xml: bool,
+        /// File to write instead of printing the service definition
+        #[structopt(short = "o", long = "output")]
+        output: Option<String>,
+    },
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, StructOpt, Debug, Deserialize)]
+enum ConfigCommand {
+    #[structopt(
+        name = "show",
+        help = "Prints the merged configuration. Use --origin to print each value with the file it came from"
+    )]
+    Show {
+        #[structopt(long = "origin")]
+        origin: bool,
+    },
+
+    #[structopt(
+        name = "env",
+        help = "Prints every key environment variables can override, with the variable's name. Nested keys are separated by __, lists by commas"
+    )]
+    Env,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 struct System {
-    /// Linux
-    // https://manpages.debian.org/stretch/apt/apt.8.en.html
-    apt: Option<Vec<String>>,
-    // https://manpages.debian.org/stretch/apt/apt-get.8.en.html
-    apt_get: Option<Vec<String>>,
-    // https://manpages.debian.org/stretch/aptitude/aptitude.8.en.html
-    aptitude: Option<Vec<String>>,
-    // https://wiki.sabayon.org/index.php?title=En:Entropy
-    equo: Option<Vec<String>>,
-    // https://wiki.gentoo.org/wiki/Handbook:AMD64/Working/Portage
-    emerge: Option<Vec<String>>,
-    // https://flathub.org
-    flatpak: Option<Vec<String>>,
-    // https://www.gnu.org/software/guix/
-    guix: Option<Vec<String>>,
-    // https://nixos.org/nix/manual/#chap-quick-start
-    nix: Option<Vec<String>>,
-    // http://www.openpkg.org/documentation/tutorial/
-    openpkg: Option<Vec<String>>,
-    // http://wiki.openmoko.org/wiki/Opkg
-    opkg: Option<Vec<String>>,
-    // https://wiki.archlinux.org/index.php/Pacman
-    pacman: Option<Vec<String>>,
-    // https://puppylinux.org/wikka/ppm
-    ppm: Option<Vec<String>>,
-    // https://github.com/examachine/pisi
-    pisi: Option<Vec<String>>,
-    // http://yum.baseurl.org
-    yum: Option<Vec<String>>,
-    // https://rpm-software-management.github.io
-    dnf: Option<Vec<String>>,
-    // http://rpmfind.net/linux/rpm2html/search.php?query=up2date
-    up2date: Option<Vec<String>>,
-    // https://metacpan.org/pod/distribution/urpmi/pod/8/urpmihowto.pod
-    urpmi: Option<Vec<String>>,
-    // https://slackpkg.org/documentation.html
-    slackpkg: Option<Vec<String>>,
-    // https://software.jaos.org/git/slapt-get/plain/README
-    slapt_get: Option<Vec<String>>,
-    // https://docs.snapcraft.io/getting-started
-    snap: Option<Vec<String>>,
-    // http://www.brunolinux.com/03-Installing_Software/Swaret.html
-    swaret: Option<Vec<String>>,
-
-    /// Windows
-    // https://chocolatey.org
-    choco: Option<Vec<String>>,
-
-    /// OS X
-    // https://brew.sh
-    brew: Option<Vec<String>>,
-
-    /// BSD
-    // https://www.freebsd.org/cgi/man.cgi?query=pkg
-    pkg: Option<Vec<String>>,
-
-    /// Windows, Linux, OS X
-    // https://0install.de/docs/commands/
-    _0install: Option<Vec<String>>,
-
-    apk: Option<Vec<String>>,
-}
-
-#[derive(Clone, Debug, Deserialize)]
+    // Linux
+    /// https://manpages.debian.org/stretch/apt/apt.8.en.html
+    apt: Option<Packages>,
+    /// https://manpages.debian.org/stretch/apt/apt-get.8.en.html
+    apt_get: Option<Packages>,
+    /// https://manpages.debian.org/stretch/aptitude/aptitude.8.en.html
+    aptitude: Option<Packages>,
+    /// https://wiki.sabayon.org/index.php?title=En:Entropy
+    equo: Option<Packages>,
+    /// https://wiki.gentoo.org/wiki/Handbook:AMD64/Working/Portage
+    emerge: Option<Packages>,
+    /// https://flathub.org
+    flatpak: Option<Packages>,
+    /// https://www.gnu.org/software/guix/
+    guix: Option<Packages>,
+    /// https://nixos.org/nix/manual/#chap-quick-start
+    nix: Option<Packages>,
+    /// http://www.openpkg.org/documentation/tutorial/
+    openpkg: Option<Packages>,
+    /// http://wiki.openmoko.org/wiki/Opkg
+    opkg: Option<Packages>,
+    /// https://wiki.archlinux.org/index.php/Pacman
+    pacman: Option<Packages>,
+    /// https://puppylinux.org/wikka/ppm
+    ppm: Option<Packages>,
+    /// https://github.com/examachine/pisi
+    pisi: Option<Packages>,
+    /// http://yum.baseurl.org
+    yum: Option<Packages>,
+    /// https://rpm-software-management.github.io
+    dnf: Option<Packages>,
+    /// http://rpmfind.net/linux/rpm2html/search.php?query=up2date
+    up2date: Option<Packages>,
+    /// https://metacpan.org/pod/distribution/urpmi/pod/8/urpmihowto.pod
+    urpmi: Option<Packages>,
+    /// https://slackpkg.org/documentation.html
+    slackpkg: Option<Packages>,
+    /// https://software.jaos.org/git/slapt-get/plain/README
+    slapt_get: Option<Packages>,
+    /// https://docs.snapcraft.io/getting-started
+    snap: Option<Packages>,
+    /// http://www.brunolinux.com/03-Installing_Software/Swaret.html
+    swaret: Option<Packages>,
+
+    // Windows
+    /// https://chocolatey.org
+    choco: Option<Packages>,
+
+    // OS X
+    /// https://brew.sh
+    brew: Option<Packages>,
+
+    // BSD
+    /// https://www.freebsd.org/cgi/man.cgi?query=pkg
+    pkg: Option<Packages>,
+
+    // Windows, Linux, OS X
+    /// https://0install.de/docs/commands/
+    _0install: Option<Packages>,
+
+    /// https://wiki.alpinelinux.org/wiki/Alpine_Linux_package_management
+    apk: Option<Packages>,
+}
+
+impl System {
+    /// Packages by manager name, as written in the configuration
+    fn managers(&self) -> Vec<(&'static str, &Packages)> {
+        vec![
+            ("apt", &self.apt),
+            ("apt_get", &self.apt_get),
+            ("aptitude", &self.aptitude),
+            ("equo", &self.equo),
+            ("emerge", &self.emerge),
+            ("flatpak", &self.flatpak),
+            ("guix", &self.guix),
+            ("nix", &self.nix),
+            ("openpkg", &self.openpkg),
+            ("opkg", &self.opkg),
+            ("pacman", &self.pacman),
+            ("ppm", &self.ppm),
+            ("pisi", &self.pisi),
+            ("yum", &self.yum),
+            ("dnf", &self.dnf),
+            ("up2date", &self.up2date),
+            ("urpmi", &self.urpmi),
+            ("slackpkg", &self.slackpkg),
+            ("slapt_get", &self.slapt_get),
+            ("snap", &self.snap),
+            ("swaret", &self.swaret),
+            ("choco", &self.choco),
+            ("brew", &self.brew),
+            ("pkg", &self.pkg),
+            ("_0install", &self._0install),
+            ("apk", &self.apk),
+        ]
+        .into_iter()
+        .filter_map(|(name, packages)| packages.as_ref().map(|p| (name, p)))
+        .collect()
+    }
+}
+
+/// Packages to install with one package manager: a plain list, or split between packages other
+/// projects may also use (eg: cmake) and packages owned by this project alone
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum Packages {
+    List(Vec<Package>),
+    Grouped(PackageGroups),
+}
+
+// untagged variants are buffered before being deserialized, so unknown keys in them are never
+// reported by strict loading: they are denied instead
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct PackageGroups {
+    /// Kept on uninstall while other projects still use them
+    sharable: Option<Vec<Package>>,
+    /// Owned by this project, removed on uninstall
+    exclusive: Option<Vec<Package>>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum Package {
+    Name(String),
+    Detailed(DetailedPackage),
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct DetailedPackage {
+    name: String,
+    /// Exact version to install
+    version: Option<String>,
+    /// Overrides the group the package is listed in
+    sharable: Option<bool>,
+}
+
+/// A package with its group resolved. Packages not listed as `exclusive` are sharable, so that
+/// uninstalling a project never removes a package another project may rely on.
+#[derive(Clone, Debug, PartialEq)]
+struct SystemPackage {
+    name: String,
+    version: Option<String>,
+    sharable: bool,
+}
+
+impl SystemPackage {
+    /// `name=version`, as package managers and the inventory take pinned packages
+    fn spec(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}={}", self.name, version),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Whether `manager` can install a given version of a package, so that its packages are locked
+fn pins_versions(manager: &str) -> bool {
+    matches!(
+        manager,
+        "apt" | "apt_get" | "aptitude" | "apk" | "yum" | "dnf"
+    )
+}
+
+impl SystemPackage {
+    /// The package as `manager` installs it, at its version when set
+    fn install_spec(&self, manager: &str) -> io::Result<String> {
+        let version = match &self.version {
+            Some(version) => version,
+            None => return Ok(self.name.clone()),
+        };
+        match manager {
+            "apt" | "apt_get" | "aptitude" | "apk" => Ok(format!("{}={}", self.name, version)),
+            "yum" | "dnf" => Ok(format!("{}-{}", self.name, version)),
+            // keep in sync with pins_versions
+            _ => Err(io::Error::other(format!(
+                "{} cannot install {} at version {}",
+                manager, self.name, version
+            ))),
+        }
+    }
+}
+
+impl Package {
+    fn resolve(&self, sharable: bool) -> SystemPackage {
+        match self {
+            Package::Name(name) => SystemPackage {
+                name: name.clone(),
+                version: None,
+                sharable,
+            },
+            Package::Detailed(package) => SystemPackage {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                sharable: package.sharable.unwrap_or(sharable),
+            },
+        }
+    }
+}
+
+impl Packages {
+    /// Every package, in the order written, sharable ones first when grouped
+    fn resolve(&self) -> Vec<SystemPackage> {
+        match self {
+            Packages::List(packages) => packages.iter().map(|p| p.resolve(true)).collect(),
+            Packages::Grouped(PackageGroups {
+                sharable,
+                exclusive,
+            }) => sharable
+                .iter()
+                .flatten()
+                .map(|p| p.resolve(true))
+                .chain(exclusive.iter().flatten().map(|p| p.resolve(false)))
+                .collect(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 struct Dependencies {
+    /// Services the project needs (eg: postgresql, redis), by name
     applications: Option<HashMap<String, Application>>,
+    /// How to install the project on each platform (eg: ubuntu, mac, windows), by name
     platforms: Option<HashMap<String, Platform>>,
 }
 
 fn process_bash(command: &str) {
     SystemCommand::new("sh")
-        .args(command.split(" "))
+        .args(command.split(' '))
         .output()
-        .expect(format!("Command `{}` failed", command).as_str());
+        .unwrap_or_else(|_| panic!("Command `{}` failed", command));
 }
 
 fn process_cmd(command: &str) {
     SystemCommand::new("cmd")
-        .args(command.split(" "))
+        .args(command.split(' '))
         .output()
-        .expect(format!("Command `{}` failed", command).as_str());
+        .unwrap_or_else(|_| panic!("Command `{}` failed", command));
 }
 
 fn process_pre_install_windows(pre_install: &Option<Vec<String>>) {
-    match pre_install {
-        Some(script) => {
-            script.iter().for_each(|s| process_cmd(s.as_str()));
-        }
-        None => {}
+    if let Some(script) = pre_install {
+        script.iter().for_each(|s| process_cmd(s.as_str()));
     }
 }
 
 fn process_pre_install_unix_like(pre_install: &Option<Vec<String>>) {
-    match pre_install {
-        Some(script) => {
-            script.iter().for_each(|s| process_bash(s.as_str()));
+    if let Some(script) = pre_install {
+        script.iter().for_each(|s| process_bash(s.as_str()));
+    }
+}
+
+/// Fetch and verify `downloads` into `directory`, printing each file's progress
+fn run_downloads(downloads: &Option<Downloads>, directory: &Option<String>) {
+    let downloads: Vec<&Download> = match downloads {
+        Some(downloads) => downloads.as_slice().iter().collect(),
+        None => return,
+    };
+    let directory = PathBuf::from(directory.as_ref().map_or(".", String::as_str));
+    let total = downloads.len();
+    let done = AtomicUsize::new(0);
+    let results = download::fetch_all(
+        &downloads,
+        &directory,
+        download::DOWNLOAD_CONCURRENCY,
+        &SystemRunner,
+        &|progress| match progress {
+            download::Progress::Started { filename } => println!("downloading {}", filename),
+            download::Progress::Verified { filename, path } => println!(
+                "[{}/{}] {} verified: {}",
+                done.fetch_add(1, Ordering::SeqCst) + 1,
+                total,
+                filename,
+                path.display()
+            ),
+            download::Progress::Failed { filename, error } => println!(
+                "[{}/{}] {} failed: {}",
+                done.fetch_add(1, Ordering::SeqCst) + 1,
+                total,
+                filename,
+                error
+            ),
+        },
+    );
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    if failed > 0 {
+        panic!("{} of {} downloads failed", failed, total);
+    }
+}
+
+/// Program and arguments installing packages with `manager`
+fn install_command(manager: &str) -> Option<(&'static str, &'static [&'static str])> {
+    match manager {
+        "apt" | "apt_get" | "aptitude" => Some(("apt-get", &["install", "-y"])),
+        "pacman" => Some(("pacman", &["-S", "--noconfirm"])),
+        "yum" => Some(("yum", &["install", "-y"])),
+        "dnf" => Some(("dnf", &["install", "-y"])),
+        "apk" => Some(("apk", &["add"])),
+        "brew" => Some(("brew", &["install"])),
+        "choco" => Some(("choco", &["install", "-y"])),
+        _ => None,
+    }
+}
+
+/// Install the packages of `system` missing from `inventory`, with one command per package
+/// manager. With `locked`, every package is pinned to its locked version, except those of managers
+/// that cannot install a given version (eg: brew, pacman, choco), installed at their latest.
+fn install_system(
+    system: &System,
+    inventory: &Inventory,
+    locked: Option<&LockedPlatform>,
+    runner: &dyn CommandRunner,
+) -> io::Result<()> {
+    for (manager, packages) in system.managers() {
+        let mut packages = packages.resolve();
+        if locked.is_some() && !pins_versions(manager) {
+            let names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+            println!(
+                "not pinning {:?}: {} cannot install a locked version",
+                names, manager
+            );
+        }
+        if let Some(locked) = locked.filter(|_| pins_versions(manager)) {
+            for package in &mut packages {
+                let version = locked.version(manager, &package.name).ok_or_else(|| {
+                    io::Error::other(format!(
+                        "{} {} is not locked, run `offsetup install` without --locked to lock it",
+                        manager, package.name
+                    ))
+                })?;
+                package.version = Some(version.into());
+            }
+        }
+        // the packages of managers whose database is not scanned are always installed
+        let database = PackageDatabase::of(manager);
+        let missing: Vec<&SystemPackage> = packages
+            .iter()
+            .filter(|package| {
+                !database.is_some_and(|database| inventory.is_satisfied(database, &package.spec()))
+            })
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+        let (program, flags) = match install_command(manager) {
+            Some(command) => command,
+            None => {
+                let names: Vec<&str> = missing.iter().map(|p| p.name.as_str()).collect();
+                println!("not installing {:?}: {} is not supported", names, manager);
+                continue;
+            }
+        };
+        let specs = missing
+            .iter()
+            .map(|package| package.install_spec(manager))
+            .collect::<io::Result<Vec<String>>>()?;
+        let args: Vec<&str> = flags
+            .iter()
+            .cloned()
+            .chain(specs.iter().map(String::as_str))
+            .collect();
+        let output = runner.run(program, &args)?;
+        if !output.success {
+            return Err(io::Error::other(format!(
+                "`{} {}` failed: {}",
+                program,
+                args.join(" "),
+                output.stderr
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fail when one of `downloads` is not the file locked for its URI
+fn check_locked_downloads(
+    downloads: &Option<Downloads>,
+    locked: &LockedPlatform,
+) -> Result<(), String> {
+    for download in downloads.iter().flat_map(Downloads::as_slice) {
+        let uri = download.uri.unparse();
+        match locked.downloads.get(&uri) {
+            Some(sha512) if sha512.eq_ignore_ascii_case(&download.sha512) => {}
+            Some(_) => return Err(format!("the sha512 of {} changed since it was locked", uri)),
+            None => return Err(format!("{} is not locked", uri)),
         }
-        None => {}
     }
+    Ok(())
 }
 
-fn run_apt_command(apt: &Vec<String>) {
-    let cmd = format!("apt-get install {}", apt.join(" "));
-    process_bash(cmd.as_str());
+fn install_platform(
+    platform: &Platform,
+    current_platform: &CurrentPlatform,
+    locked: Option<&LockedPlatform>,
+) {
+    match &current_platform.name {
+        PlatformName::Windows => process_pre_install_windows(&platform.pre_install),
+        PlatformName::Unknown => panic!("WHAT YO' DOIN'"),
+        _ => process_pre_install_unix_like(&platform.pre_install),
+    };
+
+    let inventory = Inventory::scan(Path::new("/"), &SystemRunner)
+        .unwrap_or_else(|e| panic!("Failed to read installed packages: {}", e));
+    let systems = platform
+        .system
+        .iter()
+        .chain(platform.source.iter().flat_map(|s| s.system.iter()));
+    for system in systems {
+        install_system(system, &inventory, locked, &SystemRunner)
+            .unwrap_or_else(|e| panic!("Failed to install packages: {}", e));
+    }
+
+    let downloads = platform
+        .source
+        .iter()
+        .map(|s| (&s.download, &s.download_directory))
+        .chain(iter::once((
+            &platform.download,
+            &platform.download_directory,
+        )));
+    for (downloads, directory) in downloads {
+        if let Some(locked) = locked {
+            check_locked_downloads(downloads, locked)
+                .unwrap_or_else(|e| panic!("{}, run `offsetup install` without --locked", e));
+        }
+        run_downloads(downloads, directory);
+    }
 }
 
-fn run_pacman_command(pacman: &Vec<String>) {
-    let cmd = format!("pacman -S {}", pacman.join(" "));
-    process_bash(cmd.as_str());
+/// The versions of the packages of the current platform found in `inventory`, for the managers
+/// that can pin them, the checksums of its downloads and what the applications resolved to
+fn lock_platform(
+    config: &OffSetup,
+    current_platform: &CurrentPlatform,
+    inventory: &Inventory,
+    runner: &dyn CommandRunner,
+) -> LockedPlatform {
+    let mut locked = LockedPlatform::default();
+    for system in config.systems(current_platform) {
+        for (manager, packages) in system.managers() {
+            if !pins_versions(manager) {
+                continue;
+            }
+            let database = PackageDatabase::of(manager);
+            for package in packages.resolve() {
+                let installed =
+                    database.and_then(|database| inventory.get(database, &package.name));
+                if let Some(installed) = installed {
+                    locked.lock_package(manager, &package.name, &installed.version);
+                }
+            }
+        }
+    }
+    let platform = config
+        .dependencies
+        .as_ref()
+        .and_then(|d| d.platform(current_platform));
+    if let Some(platform) = platform {
+        let downloads = platform
+            .source
+            .iter()
+            .map(|s| &s.download)
+            .chain(iter::once(&platform.download));
+        for download in downloads.flatten().flat_map(Downloads::as_slice) {
+            locked
+                .downloads
+                .insert(download.uri.unparse(), download.sha512.to_lowercase());
+        }
+    }
+    let applications = config
+        .dependencies
+        .iter()
+        .flat_map(|d| d.applications.iter().flatten());
+    for (name, application) in applications {
+        let resolved = lock_application(application, runner);
+        if resolved != LockedApplication::default() {
+            locked.applications.insert(name.clone(), resolved);
+        }
+    }
+    locked
 }
 
-fn run_yum_command(yum: &Vec<String>) {
-    let cmd = format!("yum install {}", yum.join(" "));
-    process_bash(cmd.as_str());
+/// The digest of the application's image, when pulled, and the commit its `pkg` repository is at
+fn lock_application(application: &Application, runner: &dyn CommandRunner) -> LockedApplication {
+    let stdout = |program: &str, args: &[&str]| match runner.run(program, args) {
+        Ok(output) if output.success => Some(output.stdout),
+        _ => None,
+    };
+    LockedApplication {
+        image: application.image.as_ref().and_then(|image| {
+            let format = "{{index .RepoDigests 0}}";
+            stdout("docker", &["image", "inspect", "--format", format, image])
+                .map(|digest| digest.trim().to_string())
+                .filter(|digest| !digest.is_empty())
+        }),
+        commit: application.pkg.as_ref().and_then(|pkg| {
+            stdout("git", &["ls-remote", pkg, "HEAD"])?
+                .split_whitespace()
+                .next()
+                .map(String::from)
+        }),
+    }
 }
 
-fn run_download_unix(download: &Download, directory: &Option<String>) {
-    unimplemented!()
+impl OffSetup {
+    /// Every System of the platform the project runs on, its own then its Source's
+    fn systems(&self, current_platform: &CurrentPlatform) -> Vec<&System> {
+        let platform = self
+            .dependencies
+            .as_ref()
+            .and_then(|d| d.platform(current_platform));
+        platform
+            .iter()
+            .flat_map(|p| {
+                p.system
+                    .iter()
+                    .chain(p.source.iter().flat_map(|s| s.system.iter()))
+            })
+            .collect()
+    }
 }
 
-fn run_arch_source(source: &Source) {
-    if let Some(system) = &source.system {
-        if let Some(pacman) = &system.pacman {
-            run_pacman_command(pacman);
+/// Record that `project` uses the sharable packages of `system` offsetup installed: those missing
+/// from the inventory taken before installing, or already used by another project. Packages of
+/// managers whose database is not scanned, eg: brew, may have been there before, so are not.
+fn register_shared_packages(
+    project: &str,
+    system: &System,
+    installed_before: &Inventory,
+    shared: &mut SharedPackages,
+) {
+    for (manager, packages) in system.managers() {
+        for package in packages.resolve().iter().filter(|p| p.sharable) {
+            let missing = PackageDatabase::of(manager)
+                .is_some_and(|database| installed_before.get(database, &package.name).is_none());
+            if missing || shared.is_registered(manager, &package.name) {
+                shared.register(manager, &package.name, project);
+            }
+        }
+    }
+}
+
+/// Packages of `system` to remove when uninstalling `project`: all of its exclusive packages, and
+/// with `remove_shared` the sharable packages it installed that no other project uses anymore.
+/// Releases the project's sharable packages from `shared` either way.
+fn plan_uninstall(
+    project: &str,
+    system: &System,
+    shared: &mut SharedPackages,
+    remove_shared: bool,
+) -> Vec<(&'static str, SystemPackage)> {
+    let mut removals = vec![];
+    for (manager, packages) in system.managers() {
+        for package in packages.resolve() {
+            if package.sharable {
+                let users = shared.release(manager, &package.name, project);
+                if !remove_shared || users != Some(0) {
+                    continue;
+                }
+            }
+            removals.push((manager, package));
         }
     }
-    if let Some(download) = &source.download {
-        run_download_unix(download, &source.download_directory);
+    removals
+}
+
+/// Program and arguments removing packages with `manager`
+fn remove_command(manager: &str) -> Option<(&'static str, &'static [&'static str])> {
+    match manager {
+        "apt" | "apt_get" | "aptitude" => Some(("apt-get", &["remove", "-y"])),
+        "pacman" => Some(("pacman", &["-R", "--noconfirm"])),
+        "yum" => Some(("yum", &["remove", "-y"])),
+        "dnf" => Some(("dnf", &["remove", "-y"])),
+        "apk" => Some(("apk", &["del"])),
+        "brew" => Some(("brew", &["uninstall"])),
+        "choco" => Some(("choco", &["uninstall", "-y"])),
+        _ => None,
     }
 }
 
-fn run_debian_source(source: &Source) {
-    if let Some(system) = &source.system {
-        if let Some(apt) = &system.apt {
-            run_apt_command(apt);
+/// Remove `removals` with one command per package manager
+fn uninstall_packages(
+    removals: &[(&str, SystemPackage)],
+    runner: &dyn CommandRunner,
+) -> io::Result<()> {
+    for (manager, packages) in &removals.iter().group_by(|(manager, _)| *manager) {
+        let names: Vec<&str> = packages.map(|(_, p)| p.name.as_str()).collect();
+        let (program, flags) = match remove_command(manager) {
+            Some(command) => command,
+            None => {
+                println!("not removing {:?}: {} is not supported", names, manager);
+                continue;
+            }
+        };
+        let args: Vec<&str> = flags.iter().cloned().chain(names).collect();
+        let output = runner.run(program, &args)?;
+        if !output.success {
+            return Err(io::Error::other(format!(
+                "`{} {}` failed: {}",
+                program,
+                args.join(" "),
+                output.stderr
+            )));
         }
-        if let Some(apt_get) = &system.apt_get {
-            run_apt_command(apt_get);
+    }
+    Ok(())
+}
+
+impl Dependencies {
+    fn platform(&self, current_platform: &CurrentPlatform) -> Option<&Platform> {
+        self.platforms
+            .as_ref()?
+            .get(current_platform.name.to_string().as_str())
+    }
+
+    fn install(&self, current_platform: &CurrentPlatform, locked: Option<&LockedPlatform>) {
+        self.install_applications();
+        self.install_platforms(current_platform, locked);
+    }
+
+    fn install_platforms(
+        &self,
+        current_platform: &CurrentPlatform,
+        locked: Option<&LockedPlatform>,
+    ) {
+        if let Some(p) = self.platform(current_platform) {
+            install_platform(p, current_platform, locked);
         }
     }
-    if let Some(download) = &source.download {
-        run_download_unix(download, &source.download_directory);
+
+    fn install_applications(&self) {}
+
+    /// Names of the applications, each after those it depends on. Fails with the names forming
+    /// a cycle, the first one repeated at the end.
+    fn application_order(&self) -> Result<Vec<&str>, Vec<&str>> {
+        fn visit<'a>(
+            name: &'a str,
+            applications: &'a HashMap<String, Application>,
+            path: &mut Vec<&'a str>,
+            order: &mut Vec<&'a str>,
+        ) -> Result<(), Vec<&'a str>> {
+            if order.contains(&name) {
+                return Ok(());
+            }
+            if let Some(start) = path.iter().position(|visiting| *visiting == name) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(name);
+                return Err(cycle);
+            }
+            path.push(name);
+            let depends_on = applications
+                .get(name)
+                .and_then(|application| application.depends_on.as_ref());
+            for dependency in depends_on.into_iter().flatten() {
+                // unknown names are reported by validation
+                if applications.contains_key(dependency) {
+                    visit(dependency, applications, path, order)?;
+                }
+            }
+            path.pop();
+            order.push(name);
+            Ok(())
+        }
+
+        let applications = match &self.applications {
+            Some(applications) => applications,
+            None => return Ok(vec![]),
+        };
+        let mut names: Vec<&str> = applications.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        let mut order = vec![];
+        for name in names {
+            visit(name, applications, &mut vec![], &mut order)?;
+        }
+        Ok(order)
     }
 }
 
-fn run_redhat_source(source: &Source) {
-    if let Some(system) = &source.system {
-        if let Some(yum) = &system.yum {
-            run_yum_command(yum);
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct Application {
+    /// Repository of the offsetup package installing the application
+    pkg: Option<String>,
+    /// Image the docker install method runs, eg: postgres:11
+    image: Option<String>,
+    /// Version constraint, eg: '>9.6.4'
+    version: Option<String>,
+    /// Environment variable the application's connection string is exported to
+    env: Option<String>,
+    /// Connection string exported to `env`, eg: redis://localhost:6379
+    uri: Option<String>,
+    /// Environment of the application, eg: [POSTGRES_DB=app]. A list, as table keys are lowercased
+    environment: Option<Vec<String>>,
+    exposes: Option<Exposes>,
+    /// Applications installed and started before this one
+    depends_on: Option<Vec<String>>,
+
+    /// Install methods to try, in order, eg: [docker, native]
+    install_priority: Option<Vec<String>>,
+    skip_install: Option<bool>,
+    fail_silently: Option<bool>,
+
+    /// Users to create
+    users: Option<Vec<User>>,
+    /// Databases to create
+    databases: Option<Vec<Database>>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct User {
+    name: String,
+    password: Option<Secret>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct Database {
+    name: String,
+    /// User owning the database
+    owner: Option<String>,
+}
+
+/// Language toolchains found in `dir`, as the application entries `offsetup new` writes
+fn new_applications(dir: &str) -> HashMap<String, Application> {
+    PlatformScanner::get_project_language_versions(dir.into())
+        .unwrap_or_default()
+        .iter()
+        .map(|language| (language.name.to_string(), Application::from(language)))
+        .collect()
+}
+
+/// System packages needed on `platform` for the languages `dir` is written in and the native libraries
+/// their dependencies link against, for `offsetup new`
+fn new_system_packages(dir: &str, platform: &PlatformName) -> Vec<String> {
+    let native = PlatformScanner::get_native_requirements(dir.into());
+    PlatformScanner::scan_project(dir.into(), None)
+        .significant()
+        .iter()
+        .flat_map(|language| language.name.system_packages(platform).iter())
+        .map(ToString::to_string)
+        .chain(native.for_platform(platform).iter().cloned())
+        .unique()
+        .collect()
+}
+
+impl From<&LangDependency> for Application {
+    fn from(language: &LangDependency) -> Application {
+        Application {
+            pkg: None,
+            image: None,
+            version: Some(language.version.clone()),
+            env: None,
+            uri: None,
+            environment: None,
+            exposes: None,
+            depends_on: None,
+            install_priority: None,
+            skip_install: None,
+            fail_silently: None,
+            users: None,
+            databases: None,
         }
     }
-    if let Some(download) = &source.download {
-        run_download_unix(download, &source.download_directory);
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Validate)]
+#[schemars(deny_unknown_fields)]
+#[validate(schema(function = "validate_platform_download", skip_on_field_errors = false))]
+struct Platform {
+    /// Version constraints of the platform this applies to, eg: ['14.04', '>16.04']
+    versions: Vec<String>,
+
+    /// CPU architecture, eg: x86_64
+    arch: Option<String>,
+
+    /// Build from source
+    source: Option<Source>,
+
+    /// Install with the system package managers
+    system: Option<System>,
+    /// Directory downloads are saved to
+    download_directory: Option<String>,
+    /// Installers and archives fetched as is, eg: msi files on Windows
+    download: Option<Downloads>,
+    /// Commands run before installing anything
+    pre_install: Option<Vec<String>>,
+    /// Install methods to try, in order, eg: [docker, native]
+    install_priority: Option<Vec<String>>,
+    skip_install: Option<bool>,
+    fail_silently: Option<bool>,
+    /// Daemons of the system packages to enable and start, or not, by the name their service
+    /// manager knows them by, eg: redis-server
+    services: Option<BTreeMap<String, service::manager::Desired>>,
+}
+
+fn validate_download_directory(
+    download_directory: &Option<String>,
+    download: &Option<Downloads>,
+) -> Result<(), ValidationError> {
+    if download_directory.is_none() && download.is_some() {
+        return Err(ValidationError::new("download_directory_required"));
+    }
+
+    if download_directory.is_some() && download.is_none() {
+        return Err(ValidationError::new("download_is_required"));
+    }
+
+    Ok(())
+}
+
+fn validate_source_download(data: &Source) -> Result<(), ValidationError> {
+    validate_download_directory(&data.download_directory, &data.download)
+}
+
+fn validate_platform_download(data: &Platform) -> Result<(), ValidationError> {
+    validate_download_directory(&data.download_directory, &data.download)
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Validate)]
+#[schemars(deny_unknown_fields)]
+#[validate(schema(function = "validate_source_download", skip_on_field_errors = false))]
+struct Source {
+    // validated along with the rest of the configuration by `validation::check` after loading
+    /// Directory downloads are saved to
+    download_directory: Option<String>,
+    download: Option<Downloads>,
+
+    /// Build dependencies
+    system: Option<System>,
+    install: Option<Install>,
+}
+
+/// Build commands of a Source, given inline or as a JSON pointer to commands defined elsewhere
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+#[serde(untagged)]
+enum Install {
+    Ref {
+        #[serde(rename = "$ref")]
+        reference: String,
+    },
+    Commands(Vec<String>),
+}
+
+/// A config file to merge beneath the one including it: a path relative to the including file,
+/// or a download pinned by its checksum
+#[derive(Clone, Debug, JsonSchema)]
+#[schemars(untagged)]
+enum Include {
+    Path(String),
+    Download(Box<Download>),
+}
+
+// A path or a table, told apart by the input's shape so the download is read straight from the source
+impl<'de> Deserialize<'de> for Include {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct IncludeVisitor;
+
+        impl<'de> Visitor<'de> for IncludeVisitor {
+            type Value = Include;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a path or a download")
+            }
+
+            fn visit_str<E: de::Error>(self, path: &str) -> Result<Include, E> {
+                Ok(Include::Path(path.into()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Include, A::Error> {
+                Download::deserialize(MapAccessDeserializer::new(map))
+                    .map(|download| Include::Download(Box::new(download)))
+            }
+        }
+
+        de.deserialize_any(IncludeVisitor)
     }
 }
 
-fn install_centos(platform: &Platform) {
-    process_pre_install_unix_like(&platform.pre_install);
-    if let Some(source) = &platform.source {
-        run_redhat_source(source);
+/// One download, or a list of them
+#[derive(Clone, Debug, JsonSchema)]
+#[schemars(untagged)]
+enum Downloads {
+    One(Box<Download>),
+    Many(Vec<Download>),
+}
+
+impl Downloads {
+    fn as_slice(&self) -> &[Download] {
+        match self {
+            Downloads::One(download) => std::slice::from_ref(&**download),
+            Downloads::Many(downloads) => downloads,
+        }
     }
 }
 
-fn install_debian(platform: &Platform) {
-    process_pre_install_unix_like(&platform.pre_install);
-    if let Some(source) = &platform.source {
-        run_debian_source(source);
+// Not `#[serde(untagged)]`: untagged enums buffer their input, hiding unknown keys from strict
+// loading. Dispatching on the input's shape deserializes the Download straight from the source.
+impl<'de> Deserialize<'de> for Downloads {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DownloadsVisitor;
+
+        impl<'de> Visitor<'de> for DownloadsVisitor {
+            type Value = Downloads;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a download or a list of downloads")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Downloads, A::Error> {
+                Download::deserialize(MapAccessDeserializer::new(map))
+                    .map(|download| Downloads::One(Box::new(download)))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Downloads, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq)).map(Downloads::Many)
+            }
+        }
+
+        de.deserialize_any(DownloadsVisitor)
     }
 }
 
-fn install_manjaro(platform: &Platform) {
-    process_pre_install_unix_like(&platform.pre_install);
-    if let Some(source) = &platform.source {
-        run_arch_source(source);
+pub trait DeserializeWith: Sized {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+impl DeserializeWith for Url {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+        Ok(urlparse(&s))
     }
 }
 
-fn install_redhat(platform: &Platform) {
-    process_pre_install_unix_like(&platform.pre_install);
-    if let Some(source) = &platform.source {
-        run_redhat_source(source);
+/// A file fetched, and verified, before building from source
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct Download {
+    /// Extract the archive after downloading it
+    extract: Option<bool>,
+    /// Hex encoded SHA-512 checksum of the file
+    sha512: String,
+    /// Keep the file on uninstall, as other projects may use it
+    #[serde(alias = "shareable")]
+    sharable: Option<bool>,
+    /// Name to save the file as, defaults to the last segment of the URI
+    filename: Option<String>,
+    /// Directory to save the file to, instead of the download directory
+    dest: Option<String>,
+    #[serde(deserialize_with = "Url::deserialize_with")]
+    /// Where to download the file from
+    #[schemars(with = "String")]
+    uri: Url,
+}
+
+/// How the project runs once installed
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct Start {
+    command: CommandLine,
+    /// Directory the command runs in, defaults to the current directory
+    working_directory: Option<String>,
+    /// Environment of the command, eg: [NODE_ENV=production]. A list, as table keys are lowercased
+    env: Option<Vec<String>>,
+    /// User the command runs as, defaults to the current user
+    user: Option<String>,
+    /// When service managers start the project again, defaults to on-failure
+    restart: Option<service::Restart>,
+}
+
+/// A command run through the shell, or a list of its program and arguments
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum CommandLine {
+    Shell(String),
+    Args(Vec<String>),
+}
+
+impl CommandLine {
+    fn args(&self) -> Vec<String> {
+        match self {
+            CommandLine::Shell(command) if cfg!(windows) => {
+                vec!["cmd".into(), "/C".into(), command.clone()]
+            }
+            CommandLine::Shell(command) => vec!["sh".into(), "-c".into(), command.clone()],
+            CommandLine::Args(args) => args.clone(),
+        }
     }
 }
 
-fn install_ubuntu(platform: &Platform) {
-    process_pre_install_unix_like(&platform.pre_install);
-    if let Some(source) = &platform.source {
-        run_debian_source(source);
+impl Start {
+    /// Program and arguments, run through `sudo` to switch to `user`. Only the names of the `env`
+    /// variables are listed, for sudo to keep: their values stay out of the process list.
+    fn args(&self) -> Vec<String> {
+        let user = match &self.user {
+            Some(user) => user,
+            None => return self.command.args(),
+        };
+        let mut args: Vec<String> = vec!["sudo".into(), "-u".into(), user.clone()];
+        let names: Vec<&str> = self.variables().map(|(name, _)| name).collect();
+        if !names.is_empty() {
+            args.push(format!("--preserve-env={}", names.join(",")));
+        }
+        args.extend(self.command.args());
+        args
+    }
+
+    /// Names and values of the `KEY=value` entries of `env`
+    fn variables(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.env
+            .iter()
+            .flatten()
+            .filter_map(|variable| variable.split_once('='))
+    }
+
+    /// Run the command in the background, left running once offsetup exits. Returns its pid
+    fn spawn(&self) -> io::Result<u32> {
+        let args = self.args();
+        let mut command = SystemCommand::new(&args[0]);
+        command.args(&args[1..]);
+        if let Some(dir) = &self.working_directory {
+            command.current_dir(dir);
+        }
+        command.envs(self.variables());
+        command.spawn().map(|child| child.id())
     }
 }
 
-fn install_macos(platform: &Platform) {
-    process_pre_install_unix_like(&platform.pre_install);
+/// What the project serves once started
+#[allow(dead_code)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+#[serde(rename_all = "lowercase")]
+enum Exposes {
+    /// Ports listened on
+    Ports {
+        /// Eg: 80, 8000-8010, redis: 6379, or {port: 6379, bind: 127.0.0.1, internal: true}
+        #[serde(default, deserialize_with = "Port::deserialize_list")]
+        #[schemars(with = "Option<Vec<PortShape>>")]
+        tcp: Option<Vec<Port>>,
+        #[serde(default, deserialize_with = "Port::deserialize_list")]
+        #[schemars(with = "Option<Vec<PortShape>>")]
+        udp: Option<Vec<Port>>,
+        /// Unix domain sockets, eg: /run/app.sock or app: /run/app.sock
+        #[serde(default, deserialize_with = "Socket::deserialize_list")]
+        #[schemars(with = "Option<Vec<SocketShape>>")]
+        unix: Option<Vec<Socket>>,
+        /// Ports to suggest instead of those already in use
+        port_range: Option<PortRange>,
+        /// How `start` tells the project is ready
+        readiness: Option<Readiness>,
+        /// Open the ports in the host firewall on install, and close them on uninstall
+        firewall: Option<Firewall>,
+    },
+}
+
+/// A port, or range of ports, listened on
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Port {
+    /// Referred to by other settings, eg: redis
+    name: Option<String>,
+    port: u16,
+    /// Last port of a range, included
+    end: Option<u16>,
+    /// Address listened on, defaults to every interface
+    bind: Option<String>,
+    /// Only used from the host or its private network, so left closed in the firewall
+    internal: Option<bool>,
 }
 
-fn install_arch(platform: &Platform) {
-    process_pre_install_unix_like(&platform.pre_install);
-    if let Some(source) = &platform.source {
-        run_arch_source(source);
+impl From<u16> for Port {
+    fn from(port: u16) -> Port {
+        Port {
+            name: None,
+            port,
+            end: None,
+            bind: None,
+            internal: None,
+        }
     }
 }
 
-fn install_windows(platform: &Platform) {
-    process_pre_install_windows(&platform.pre_install);
+impl Port {
+    fn deserialize_list<'de, D>(de: D) -> Result<Option<Vec<Port>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let entries: Option<Vec<PortEntry>> = Deserialize::deserialize(de)?;
+        Ok(entries.map(|entries| entries.into_iter().map(|entry| entry.0).collect()))
+    }
+
+    fn numbers(&self) -> RangeInclusive<u16> {
+        self.port..=self.end.unwrap_or(self.port)
+    }
+
+    /// Where the port is reached from the host itself
+    fn host(&self) -> String {
+        match self
+            .bind
+            .as_ref()
+            .and_then(|bind| bind.parse::<IpAddr>().ok())
+        {
+            Some(address) if !address.is_unspecified() => address.to_string(),
+            _ => "localhost".into(),
+        }
+    }
+
+    /// Whether other hosts are meant to connect to the port
+    fn is_public(&self) -> bool {
+        let loopback = self
+            .bind
+            .as_ref()
+            .and_then(|bind| bind.parse::<IpAddr>().ok())
+            .is_some_and(|address| address.is_loopback());
+        !loopback && !self.internal.unwrap_or(false)
+    }
 }
 
-fn install_platform(platform: &Platform, current_platform: &CurrentPlatform) {
-    match &current_platform.name {
-        PlatformName::Arch => install_arch(platform),
-        PlatformName::CentOS => install_centos(platform),
-        PlatformName::Debian => install_debian(platform),
-        PlatformName::Manjaro => install_manjaro(platform),
-        PlatformName::Redhat => install_redhat(platform),
-        PlatformName::Ubuntu => install_ubuntu(platform),
-        PlatformName::MacOSX => install_macos(platform),
-        PlatformName::Windows => install_windows(platform),
-        PlatformName::Unknown => panic!("WHAT YO' DOIN'"),
-    };
+/// A list item of `tcp` or `udp`: a number, a range, a named port or a table
+struct PortEntry(Port);
+
+// Tables are read whole first: loaded tables come in no fixed order, so whether `{redis: 6379}` is
+// one named port or `{port: 80, bind: ..}` a table is only known once every key is read. Buffered,
+// their keys never reach strict loading, so `Port` denies unknown keys itself, `x-` ones included
+// (see `WITHOUT_EXTENSIONS` in schema.rs).
+impl<'de> Deserialize<'de> for PortEntry {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PortVisitor;
+
+        impl<'de> Visitor<'de> for PortVisitor {
+            type Value = Port;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(
+                    "a port, eg: 80, a range, eg: 8000-8010, or a named port, eg: redis: 6379",
+                )
+            }
+
+            fn visit_u64<E: de::Error>(self, port: u64) -> Result<Port, E> {
+                u16::try_from(port)
+                    .map(Port::from)
+                    .map_err(|_| E::custom(format!("port {} is above 65535", port)))
+            }
+
+            fn visit_i64<E: de::Error>(self, port: i64) -> Result<Port, E> {
+                match u64::try_from(port) {
+                    Ok(port) => self.visit_u64(port),
+                    Err(_) => Err(E::custom(format!("port {} is negative", port))),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, text: &str) -> Result<Port, E> {
+                let invalid = || {
+                    E::custom(format!(
+                        "invalid port {:?}, expected eg: 80 or 8000-8010",
+                        text
+                    ))
+                };
+                let number = |text: &str| text.trim().parse::<u16>().map_err(|_| invalid());
+                match text.split_once('-') {
+                    Some((start, end)) => Ok(Port {
+                        end: Some(number(end)?),
+                        ..Port::from(number(start)?)
+                    }),
+                    None => number(text).map(Port::from),
+                }
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Port, A::Error> {
+                let table = JsonMap::deserialize(MapAccessDeserializer::new(map))?;
+                if table.contains_key("port") {
+                    return Port::deserialize(JsonValue::Object(table)).map_err(de::Error::custom);
+                }
+                if table.len() != 1 {
+                    return Err(de::Error::custom(
+                        "expected a table with a port, or one named port, eg: redis: 6379",
+                    ));
+                }
+                let (name, value) = table.into_iter().next().expect("one named port");
+                let mut port = PortEntry::deserialize(value).map_err(de::Error::custom)?.0;
+                port.name = Some(name);
+                Ok(port)
+            }
+        }
+
+        de.deserialize_any(PortVisitor).map(PortEntry)
+    }
 }
 
-impl Dependencies {
-    fn install(&self, current_platform: &CurrentPlatform) {
-        self.install_applications();
-        self.install_platforms(current_platform);
+/// A unix domain socket listened on
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Socket {
+    /// Referred to by other settings, eg: app
+    name: Option<String>,
+    /// Absolute path of the socket
+    path: String,
+}
+
+impl Socket {
+    fn deserialize_list<'de, D>(de: D) -> Result<Option<Vec<Socket>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let entries: Option<Vec<SocketEntry>> = Deserialize::deserialize(de)?;
+        Ok(entries.map(|entries| entries.into_iter().map(|entry| entry.0).collect()))
     }
+}
 
-    fn install_platforms(&self, current_platform: &CurrentPlatform) {
-        match &self.platforms {
-            Some(platforms) => {
-                if let Some(p) = platforms.get(current_platform.name.to_string().as_str()) {
-                    install_platform(p, current_platform);
+/// A list item of `unix`: a path, a named path or a table
+struct SocketEntry(Socket);
+
+// Tables are read whole first, as port tables are, so `Socket` denies unknown keys itself
+impl<'de> Deserialize<'de> for SocketEntry {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SocketVisitor;
+
+        impl<'de> Visitor<'de> for SocketVisitor {
+            type Value = Socket;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a socket path, or a named one, eg: app: /run/app.sock")
+            }
+
+            fn visit_str<E: de::Error>(self, path: &str) -> Result<Socket, E> {
+                Ok(Socket {
+                    name: None,
+                    path: path.into(),
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Socket, A::Error> {
+                let table = JsonMap::deserialize(MapAccessDeserializer::new(map))?;
+                if table.contains_key("path") {
+                    return Socket::deserialize(JsonValue::Object(table))
+                        .map_err(de::Error::custom);
+                }
+                let error = || {
+                    de::Error::custom(
+                        "expected a table with a path, or one named path, eg: app: /run/app.sock",
+                    )
+                };
+                if table.len() != 1 {
+                    return Err(error());
+                }
+                match table.into_iter().next() {
+                    Some((name, JsonValue::String(path))) => Ok(Socket {
+                        name: Some(name),
+                        path,
+                    }),
+                    _ => Err(error()),
                 }
             }
-            None => {}
-        };
+        }
+
+        de.deserialize_any(SocketVisitor).map(SocketEntry)
     }
+}
 
-    fn install_applications(&self) {}
+/// A port written as a number, a range, one named port or a table
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(untagged)]
+enum PortShape {
+    Number(u16),
+    /// First and last ports, eg: 8000-8010
+    Range(String),
+    /// One port by its name, eg: redis: 6379
+    Named(BTreeMap<String, NamedPortShape>),
+    Table(Port),
 }
 
-#[derive(Clone, Debug, Deserialize)]
-struct Application {
-    pkg: Option<String>,
-    version: Option<String>,
-    env: Option<String>,
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(untagged)]
+enum NamedPortShape {
+    Number(u16),
+    Range(String),
+    Table(Port),
+}
+
+/// A socket written as a path, one named path or a table
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(untagged)]
+enum SocketShape {
+    Path(String),
+    /// One path by its name, eg: app: /run/app.sock
+    Named(BTreeMap<String, String>),
+    Table(Socket),
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct Firewall {
+    /// Defaults to the firewall running on the host
+    backend: Option<firewall::Backend>,
+    /// CIDRs allowed to connect, eg: 10.0.0.0/8, any when empty
+    sources: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct PortRange {
+    start: u16,
+    /// Last port of the range, included
+    end: u16,
+}
+
+/// Probes `start` waits on. Every declared tcp port is connected to, then the optional HTTP
+/// and command probes run.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct Readiness {
+    /// Connect to every declared tcp port and unix socket, defaults to true
+    tcp: Option<bool>,
+    http: Option<HttpProbe>,
+    /// Shell command exiting successfully once ready, eg: redis-cli ping
+    command: Option<String>,
+    /// Seconds each attempt may take, defaults to 5
+    timeout: Option<u64>,
+    /// Attempts after the first failing one, defaults to 30
+    retries: Option<u32>,
+    /// Seconds between attempts, defaults to 1
+    interval: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct HttpProbe {
+    /// Path to GET, eg: /health
+    path: String,
+    /// Defaults to the first declared tcp port
+    port: Option<u16>,
+    /// Expected status, defaults to 200
+    status: Option<u16>,
+}
+
+impl Exposes {
+    /// Every declared port entry
+    fn entries(&self) -> Vec<(ports::Protocol, &Port)> {
+        let Exposes::Ports { tcp, udp, .. } = self;
+        tcp.iter()
+            .flatten()
+            .map(|port| (ports::Protocol::Tcp, port))
+            .chain(
+                udp.iter()
+                    .flatten()
+                    .map(|port| (ports::Protocol::Udp, port)),
+            )
+            .collect()
+    }
+
+    /// Every declared port, ranges included
+    fn ports(&self) -> Vec<(ports::Protocol, u16)> {
+        self.entries()
+            .into_iter()
+            .flat_map(|(protocol, port)| port.numbers().map(move |number| (protocol, number)))
+            .collect()
+    }
+
+    /// The ports other hosts are meant to connect to
+    fn public_ports(&self) -> Vec<(ports::Protocol, u16)> {
+        self.entries()
+            .into_iter()
+            .filter(|(_, port)| port.is_public())
+            .flat_map(|(protocol, port)| port.numbers().map(move |number| (protocol, number)))
+            .collect()
+    }
+
+    /// First and last ports alternatives are picked from
+    fn port_range(&self) -> Option<(u16, u16)> {
+        match self {
+            Exposes::Ports { port_range, .. } => port_range.map(|range| (range.start, range.end)),
+        }
+    }
+
+    /// What `start` waits on, in order
+    fn probes(&self) -> Vec<readiness::Probe> {
+        let Exposes::Ports {
+            tcp,
+            unix,
+            readiness,
+            ..
+        } = self;
+        let default = Readiness::default();
+        let readiness = readiness.as_ref().unwrap_or(&default);
+        let tcp = tcp.as_deref().unwrap_or_default();
+        let mut probes = vec![];
+        if readiness.tcp.unwrap_or(true) {
+            for port in tcp {
+                probes.extend(port.numbers().map(|number| readiness::Probe::Tcp {
+                    host: port.host(),
+                    port: number,
+                }));
+            }
+            probes.extend(unix.iter().flatten().map(|socket| readiness::Probe::Unix {
+                path: PathBuf::from(&socket.path),
+            }));
+        }
+        if let Some(http) = &readiness.http {
+            if let Some(port) = http.port.or_else(|| tcp.first().map(|port| port.port)) {
+                let host = tcp
+                    .iter()
+                    .find(|declared| declared.numbers().contains(&port))
+                    .map_or_else(|| "localhost".into(), Port::host);
+                probes.push(readiness::Probe::Http {
+                    host,
+                    port,
+                    path: http.path.clone(),
+                    status: http.status.unwrap_or(200),
+                });
+            }
+        }
+        if let Some(command) = &readiness.command {
+            probes.push(readiness::Probe::Command(command.clone()));
+        }
+        probes
+    }
 
-    install_priority: Option<Vec<String>>,
-    skip_install: Option<bool>,
-    fail_silently: Option<bool>,
+    fn readiness_policy(&self) -> readiness::Policy {
+        let Exposes::Ports { readiness, .. } = self;
+        let readiness = readiness.as_ref();
+        readiness::Policy {
+            timeout: Duration::from_secs(readiness.and_then(|r| r.timeout).unwrap_or(5)),
+            retries: readiness.and_then(|r| r.retries).unwrap_or(30),
+            interval: Duration::from_secs(readiness.and_then(|r| r.interval).unwrap_or(1)),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-struct Platform {
-    versions: Vec<String>,
-
-    arch: Option<String>,
+/// Firewall backend and rules opening the exposed ports, when the firewall step is enabled
+fn firewall_rules(
+    config: &OffSetup,
+    runner: &dyn CommandRunner,
+) -> Option<(firewall::Backend, Vec<firewall::Rule>)> {
+    let exposes = config.exposes.as_ref()?;
+    let Exposes::Ports { firewall, .. } = exposes;
+    let settings = firewall.as_ref()?;
+    let backend = settings
+        .backend
+        .or_else(|| firewall::detect(runner))
+        .unwrap_or_else(|| {
+            panic!("No firewall found, set exposes.ports.firewall.backend to the one to use")
+        });
+    let sources = settings.sources.clone().unwrap_or_default();
+    Some((backend, firewall::rules(&exposes.public_ports(), &sources)))
+}
 
-    source: Option<Source>,
+/// The services the current platform sets, none when it sets no services
+fn platform_services<'a>(
+    config: &'a OffSetup,
+    current_platform: &CurrentPlatform,
+) -> Option<Vec<(&'a str, &'a service::manager::Desired)>> {
+    let services = config
+        .dependencies
+        .as_ref()?
+        .platform(current_platform)?
+        .services
+        .as_ref()?;
+    Some(
+        services
+            .iter()
+            .map(|(name, desired)| (name.as_str(), desired))
+            .collect(),
+    )
+}
 
-    system: Option<System>,
-    pre_install: Option<Vec<String>>,
-    install_priority: Option<Vec<String>>,
-    skip_install: Option<bool>,
-    fail_silently: Option<bool>,
+/// The service manager running the services of the current platform
+fn service_manager(
+    current_platform: &CurrentPlatform,
+) -> Box<dyn service::manager::ServiceManager> {
+    service::manager::detect(&current_platform.name, &SystemRunner)
+        .unwrap_or_else(|| panic!("No service manager found on {}", current_platform.name))
 }
 
-fn validate_source_download(data: &Source) -> Result<(), ValidationError> {
-    if data.download_directory.is_none() && data.download.is_some() {
-        return Err(ValidationError::new("download_directory_required"));
+/// The service running the start command of the project, with the connection strings of its
+/// applications, after those installed natively and the services the current platform starts.
+/// Only the configuration orders it, not what the host runs, so exported units are reproducible.
+fn project_service(
+    config: &OffSetup,
+    current_platform: &CurrentPlatform,
+) -> Option<service::Service> {
+    let start = config.start.as_ref()?;
+    let mut environment: Vec<(String, String)> = start
+        .env
+        .iter()
+        .flatten()
+        .filter_map(|variable| variable.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let mut after = vec![];
+    let dependencies = config.dependencies.as_ref();
+    if let Some(applications) = dependencies.and_then(|d| d.applications.as_ref()) {
+        // cycles were reported when loading
+        let order = dependencies.and_then(|d| d.application_order().ok());
+        for name in order.unwrap_or_default() {
+            let application = &applications[name];
+            if let (Some(env), Some(uri)) = (&application.env, &application.uri) {
+                environment.push((env.clone(), uri.clone()));
+            }
+            // installed natively, an application runs as a service of its name, eg: postgresql
+            let native = application
+                .install_priority
+                .as_ref()
+                .is_none_or(|priority| priority.iter().any(|method| method == "native"));
+            if native {
+                after.push(name.to_string());
+            }
+        }
     }
-
-    if data.download_directory.is_some() && data.download.is_none() {
-        return Err(ValidationError::new("download_is_required"));
+    let started = platform_services(config, current_platform)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, desired)| desired.state == Some(service::manager::State::Started))
+        .map(|(name, _)| name.to_string());
+    for name in started {
+        if !after.contains(&name) {
+            after.push(name);
+        }
     }
-
-    Ok(())
+    let (args, shell) = match &start.command {
+        CommandLine::Shell(command) => (vec![command.clone()], true),
+        CommandLine::Args(args) => (args.clone(), false),
+    };
+    Some(service::Service {
+        name: service::name(&config.name),
+        description: format!("{} {}", config.name, config.version),
+        args,
+        shell,
+        working_directory: start.working_directory.clone(),
+        environment,
+        user: start.user.clone(),
+        restart: start.restart.unwrap_or_default(),
+        after,
+    })
 }
 
-#[derive(Clone, Debug, Deserialize, Validate)]
-#[validate(schema(function = "validate_source_download", skip_on_field_errors = false))]
-struct Source {
-    // TODO: find out if automatic/implicit validate() call can be made after Deserialize
-    download_directory: Option<String>,
-    download: Option<Download>,
-
-    system: Option<System>,
+/// How to remove the systemd units `install --systemd` wrote, system wide and for the user
+fn installed_units(config: &OffSetup, current_platform: &CurrentPlatform) -> Vec<plan::Plan> {
+    let service = match project_service(config, current_platform) {
+        Some(service) => service,
+        None => return vec![],
+    };
+    [false, true]
+        .iter()
+        .map(|&user| (user, service::systemd::unit_dir(user)))
+        .filter(|(_, dir)| dir.join(service::systemd::unit_name(&service)).exists())
+        .map(|(user, dir)| service::systemd::uninstall(&service, user, &dir))
+        .collect()
 }
 
-pub trait DeserializeWith: Sized {
-    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>;
+/// Block until the project answers every readiness probe, naming the probe that never passed
+fn wait_ready(config: &OffSetup, runner: &dyn CommandRunner) -> Result<(), readiness::Failure> {
+    match &config.exposes {
+        Some(exposes) => readiness::wait(&exposes.probes(), exposes.readiness_policy(), runner),
+        None => Ok(()),
+    }
 }
 
-impl DeserializeWith for Url {
-    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s = String::deserialize(de)?;
-        Ok(urlparse(&s))
-    }
+/// Name of the directory holding `path`, as the name of the project a file in it describes
+fn project_name(path: &Path) -> String {
+    fs::canonicalize(path)
+        .ok()
+        .as_ref()
+        .and_then(|path| path.parent())
+        .and_then(Path::file_name)
+        .map_or_else(
+            || "project".into(),
+            |name| name.to_string_lossy().into_owned(),
+        )
 }
 
-#[derive(Clone, Debug, Deserialize)]
-struct Download {
-    extract: Option<bool>,
-    sha512: String,
-    shareable: Option<bool>,
-    #[serde(deserialize_with = "Url::deserialize_with")]
-    uri: Url,
+/// Write `config` to `output` in the format of its extension, or print it as YAML
+fn write_or_print(config: &serde_json::Value, output: Option<&Path>) -> Result<(), ConfigError> {
+    match output {
+        Some(output) => {
+            let content = format::render(config, Format::from_path(output)?)?;
+            fs::write(output, content)
+                .map_err(|e| ConfigError::Message(format!("cannot write {:?}: {}", output, e)))
+        }
+        None => {
+            print!("{}", format::render(config, Format::Yaml)?);
+            Ok(())
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-enum Exposes {
-    Ports {
-        tcp: Option<Vec<u16>>,
-        udp: Option<Vec<u16>>,
-    },
+/// Fail when a port the project exposes is already in use, naming the process using it. Ports the
+/// current user lacks the privileges to bind are only warned about, as the project may run as another.
+fn preflight_ports(config: &OffSetup) {
+    let exposes = match &config.exposes {
+        Some(exposes) => exposes,
+        None => return,
+    };
+    let conflicts = ports::preflight(
+        &exposes.ports(),
+        exposes.port_range(),
+        &ports::availability,
+        Path::new("/proc"),
+    );
+    let (denied, conflicts): (Vec<_>, Vec<_>) = conflicts
+        .into_iter()
+        .partition(|conflict| conflict.availability == ports::Availability::Denied);
+    for conflict in &denied {
+        println!("warning: {}", conflict);
+    }
+    if conflicts.is_empty() {
+        return;
+    }
+    let report: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+    match config.dry_run {
+        Some(true) => println!("DRY-RUN: port conflicts\n{}", report.join("\n")),
+        _ => panic!("Ports already in use:\n{}", report.join("\n")),
+    }
 }
 
 impl OffSetup {
-    fn with_cli(cli: OffSetupCli) -> Result<Self, ConfigError> {
-        let mut config = Config::new();
-
-        println!(
-            "loading configuration from file: {:?}",
-            cli.config_file.clone()
-        );
-        config.merge(File::new(&cli.config_file, FileFormat::Yaml))?;
+    /// Print a line of a dry-run, masking secrets
+    fn dry_run_print(&self, line: &str) {
+        println!("DRY-RUN: {}", self.secrets.redact(line));
+    }
 
-        println!("loading configuration from environment");
-        config.merge(Environment::with_prefix("OFFSETUP"))?;
+    fn with_cli(cli: OffSetupCli) -> Result<Self, ConfigError> {
+        let (mut config, path) = OffSetup::read(
+            cli.config_file.as_ref().map(Path::new),
+            cli.profile.as_deref(),
+        )?;
 
-        if cli.install_priority.is_some() {
-            let priorities = cli.install_priority.unwrap();
+        if let Some(priorities) = cli.install_priority {
             println!("overriding install priorities to: {:?}", &priorities);
 
             if let Ok(Some(platforms)) =
@@ -566,33 +2543,51 @@ impl OffSetup {
 
         println!("configuration loaded");
 
-        config.try_into()
+        load(config, &path)
+    }
+
+    /// Merge every layer of the configuration at `path`, by default the config file found in the
+    /// current directory: see `layers::layers` for the files merged and their precedence
+    fn read(path: Option<&Path>, profile: Option<&str>) -> Result<(Config, PathBuf), ConfigError> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => format::discover(Path::new("."))?,
+        };
+        let layers = layers::layers(&path, profile, &SystemRunner)?;
+        for layer in &layers {
+            println!("loading configuration from {}", layer.origin);
+        }
+        Ok((layers::merge(&layers)?, path))
     }
 }
 
+/// Deserialize the merged configuration read from `origin`, then validate it, so that no command
+/// ever runs against a configuration with unknown keys or invalid values
+fn load(config: Config, origin: &Path) -> Result<OffSetup, ConfigError> {
+    let (config, secrets) = interpolate::interpolate(config, &|name| env::var(name).ok())?;
+    let source = match Format::from_path(origin) {
+        Ok(format) if format.is_yaml_compatible() => fs::read_to_string(origin).ok(),
+        _ => None,
+    };
+    let mut offsetup: OffSetup =
+        strict::deserialize(config.clone(), &origin.to_string_lossy(), source.as_deref())?;
+    validation::check(&offsetup, &config)?;
+    offsetup.secrets = secrets;
+    Ok(offsetup)
+}
+
 impl Default for OffSetup {
     fn default() -> Self {
         const DEFAULT: fn() -> Result<OffSetup, ConfigError> = || {
-            let mut config = Config::new();
-
-            // Start off by merging in the "default" configuration file
-            config.merge(File::from(PathBuf::from("offsetup.yml")))?;
-
-            // Add in the current environment file
-            // Default to 'development' env
-            // Note that this file is _optional_
-            let run_mode = env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
-            config.merge(File::from(PathBuf::from("config").join(run_mode)).required(false))?;
-
-            // Add in settings from the environment (with a prefix of OFFSETUP)
-            // Eg.. `OFFSETUP_DEBUG=1 ./target/app` would set the `debug` key
-            config.merge(Environment::with_prefix("OFFSETUP"))?;
+            // Start off by merging in the "default" configuration file, in any supported format
+            let (config, path) = OffSetup::read(None, None)?;
 
             // Now that we're done, let's access our configuration
             println!("debug: {:?}", config.get_bool("debug"));
 
-            // You can deserialize (and thus freeze) the entire configuration
-            config.try_into()
+            // You can deserialize (and thus freeze) the entire configuration, rejecting unknown keys
+            // and values breaking any validation rule
+            load(config, &path)
         };
         DEFAULT().unwrap()
     }
@@ -601,6 +2596,8 @@ impl Default for OffSetup {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::runner::fake::FakeRunner;
+    use config::{File, FileFormat};
 
     #[test]
     fn can_read_simple_ports() {
@@ -612,14 +2609,14 @@ mod tests {
 
             match config.get::<Option<Vec<u16>>>("ports.tcp") {
                 Ok(udp) => assert!(udp.is_some()),
-                Err(e) => panic!(format!("error getting tdp: {:?}", e)),
+                Err(e) => panic!("error getting tdp: {:?}", e),
             }
 
             config.try_into()
         };
         match get_exposes() {
             Ok(exposes) => println!("Successful: {:#?}", exposes),
-            Err(e) => panic!(format!("Failed to get configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get configuration: {:?}", e),
         }
     }
 
@@ -632,7 +2629,7 @@ mod tests {
 
             match config.get::<Option<Vec<u16>>>("exposes.ports.tcp") {
                 Ok(tcp) => assert!(tcp.is_some()),
-                Err(e) => panic!(format!("error getting tcp: {:?}", e)),
+                Err(e) => panic!("error getting tcp: {:?}", e),
             }
 
             config.try_into()
@@ -642,7 +2639,7 @@ mod tests {
                 println!("Successful simple: {:#?}", offsetup);
                 assert_eq!(offsetup.name, "random python project name")
             }
-            Err(e) => panic!(format!("Failed to get simple configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get simple configuration: {:?}", e),
         }
     }
 
@@ -655,7 +2652,7 @@ mod tests {
 
             match config.get::<Option<Download>>("download") {
                 Ok(download) => assert!(download.is_some(), "couldn't get download"),
-                Err(e) => panic!(format!("error getting download from Source file: {:?}", e)),
+                Err(e) => panic!("error getting download from Source file: {:?}", e),
             }
 
             config.try_into()
@@ -664,11 +2661,16 @@ mod tests {
             Ok(source) => {
                 println!("Successful Source: {:#?}", source);
                 assert_eq!(
-                    source.download.unwrap().uri.hostname.unwrap().to_string(),
+                    source.download.unwrap().as_slice()[0]
+                        .clone()
+                        .uri
+                        .hostname
+                        .unwrap()
+                        .to_string(),
                     "download.redis.io"
                 )
             }
-            Err(e) => panic!(format!("Failed to get Source configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get Source configuration: {:?}", e),
         }
     }
 
@@ -687,22 +2689,19 @@ mod tests {
             Ok(source) => {
                 println!("Successfully loaded valid Source: {:#?}", source);
                 assert_eq!(
-                    source
+                    source.clone().download.unwrap().as_slice()[0]
                         .clone()
-                        .download
-                        .unwrap()
                         .uri
                         .hostname
                         .unwrap()
                         .to_string(),
                     "download.redis.io"
                 );
-                match source.validate() {
-                    Ok(_) => (),
-                    Err(e) => panic!(format!("Valid Source download failed validation: {:?}", e)),
+                if let Err(e) = source.validate() {
+                    panic!("Valid Source download failed validation: {:?}", e)
                 }
             }
-            Err(e) => panic!(format!("Failed to get valid Source configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get valid Source configuration: {:?}", e),
         }
     }
 
@@ -721,33 +2720,26 @@ mod tests {
             Ok(source) => {
                 println!("Successfully loaded invalid Source no1: {:#?}", source);
                 assert_eq!(
-                    source
+                    source.clone().download.unwrap().as_slice()[0]
                         .clone()
-                        .download
-                        .unwrap()
                         .uri
                         .hostname
                         .unwrap()
                         .to_string(),
                     "download.redis.io"
                 );
-                assert_eq!(
-                    true,
+                assert!(
                     source.clone().download_directory.is_none(),
                     "shouldn't find directory"
                 );
-                match source.validate() {
-                    Ok(valid) => panic!(format!(
+                if let Ok(valid) = source.validate() {
+                    panic!(
                         "Invalid Source download is not supposed to pass: {:#?}",
                         valid
-                    )),
-                    Err(_) => (),
+                    )
                 }
             }
-            Err(e) => panic!(format!(
-                "Failed to get invalid Source configuration: {:?}",
-                e
-            )),
+            Err(e) => panic!("Failed to get invalid Source configuration: {:?}", e),
         }
     }
 
@@ -766,30 +2758,708 @@ mod tests {
             Ok(source) => {
                 println!("Successfully loaded invalid Source 2: {:#?}", source);
                 assert_eq!(
-                    source
+                    source.clone().download.unwrap().as_slice()[0]
                         .clone()
-                        .download
-                        .unwrap()
                         .uri
                         .hostname
                         .unwrap()
                         .to_string(),
                     "download.redis.io"
                 );
-                assert_eq!(true, source.clone().download_directory.is_none());
-                match source.validate() {
-                    Ok(valid) => panic!(format!(
+                assert!(source.clone().download_directory.is_none());
+                if let Ok(valid) = source.validate() {
+                    panic!(
                         "Invalid Source download 2 is not supposed to pass: {:#?}",
                         valid
-                    )),
-                    Err(_) => (),
+                    )
                 }
             }
-            Err(e) => panic!(format!(
-                "Failed to get invalid Source 2 configuration: {:?}",
-                e
-            )),
+            Err(e) => panic!("Failed to get invalid Source 2 configuration: {:?}", e),
+        }
+    }
+
+    fn read_strict(name: &str) -> Result<OffSetup, ConfigError> {
+        let path = PathBuf::from("examples").join(name);
+        let mut config = Config::default();
+        config.merge(File::from(path.clone()))?;
+        let source = fs::read_to_string(&path).ok();
+        strict::deserialize(config, &path.to_string_lossy(), source.as_deref())
+    }
+
+    #[test]
+    fn reads_exposes_whatever_the_case_of_ports() {
+        // keys are lowercased when loaded, so `Ports` keeps loading as `ports`
+        for spelling in &["ports", "Ports"] {
+            let yaml = format!(
+                "name: a\nversion: '1'\nexposes:\n  {}:\n    tcp: [80]\n",
+                spelling
+            );
+            let mut config = Config::default();
+            config
+                .merge(File::from_str(&yaml, FileFormat::Yaml))
+                .unwrap();
+            match strict::deserialize::<OffSetup>(config, "offsetup.yml", Some(&yaml)) {
+                Ok(OffSetup {
+                    exposes: Some(Exposes::Ports { tcp, .. }),
+                    ..
+                }) => assert_eq!(Some(80), tcp.map(|ports| ports[0].port)),
+                other => panic!("{} should load: {:?}", spelling, other),
+            }
+        }
+    }
+
+    #[test]
+    fn can_read_example_files_strictly() {
+        for name in &["redis.yml", "simple.yml"] {
+            if let Err(e) = read_strict(name) {
+                panic!("{} should only contain known keys: {}", name, e)
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let message = match read_strict("unknown_keys.yml") {
+            Ok(offsetup) => panic!("unknown keys should be rejected: {:#?}", offsetup),
+            Err(e) => e.to_string(),
+        };
+
+        assert!(message.contains(
+            "unknown key `dependencies.platforms.ubuntu.install_prefix` at examples/unknown_keys.yml:9:7"
+        ));
+        assert!(message.contains(
+            "unknown key `dependencies.platforms.ubuntu.source.download.checksum` at examples/unknown_keys.yml:15:11"
+        ));
+        assert!(message.contains(
+            "unknown key `dependencies.applications.postgresql.features` at examples/unknown_keys.yml:19:7"
+        ));
+        assert!(!message.contains("x-owner"), "x- keys are custom metadata");
+        assert!(!message.contains("x-users"));
+    }
+
+    #[test]
+    fn masks_interpolated_values() {
+        env::set_var("MASKS_INTERPOLATED_DB_PASSWORD", "hunter2");
+        let yaml = "name: app\nversion: '1'\nstart:\n  command: ./app\n  user: app\n  env:\n    - DB_PASSWORD=${MASKS_INTERPOLATED_DB_PASSWORD}\n";
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(yaml, FileFormat::Yaml))
+            .unwrap();
+        let offsetup = load(config, Path::new("offsetup.yml")).unwrap();
+
+        let start = offsetup.start.as_ref().unwrap();
+        assert_eq!(
+            vec![("DB_PASSWORD", "hunter2")],
+            start.variables().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "sudo -u app --preserve-env=DB_PASSWORD sh -c ./app",
+            start.args().join(" "),
+            "values are passed in the environment, not in arguments"
+        );
+        assert!(format!("{:?}", Unredacted(&offsetup)).contains("hunter2"));
+        assert!(!format!("{:?}", offsetup).contains("hunter2"));
+        assert!(!format!("{:#?}", offsetup).contains("hunter2"));
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let mut config = Config::default();
+        config
+            .merge(File::from(PathBuf::from("examples").join("invalid_values")))
+            .unwrap();
+        let offsetup: OffSetup = config.clone().try_into().unwrap();
+        let paths: Vec<String> = validation::validate(&offsetup, &config)
+            .into_iter()
+            .map(|v| v.path)
+            .collect();
+
+        assert_eq!(
+            vec![
+                "dependencies.platforms.ubuntu.versions.1",
+                "dependencies.platforms.ubuntu.system.apt.1",
+                "dependencies.platforms.ubuntu.source",
+                "dependencies.platforms.ubuntu.source.download.sha512",
+                "dependencies.platforms.ubuntu.source.download.uri",
+                "dependencies.platforms.ubuntu.source.install.$ref",
+                "dependencies.applications.redis.version",
+                "exposes.ports.tcp.0",
+                "exposes.ports.tcp.2.bind",
+                "exposes.ports.port_range",
+                "exposes.ports.firewall.sources.0",
+                "exposes.ports.readiness.http.path",
+                "exposes.ports.readiness.http.status",
+            ],
+            paths
+        );
+    }
+
+    #[test]
+    fn can_validate_example_files() {
+        for name in &["redis.yml", "simple.yml", "unknown_keys.yml"] {
+            let mut config = Config::default();
+            config
+                .merge(File::from(PathBuf::from("examples").join(name)))
+                .unwrap();
+            let offsetup: OffSetup = config.clone().try_into().unwrap();
+            assert_eq!(
+                Vec::<validation::Violation>::new(),
+                validation::validate(&offsetup, &config),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn can_load_every_format() {
+        let dir = env::temp_dir().join("offsetup-formats-test");
+        fs::create_dir_all(&dir).unwrap();
+        let redis = format::parse(Path::new("examples/redis.yml")).unwrap();
+        let unknown = format::parse(Path::new("examples/unknown_keys.yml")).unwrap();
+
+        for name in &["redis.toml", "redis.json", "redis.json5"] {
+            let path = dir.join(name);
+            let format = Format::from_path(&path).unwrap();
+            fs::write(&path, format::render(&redis, format).unwrap()).unwrap();
+            let (config, origin) = OffSetup::read(Some(&path), None).unwrap();
+            match load(config, &origin) {
+                Ok(offsetup) => assert_eq!("redis-config", offsetup.name),
+                Err(e) => panic!("{} should load: {}", name, e),
+            }
+
+            let path = dir.join(name.replace("redis", "unknown_keys"));
+            fs::write(&path, format::render(&unknown, format).unwrap()).unwrap();
+            let (config, origin) = OffSetup::read(Some(&path), None).unwrap();
+            let message = load(config, &origin).unwrap_err().to_string();
+            assert!(
+                message.contains("unknown key `dependencies.platforms.ubuntu.install_prefix`"),
+                "{}: {}",
+                name,
+                message
+            );
+        }
+    }
+
+    fn read_system(yaml: &str) -> System {
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(yaml, FileFormat::Yaml))
+            .unwrap();
+        config.try_into().unwrap()
+    }
+
+    #[test]
+    fn can_read_grouped_packages() {
+        let mut config = Config::default();
+        config
+            .merge(File::from(PathBuf::from("examples").join("simple")))
+            .unwrap();
+        let system: System = config.get("dependencies.platforms.ubuntu.system").unwrap();
+        let apt = system.apt.unwrap().resolve();
+
+        assert_eq!(5, apt.len());
+        assert!(apt[..4].iter().all(|p| p.sharable));
+        assert_eq!(
+            SystemPackage {
+                name: "libpq-dev".into(),
+                version: None,
+                sharable: false,
+            },
+            apt[4]
+        );
+
+        let system = read_system(
+            "apt:\n  - make\n  - name: redis-server\n    version: '5:5.0.4-1'\n    sharable: false\nbrew:\n  exclusive: [redis]\n",
+        );
+        assert_eq!(
+            "redis-server=5:5.0.4-1",
+            system.apt.as_ref().unwrap().resolve()[1].spec()
+        );
+        let sharable = |packages: &Packages| -> Vec<bool> {
+            packages.resolve().iter().map(|p| p.sharable).collect()
+        };
+        assert_eq!(vec![true, false], sharable(system.apt.as_ref().unwrap()));
+        assert_eq!(vec![false], sharable(system.brew.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn can_uninstall_keeping_shared_packages() {
+        let system =
+            read_system("apt:\n  sharable: [cmake, curl, make]\n  exclusive: [redis-server]\n");
+        // curl was installed before offsetup ran
+        let installed_before =
+            Inventory::scan(Path::new("examples/scanner/inventory"), &FakeRunner::new()).unwrap();
+        let mut shared = SharedPackages::default();
+        register_shared_packages("redis", &system, &installed_before, &mut shared);
+        shared.register("apt", "cmake", "postgres");
+        assert!(!shared.is_registered("apt", "curl"));
+
+        let removals = plan_uninstall("redis", &system, &mut shared.clone(), false);
+        let names: Vec<&str> = removals.iter().map(|(_, p)| p.name.as_str()).collect();
+        assert_eq!(vec!["redis-server"], names);
+
+        let removals = plan_uninstall("redis", &system, &mut shared, true);
+        let names: Vec<&str> = removals.iter().map(|(_, p)| p.name.as_str()).collect();
+        assert_eq!(
+            vec!["make", "redis-server"],
+            names,
+            "curl is kept, offsetup did not install it"
+        );
+
+        let runner = FakeRunner::new().respond("apt-get remove -y make redis-server", "");
+        uninstall_packages(&removals, &runner).unwrap();
+        assert_eq!(1, runner.calls().len());
+
+        assert!(plan_uninstall("redis", &system, &mut shared, true)
+            .iter()
+            .all(|(_, p)| !p.sharable));
+
+        // a package offsetup installed for another project is shared with it
+        let mut shared = SharedPackages::default();
+        shared.register("apt", "curl", "postgres");
+        register_shared_packages("redis", &system, &installed_before, &mut shared);
+        assert_eq!(Some(1), shared.release("apt", "curl", "redis"));
+    }
+
+    #[test]
+    fn installs_locked_versions() {
+        let system = read_system("apt: [redis-server, gcc]\ndnf: [redis]\n");
+        let mut locked = LockedPlatform::default();
+        locked.lock_package("apt", "redis-server", "5:5.0.4-1");
+        locked.lock_package("apt", "gcc", "4:7.4.0-1ubuntu2.3");
+        locked.lock_package("dnf", "redis", "5.0.4-1.el8");
+        let runner = FakeRunner::new()
+            .respond("apt-get install -y gcc=4:7.4.0-1ubuntu2.3", "")
+            .fail("dnf install -y redis-5.0.4-1.el8", "No match for argument");
+        let inventory =
+            Inventory::scan(Path::new("examples/scanner/inventory"), &FakeRunner::new()).unwrap();
+
+        let message = install_system(&system, &inventory, Some(&locked), &runner)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("No match for argument"), "{}", message);
+        assert_eq!(
+            vec![
+                "apt-get install -y gcc=4:7.4.0-1ubuntu2.3",
+                "dnf install -y redis-5.0.4-1.el8"
+            ],
+            runner.calls(),
+            "redis-server is already installed at its locked version"
+        );
+
+        locked.packages.remove("dnf");
+        let message = install_system(&system, &inventory, Some(&locked), &runner)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("dnf redis is not locked"), "{}", message);
+
+        let system = read_system("brew: [wget]\n");
+        let runner = FakeRunner::new().respond("brew install wget", "");
+        install_system(&system, &inventory, Some(&locked), &runner).unwrap();
+        assert_eq!(vec!["brew install wget"], runner.calls());
+    }
+
+    #[test]
+    fn can_lock_applications() {
+        let yaml = "name: app\nversion: '1'\ndependencies:\n  applications:\n    db:\n      image: postgres:11\n      pkg: https://github.com/offscale/offpostgres\n    cache:\n      image: redis:5\n";
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(yaml, FileFormat::Yaml))
+            .unwrap();
+        let config: OffSetup = strict::deserialize(config, "offsetup.yml", Some(yaml)).unwrap();
+        let runner = FakeRunner::new()
+            .respond(
+                "docker image inspect --format {{index .RepoDigests 0}} postgres:11",
+                "postgres@sha256:3f7a\n",
+            )
+            .respond(
+                "git ls-remote https://github.com/offscale/offpostgres HEAD",
+                "9c1e0f2\tHEAD\n",
+            );
+
+        let locked = lock_platform(
+            &config,
+            &CurrentPlatform::default(),
+            &Inventory::default(),
+            &runner,
+        );
+        assert_eq!(
+            Some(&LockedApplication {
+                image: Some("postgres@sha256:3f7a".into()),
+                commit: Some("9c1e0f2".into()),
+            }),
+            locked.applications.get("db")
+        );
+        assert_eq!(
+            None,
+            locked.applications.get("cache"),
+            "the redis image is not pulled"
+        );
+    }
+
+    #[test]
+    fn reads_platform_services() {
+        let config = read_strict("redis.yml").unwrap();
+        let mut ubuntu = CurrentPlatform::default();
+        ubuntu.name = PlatformName::Ubuntu;
+        let started = service::manager::Desired {
+            enabled: Some(true),
+            state: Some(service::manager::State::Started),
+        };
+        assert_eq!(
+            Some(vec![("redis-server", &started)]),
+            platform_services(&config, &ubuntu)
+        );
+        ubuntu.name = PlatformName::MacOSX;
+        assert_eq!(None, platform_services(&config, &ubuntu));
+    }
+
+    #[test]
+    fn can_lock_installed_versions() {
+        let config = read_strict("redis.yml").unwrap();
+        let mut ubuntu = CurrentPlatform::default();
+        ubuntu.name = PlatformName::Ubuntu;
+        let rpm = "rpm --root examples/scanner/inventory -qa --queryformat %{NAME}\t%{VERSION}-%{RELEASE}\n";
+        let runner = FakeRunner::new().respond(rpm, "gcc\t8.2.1-3\n");
+        let inventory = Inventory::scan(Path::new("examples/scanner/inventory"), &runner).unwrap();
+
+        let locked = lock_platform(&config, &ubuntu, &inventory, &runner);
+        assert_eq!(None, locked.version("apt", "gcc"), "gcc is an rpm package");
+        assert_eq!(
+            None,
+            locked.version("apt", "redis"),
+            "redis is an apk package"
+        );
+        assert_eq!(None, locked.version("apt", "make"), "make is not installed");
+
+        let mut system = Config::default();
+        system
+            .merge(File::from_str(
+                "name: a\nversion: '1'\ndependencies:\n  platforms:\n    ubuntu:\n      versions: ['18.04']\n      system:\n        apt: [redis-server, gcc]\n        dnf: [gcc]\n",
+                FileFormat::Yaml,
+            ))
+            .unwrap();
+        let system: OffSetup = system.try_into().unwrap();
+        let by_database = lock_platform(&system, &ubuntu, &inventory, &runner);
+        assert_eq!(
+            Some("5:5.0.4-1"),
+            by_database.version("apt", "redis-server")
+        );
+        assert_eq!(None, by_database.version("apt", "gcc"));
+        assert_eq!(Some("8.2.1-3"), by_database.version("dnf", "gcc"));
+        assert_eq!(
+            Some(&"336929c81a476e2a23a64f867823d70c3aab66fb0098eef2e61630be6522ff2f6af680169ffcae35d559758b2c6b56f88c5a953a538291fea886449cba33b8ad".to_string()),
+            locked.downloads.get("http://download.redis.io/releases/redis-5.0.4.tar.gz")
+        );
+
+        let source = &config
+            .dependencies
+            .as_ref()
+            .unwrap()
+            .platform(&ubuntu)
+            .unwrap()
+            .source;
+        let downloads = &source.as_ref().unwrap().download;
+        assert_eq!(Ok(()), check_locked_downloads(downloads, &locked));
+
+        let mut locked = LockedPlatform::default();
+        assert!(check_locked_downloads(downloads, &locked)
+            .unwrap_err()
+            .ends_with("is not locked"));
+        locked.downloads.insert(
+            "http://download.redis.io/releases/redis-5.0.4.tar.gz".into(),
+            "0".into(),
+        );
+        assert!(check_locked_downloads(downloads, &locked)
+            .unwrap_err()
+            .contains("changed since it was locked"));
+    }
+
+    #[test]
+    fn can_read_rich_ports() {
+        let mut config = Config::default();
+        config
+            .merge(File::from(PathBuf::from("examples").join("rich_exposes")))
+            .unwrap();
+        let exposes: Exposes = config.try_into().unwrap();
+        let (tcp, udp) = (ports::Protocol::Tcp, ports::Protocol::Udp);
+
+        assert_eq!(
+            vec![
+                (tcp, 80),
+                (tcp, 8000),
+                (tcp, 8001),
+                (tcp, 8002),
+                (tcp, 6379),
+                (tcp, 5432),
+                (tcp, 9100),
+                (udp, 53),
+            ],
+            exposes.ports()
+        );
+        assert_eq!(
+            vec![
+                (tcp, 80),
+                (tcp, 8000),
+                (tcp, 8001),
+                (tcp, 8002),
+                (tcp, 6379),
+                (udp, 53),
+            ],
+            exposes.public_ports(),
+            "loopback and internal ports stay closed"
+        );
+        let names: Vec<Option<&str>> = exposes
+            .entries()
+            .iter()
+            .map(|(_, port)| port.name.as_deref())
+            .collect();
+        assert_eq!(
+            vec![
+                None,
+                None,
+                Some("redis"),
+                Some("postgres"),
+                Some("metrics"),
+                Some("dns")
+            ],
+            names
+        );
+        assert_eq!(
+            vec![readiness::Probe::Http {
+                host: "127.0.0.1".into(),
+                port: 5432,
+                path: "/health".into(),
+                status: 200
+            },],
+            exposes.probes()
+        );
+
+        let Exposes::Ports { unix, .. } = &exposes;
+        let sockets: Vec<(Option<&str>, &str)> = unix
+            .iter()
+            .flatten()
+            .map(|socket| (socket.name.as_deref(), socket.path.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (None, "/run/app.sock"),
+                (Some("admin"), "/run/app-admin.sock")
+            ],
+            sockets
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_port_keys() {
+        for (yaml, message) in &[
+            ("tcp: [{port: 80, bnd: 127.0.0.1}]", "unknown field `bnd`"),
+            ("tcp: [{port: 80, x-note: web}]", "unknown field `x-note`"),
+            ("unix: [{path: /x, x-note: web}]", "unknown field `x-note`"),
+            ("tcp: [{a: 80, b: 81}]", "one named port"),
+            ("tcp: [80-x]", "invalid port \"80-x\""),
+            ("tcp: [70000]", "above 65535"),
+            ("unix: [{a: /x, b: /y}]", "one named path"),
+            ("unix: [{a: 80}]", "one named path"),
+        ] {
+            let mut config = Config::default();
+            config
+                .merge(File::from_str(
+                    &format!("ports:\n  {}\n", yaml),
+                    FileFormat::Yaml,
+                ))
+                .unwrap();
+            let error = config.try_into::<Exposes>().unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", yaml, error);
+        }
+    }
+
+    #[test]
+    fn imported_dockerfile_is_valid() {
+        let content = fs::read_to_string("examples/docker/Dockerfile.ubuntu").unwrap();
+        let translation =
+            docker::dockerfile::translate(&docker::dockerfile::parse(&content), "example");
+        let mut imported = translation.config;
+        // the checksums are left for the user to fill in
+        for download in imported["dependencies"]["platforms"]["ubuntu"]["download"]
+            .as_array_mut()
+            .unwrap()
+        {
+            download["sha512"] = "0".repeat(128).into();
         }
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(&imported.to_string(), FileFormat::Json))
+            .unwrap();
+        let offsetup: OffSetup = config.clone().try_into().unwrap();
+        assert_eq!(
+            Vec::<validation::Violation>::new(),
+            validation::validate(&offsetup, &config)
+        );
+        assert_eq!(
+            vec![
+                "sudo",
+                "-u",
+                "app",
+                "--preserve-env=APP_ENV,APP_HOME",
+                "dumb-init",
+                "--",
+                "./app",
+                "--port",
+                "8080"
+            ],
+            offsetup.start.unwrap().args()
+        );
+    }
+
+    #[test]
+    fn imported_compose_is_valid() {
+        let compose = format::parse(Path::new("examples/docker/docker-compose.yml")).unwrap();
+        let imported = docker::compose::translate(&compose, "example").config;
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(&imported.to_string(), FileFormat::Json))
+            .unwrap();
+        let offsetup: OffSetup = config.clone().try_into().unwrap();
+        assert_eq!(
+            Vec::<validation::Violation>::new(),
+            validation::validate(&offsetup, &config)
+        );
+        assert_eq!(
+            Ok(vec!["cache", "db", "worker"]),
+            offsetup.dependencies.unwrap().application_order()
+        );
+    }
+
+    #[test]
+    fn reports_application_dependency_cycles() {
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(
+                "name: cycle\nversion: '1.0'\ndependencies:\n  applications:\n    api:\n      depends_on: [db, queue]\n    db:\n      depends_on: [api]\n    queue: {}\n",
+                FileFormat::Yaml,
+            ))
+            .unwrap();
+        let offsetup: OffSetup = config.clone().try_into().unwrap();
+        assert_eq!(
+            Err(vec!["api", "db", "api"]),
+            offsetup.dependencies.as_ref().unwrap().application_order()
+        );
+        assert_eq!(
+            vec!["dependencies.applications.api.depends_on: depends on itself: api -> db -> api"],
+            validation::validate(&offsetup, &config)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn builds_the_project_service() {
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(
+                "name: My Project\nversion: '1.0'\nstart:\n  command: ./serve --port 80\n  env: [MODE=production]\n  restart: always\ndependencies:\n  applications:\n    api:\n      depends_on: [db]\n    db:\n      env: DATABASE_URL\n      uri: postgresql://localhost/app\n      install_priority: [docker, native]\n    cache:\n      install_priority: [docker]\n",
+                FileFormat::Yaml,
+            ))
+            .unwrap();
+        let offsetup: OffSetup = config.try_into().unwrap();
+        assert_eq!(
+            Some(service::Service {
+                name: "my-project".into(),
+                description: "My Project 1.0".into(),
+                args: vec!["./serve --port 80".into()],
+                shell: true,
+                working_directory: None,
+                environment: vec![
+                    ("MODE".into(), "production".into()),
+                    ("DATABASE_URL".into(), "postgresql://localhost/app".into())
+                ],
+                user: None,
+                restart: service::Restart::Always,
+                after: vec!["db".into(), "api".into()],
+            }),
+            project_service(&offsetup, &CurrentPlatform::default()),
+            "after the applications installed natively, in their order"
+        );
+
+        let mut config = Config::default();
+        config
+            .merge(File::from_str(
+                "name: app\nversion: '1'\nstart:\n  command: ./app\ndependencies:\n  platforms:\n    ubuntu:\n      versions: ['18.04']\n      services:\n        redis-server: {state: started}\n        cron: {enabled: false}\n",
+                FileFormat::Yaml,
+            ))
+            .unwrap();
+        let offsetup: OffSetup = config.try_into().unwrap();
+        let mut ubuntu = CurrentPlatform::default();
+        ubuntu.name = PlatformName::Ubuntu;
+        assert_eq!(
+            vec!["redis-server".to_string()],
+            project_service(&offsetup, &ubuntu).unwrap().after,
+            "ordered after the services the platform starts"
+        );
+    }
+
+    #[test]
+    fn probes_declared_ports() {
+        let config = read_strict("simple.yml").unwrap();
+        let exposes = config.exposes.unwrap();
+        assert_eq!(
+            vec![
+                readiness::Probe::Tcp {
+                    host: "localhost".into(),
+                    port: 80
+                },
+                readiness::Probe::Tcp {
+                    host: "localhost".into(),
+                    port: 443
+                },
+                readiness::Probe::Http {
+                    host: "localhost".into(),
+                    port: 80,
+                    path: "/health".into(),
+                    status: 200
+                },
+            ],
+            exposes.probes()
+        );
+        let policy = exposes.readiness_policy();
+        assert_eq!(Duration::from_secs(2), policy.timeout);
+        assert_eq!(10, policy.retries);
+        assert_eq!(Duration::from_secs(1), policy.interval);
+    }
+
+    #[test]
+    fn can_create_new_applications() {
+        let applications = new_applications("examples/scanner/manifest/rust");
+
+        assert_eq!(1, applications.len());
+        assert_eq!(
+            Some(">=1.56".to_string()),
+            applications.get("rust").unwrap().version
+        );
+        assert!(new_applications("examples/scanner/new/go").is_empty());
+    }
+
+    #[test]
+    fn can_create_new_system_packages() {
+        let mut packages =
+            new_system_packages("examples/scanner/new/mixed_go_rust", &PlatformName::Ubuntu);
+        packages.sort();
+
+        assert_eq!(vec!["cargo", "golang-go", "rustc"], packages);
+        assert!(new_system_packages("examples/scanner/new/go", &PlatformName::Unknown).is_empty());
+        assert_eq!(
+            vec!["python3", "python3-pip", "python3-venv"],
+            new_system_packages("examples/scanner/new/python_docs", &PlatformName::Ubuntu),
+            "the .js of the docs needs no nodejs"
+        );
+
+        let packages = new_system_packages("examples/scanner/native", &PlatformName::Arch);
+        assert!(packages.contains(&"python".to_string()));
+        assert!(packages.contains(&"postgresql-libs".to_string()));
+        assert!(packages.contains(&"sqlite".to_string()));
     }
 
     #[test]
@@ -801,14 +3471,14 @@ mod tests {
 
             match config.get::<Option<Vec<String>>>("apt") {
                 Ok(tcp) => assert!(tcp.is_some()),
-                Err(e) => panic!(format!("error getting apt from system file: {:?}", e)),
+                Err(e) => panic!("error getting apt from system file: {:?}", e),
             }
 
             config.try_into()
         };
         match get_system() {
             Ok(system) => println!("Successful system: {:#?}", system),
-            Err(e) => panic!(format!("Failed to get system configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get system configuration: {:?}", e),
         }
     }
 
@@ -825,14 +3495,14 @@ mod tests {
                     println!("{:?}: {:?}", key, apt);
                     assert!(apt.is_some())
                 }
-                Err(e) => panic!(format!("error getting apt from platform file: {:?}", e)),
+                Err(e) => panic!("error getting apt from platform file: {:?}", e),
             }
 
             config.try_into()
         };
         match get_platform() {
             Ok(platform) => println!("Successful platform: {:#?}", platform),
-            Err(e) => panic!(format!("Failed to get platform configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get platform configuration: {:?}", e),
         }
     }
 
@@ -843,20 +3513,20 @@ mod tests {
             config.merge(File::from(PathBuf::from("examples").join("dependencies")))?;
             println!("merged: {:#?}", config);
 
-            const KEY: &'static str = "platforms.ubuntu.system.apt";
+            const KEY: &str = "platforms.ubuntu.system.apt";
             match config.get::<Option<Vec<String>>>(KEY) {
                 Ok(apt) => {
                     println!("{:?}: {:?}", KEY, apt);
                     assert!(apt.is_some())
                 }
-                Err(e) => panic!(format!("error getting apt from dependencies: {:?}", e)),
+                Err(e) => panic!("error getting apt from dependencies: {:?}", e),
             }
 
             config.try_into()
         };
         match get_dependencies() {
             Ok(dependencies) => println!("Successful dependencies: {:#?}", dependencies),
-            Err(e) => panic!(format!("Failed to get dependencies configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get dependencies configuration: {:?}", e),
         }
     }
 
@@ -878,12 +3548,12 @@ mod tests {
                 assert!(windows.arch.is_some());
                 assert_eq!(windows.arch.unwrap(), "x86_64")
             }
-            Err(e) => panic!(format!("error getting windows platform: {:?}", e)),
+            Err(e) => panic!("error getting windows platform: {:?}", e),
         }
 
         match config.get::<Option<Vec<u16>>>("exposes.ports.tcp") {
             Ok(tcp) => assert!(tcp.is_some()),
-            Err(e) => panic!(format!("error getting tcp: {:?}", e)),
+            Err(e) => panic!("error getting tcp: {:?}", e),
         }
 
         match config.try_into() as Result<OffSetup, ConfigError> {
@@ -896,8 +3566,7 @@ mod tests {
                     .unwrap()
                     .platforms
                     .unwrap()
-                    .get("windows")
-                    .is_some());
+                    .contains_key("windows"));
                 assert!(offsetup
                     .dependencies
                     .unwrap()
@@ -911,7 +3580,7 @@ mod tests {
                     .apt
                     .is_some());
             }
-            Err(e) => panic!(format!("Failed to get redis configuration: {:?}", e)),
+            Err(e) => panic!("Failed to get redis configuration: {:?}", e),
         }
     }
 }
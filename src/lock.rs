@@ -0,0 +1,126 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the lockfile, written next to the config file
+pub const LOCKFILE: &str = "offsetup.lock";
+
+/// What a successful install resolved, so that `install --locked` installs exactly the same
+/// package versions and files on every host
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Lockfile {
+    /// By platform name, eg: ubuntu
+    #[serde(default)]
+    pub platforms: BTreeMap<String, LockedPlatform>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LockedPlatform {
+    /// Installed versions, by package manager then package name
+    #[serde(default)]
+    pub packages: BTreeMap<String, BTreeMap<String, String>>,
+    /// SHA-512 checksums of the downloaded files, by URI
+    #[serde(default)]
+    pub downloads: BTreeMap<String, String>,
+    /// What the applications resolved to, by application name
+    #[serde(default)]
+    pub applications: BTreeMap<String, LockedApplication>,
+}
+
+/// The artifacts an application resolved to when it was locked
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LockedApplication {
+    /// Its image by digest, eg: postgres@sha256:3f7a...
+    pub image: Option<String>,
+    /// The commit its `pkg` repository was at
+    pub commit: Option<String>,
+}
+
+impl Lockfile {
+    /// `offsetup.lock` next to the config file at `config_file`
+    pub fn path(config_file: &Path) -> PathBuf {
+        config_file
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(LOCKFILE)
+    }
+
+    /// Read the lockfile at `path`, empty when nothing was installed yet
+    pub fn load(path: &Path) -> io::Result<Lockfile> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Lockfile::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(
+            path,
+            format!(
+                "# Written by `offsetup install`, read by `offsetup install --locked`\n{}",
+                content
+            ),
+        )
+    }
+}
+
+impl LockedPlatform {
+    pub fn lock_package(&mut self, manager: &str, package: &str, version: &str) {
+        self.packages
+            .entry(manager.into())
+            .or_default()
+            .insert(package.into(), version.into());
+    }
+
+    pub fn version(&self, manager: &str, package: &str) -> Option<&str> {
+        self.packages.get(manager)?.get(package).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn can_save_and_load() {
+        let path = env::temp_dir().join("offsetup-lock-test").join(LOCKFILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(Lockfile::default(), Lockfile::load(&path).unwrap());
+
+        let mut ubuntu = LockedPlatform::default();
+        ubuntu.lock_package("apt", "redis", "5:5.0.4-1");
+        ubuntu.downloads.insert(
+            "http://download.redis.io/releases/redis-5.0.4.tar.gz".into(),
+            "3369".into(),
+        );
+        ubuntu.applications.insert(
+            "postgresql".into(),
+            LockedApplication {
+                image: Some("postgres@sha256:3f7a".into()),
+                commit: None,
+            },
+        );
+        let mut lock = Lockfile::default();
+        lock.platforms.insert("ubuntu".into(), ubuntu);
+        lock.save(&path).unwrap();
+
+        let loaded = Lockfile::load(&path).unwrap();
+        assert_eq!(lock, loaded);
+        assert_eq!(
+            Some("5:5.0.4-1"),
+            loaded.platforms["ubuntu"].version("apt", "redis")
+        );
+        assert_eq!(None, loaded.platforms["ubuntu"].version("apt", "gcc"));
+    }
+}
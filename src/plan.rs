@@ -0,0 +1,93 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::runner::{CommandOutput, CommandRunner};
+
+/// A command of a plan
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub args: Vec<String>,
+    /// Undoes a previous step, so failing means there was nothing to undo
+    pub may_fail: bool,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.args.join(" "))
+    }
+}
+
+/// Files to write, then commands to run, to change the host
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Plan {
+    pub files: Vec<(PathBuf, String)>,
+    /// Written readable by their owner only, as they hold secrets
+    pub private_files: Vec<(PathBuf, String)>,
+    pub steps: Vec<Step>,
+}
+
+/// Write the files of `plan`, then run its steps, stopping at the first one failing
+pub fn run(plan: &Plan, runner: &dyn CommandRunner) -> io::Result<()> {
+    for (path, content) in &plan.files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    for (path, content) in &plan.private_files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(path, content)?;
+    }
+    for step in &plan.steps {
+        let args: Vec<&str> = step.args[1..].iter().map(String::as_str).collect();
+        let output = runner.run(&step.args[0], &args);
+        match output {
+            Ok(CommandOutput { success: true, .. }) => {}
+            _ if step.may_fail => {}
+            Ok(output) => {
+                return Err(io::Error::other(format!(
+                    "`{}` failed: {}",
+                    step,
+                    output.stderr.trim()
+                )))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Write `content` to `path` with mode 0600, before any of it is written
+#[cfg(unix)]
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    use std::{
+        io::Write,
+        os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    };
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // the mode only applies to new files
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    fs::write(path, content)
+}
+
+pub fn step(args: &[&str], may_fail: bool) -> Step {
+    Step {
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        may_fail,
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fmt, fs, io,
+    net::{TcpListener, UdpSocket},
+    path::Path,
+};
+
+/// `st` of listening TCP sockets in `/proc/net/tcp`
+const TCP_LISTEN: &str = "0A";
+/// `st` of bound UDP sockets in `/proc/net/udp`
+const UDP_UNCONNECTED: &str = "07";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        })
+    }
+}
+
+/// Whether a port can be bound
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Availability {
+    Free,
+    /// Another socket is bound to it
+    InUse,
+    /// Binding it needs privileges the current user lacks, eg: ports below 1024 without root
+    Denied,
+}
+
+/// A process with a socket open
+#[derive(Clone, Debug, PartialEq)]
+pub struct Owner {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// A declared port that cannot be bound on the host
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub protocol: Protocol,
+    pub port: u16,
+    /// `InUse` or `Denied`
+    pub availability: Availability,
+    /// The process bound to it, when it can be found
+    pub owner: Option<Owner>,
+    /// A free port of `port_range` to use instead
+    pub alternative: Option<u16>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.availability {
+            Availability::Denied => write!(
+                f,
+                "{} port {} cannot be bound without privileges",
+                self.protocol, self.port
+            )?,
+            _ => write!(f, "{} port {} is already in use", self.protocol, self.port)?,
+        }
+        if let Some(owner) = &self.owner {
+            write!(f, " by {} (pid {})", owner.name, owner.pid)?;
+        }
+        match self.alternative {
+            Some(port) => write!(f, ", {} is free in port_range", port),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether `port` can be bound on every interface. Errors other than the address being in use or
+/// the permission being denied are not the port's doing, so it counts as free.
+pub fn availability(protocol: Protocol, port: u16) -> Availability {
+    let bound = match protocol {
+        Protocol::Tcp => TcpListener::bind(("0.0.0.0", port)).map(drop),
+        Protocol::Udp => UdpSocket::bind(("0.0.0.0", port)).map(drop),
+    };
+    match bound.map_err(|e| e.kind()) {
+        Err(io::ErrorKind::AddrInUse) => Availability::InUse,
+        Err(io::ErrorKind::PermissionDenied) => Availability::Denied,
+        _ => Availability::Free,
+    }
+}
+
+/// Check that each of `ports` can be bound, using `availability`. Owners of bound ports are looked
+/// up in `proc_root` (usually `/proc`), and an alternative is suggested from `range` when given.
+pub fn preflight(
+    ports: &[(Protocol, u16)],
+    range: Option<(u16, u16)>,
+    availability: &dyn Fn(Protocol, u16) -> Availability,
+    proc_root: &Path,
+) -> Vec<Conflict> {
+    let mut suggested: Vec<(Protocol, u16)> = vec![];
+    let mut conflicts = vec![];
+    let is_free = |protocol, port| availability(protocol, port) == Availability::Free;
+    for &(protocol, port) in ports {
+        let status = availability(protocol, port);
+        if status == Availability::Free {
+            continue;
+        }
+        let alternative = range.and_then(|(start, end)| {
+            (start..=end).find(|candidate| {
+                let candidate = (protocol, *candidate);
+                !ports.contains(&candidate)
+                    && !suggested.contains(&candidate)
+                    && is_free(protocol, candidate.1)
+            })
+        });
+        if let Some(alternative) = alternative {
+            suggested.push((protocol, alternative));
+        }
+        conflicts.push(Conflict {
+            protocol,
+            port,
+            availability: status,
+            owner: match status {
+                Availability::InUse => owner(proc_root, protocol, port),
+                _ => None,
+            },
+            alternative,
+        });
+    }
+    conflicts
+}
+
+/// Local ports and socket inodes of the bound sockets listed in a `/proc/net/{tcp,udp}{,6}` table
+pub fn parse_proc_net(content: &str, protocol: Protocol) -> Vec<(u16, u64)> {
+    let bound_state = match protocol {
+        Protocol::Tcp => TCP_LISTEN,
+        Protocol::Udp => UDP_UNCONNECTED,
+    };
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != bound_state {
+                return None;
+            }
+            let port = fields[1].rsplit(':').next()?;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let inode = fields[9].parse().ok()?;
+            Some((port, inode))
+        })
+        .collect()
+}
+
+/// The process bound to `port`, from the socket tables and file descriptors under `proc_root`
+pub fn owner(proc_root: &Path, protocol: Protocol, port: u16) -> Option<Owner> {
+    let inodes: Vec<u64> = ["", "6"]
+        .iter()
+        .filter_map(|suffix| {
+            fs::read_to_string(
+                proc_root
+                    .join("net")
+                    .join(format!("{}{}", protocol, suffix)),
+            )
+            .ok()
+        })
+        .flat_map(|content| parse_proc_net(&content, protocol))
+        .filter(|(bound, _)| *bound == port)
+        .map(|(_, inode)| inode)
+        .collect();
+    if inodes.is_empty() {
+        return None;
+    }
+
+    let sockets: Vec<String> = inodes
+        .iter()
+        .map(|inode| format!("socket:[{}]", inode))
+        .collect();
+    for process in fs::read_dir(proc_root).ok()?.flatten() {
+        let pid = match process
+            .file_name()
+            .to_str()
+            .and_then(|pid| pid.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        // other users' descriptors are unreadable without privileges
+        let fds = match fs::read_dir(process.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        let owns = fds.flatten().any(|fd| {
+            fs::read_link(fd.path())
+                .map(|target| sockets.iter().any(|socket| target == Path::new(socket)))
+                .unwrap_or(false)
+        });
+        if owns {
+            let name = fs::read_to_string(process.path().join("comm")).unwrap_or_default();
+            return Some(Owner {
+                pid,
+                name: name.trim().into(),
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const PROC_NET_TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 41523 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:0CEA 00000000:0000 0A 00000000:00000000 00:00000000 00000000   999        0 38211 1 0000000000000000 100 0 0 10 0
+   2: 0F02000A:A0F2 8EFB1AAC:01BB 01 00000000:00000000 02:000007A6 00000000  1000        0 52718 2 0000000000000000 20 4 30 10 -1
+";
+
+    #[test]
+    fn can_parse_proc_net() {
+        assert_eq!(
+            vec![(8080, 41523), (3306, 38211)],
+            parse_proc_net(PROC_NET_TCP, Protocol::Tcp)
+        );
+        assert!(
+            parse_proc_net(PROC_NET_TCP, Protocol::Udp).is_empty(),
+            "only bound sockets"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn can_find_port_owners() {
+        let proc_root = env::temp_dir().join("offsetup-proc-test");
+        let _ = fs::remove_dir_all(&proc_root);
+        fs::create_dir_all(proc_root.join("net")).unwrap();
+        fs::create_dir_all(proc_root.join("4242").join("fd")).unwrap();
+        fs::write(proc_root.join("net").join("tcp"), PROC_NET_TCP).unwrap();
+        fs::write(proc_root.join("4242").join("comm"), "nginx\n").unwrap();
+        std::os::unix::fs::symlink("socket:[41523]", proc_root.join("4242/fd/3")).unwrap();
+
+        assert_eq!(
+            Some(Owner {
+                pid: 4242,
+                name: "nginx".into()
+            }),
+            owner(&proc_root, Protocol::Tcp, 8080)
+        );
+        assert_eq!(None, owner(&proc_root, Protocol::Tcp, 3306));
+        assert_eq!(None, owner(&proc_root, Protocol::Udp, 8080));
+    }
+
+    #[test]
+    fn reports_conflicts_with_alternatives() {
+        let bound = [
+            (Protocol::Tcp, 80),
+            (Protocol::Tcp, 8000),
+            (Protocol::Tcp, 443),
+        ];
+        let availability = |protocol, port| match (protocol, port) {
+            (Protocol::Tcp, 8002) => Availability::Denied,
+            port if bound.contains(&port) => Availability::InUse,
+            _ => Availability::Free,
+        };
+        let ports = [
+            (Protocol::Tcp, 80),
+            (Protocol::Tcp, 443),
+            (Protocol::Udp, 80),
+        ];
+
+        let conflicts = preflight(
+            &ports,
+            Some((8000, 8010)),
+            &availability,
+            Path::new("/nonexistent"),
+        );
+        assert_eq!(
+            vec![(80, Some(8001)), (443, Some(8003))],
+            conflicts
+                .iter()
+                .map(|c| (c.port, c.alternative))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "tcp port 80 is already in use, 8001 is free in port_range",
+            conflicts[0].to_string()
+        );
+        assert_eq!(
+            None,
+            preflight(&ports, None, &availability, Path::new("/nonexistent"))[0].alternative
+        );
+
+        let conflicts = preflight(
+            &[(Protocol::Tcp, 8002)],
+            None,
+            &availability,
+            Path::new("/nonexistent"),
+        );
+        assert_eq!(
+            "tcp port 8002 cannot be bound without privileges",
+            conflicts[0].to_string()
+        );
+    }
+
+    #[test]
+    fn bound_ports_are_not_free() {
+        let listener = TcpListener::bind(("0.0.0.0", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert_eq!(Availability::InUse, availability(Protocol::Tcp, port));
+        drop(listener);
+        assert_eq!(Availability::Free, availability(Protocol::Tcp, port));
+    }
+}
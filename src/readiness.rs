@@ -0,0 +1,315 @@
+use std::{
+    fmt,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use crate::runner::CommandRunner;
+
+/// A check that the started project is ready to serve
+#[derive(Clone, Debug, PartialEq)]
+pub enum Probe {
+    /// Accepts TCP connections on the port
+    Tcp { host: String, port: u16 },
+    /// Answers a GET of `path` on the port with `status`
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+        status: u16,
+    },
+    /// Accepts connections on the unix domain socket
+    Unix { path: PathBuf },
+    /// The command exits successfully
+    Command(String),
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Probe::Tcp { host, port } => write!(f, "tcp connect to {}:{}", host, port),
+            Probe::Http {
+                host,
+                port,
+                path,
+                status,
+            } => write!(f, "http GET {}:{}{} returning {}", host, port, path, status),
+            Probe::Unix { path } => write!(f, "unix connect to {}", path.display()),
+            Probe::Command(command) => write!(f, "command `{}`", command),
+        }
+    }
+}
+
+/// How long to keep probing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Policy {
+    /// Of each attempt
+    pub timeout: Duration,
+    /// Attempts after the first one
+    pub retries: u32,
+    /// Between attempts
+    pub interval: Duration,
+}
+
+/// A probe still failing once every attempt was made
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub probe: Probe,
+    pub attempts: u32,
+    /// Why the last attempt failed
+    pub error: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} failed after {} attempts: {}",
+            self.probe, self.attempts, self.error
+        )
+    }
+}
+
+/// Block until every probe passed, probing in order. Stops at the first probe still failing once
+/// its retries are exhausted.
+pub fn wait(probes: &[Probe], policy: Policy, runner: &dyn CommandRunner) -> Result<(), Failure> {
+    for probe in probes {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match check(probe, policy.timeout, runner) {
+                Ok(()) => break,
+                Err(error) if attempts > policy.retries => {
+                    return Err(Failure {
+                        probe: probe.clone(),
+                        attempts,
+                        error,
+                    })
+                }
+                Err(_) => thread::sleep(policy.interval),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Probe once
+pub fn check(probe: &Probe, timeout: Duration, runner: &dyn CommandRunner) -> Result<(), String> {
+    match probe {
+        Probe::Tcp { host, port } => connect(host, *port, timeout).map(|_| ()),
+        Probe::Http {
+            host,
+            port,
+            path,
+            status,
+        } => {
+            let actual = http_status(host, *port, path, timeout)?;
+            if actual == *status {
+                Ok(())
+            } else {
+                Err(format!("got status {}", actual))
+            }
+        }
+        Probe::Unix { path } => connect_unix(path),
+        Probe::Command(command) => {
+            let (shell, flag) = if cfg!(windows) {
+                ("cmd", "/C")
+            } else {
+                ("sh", "-c")
+            };
+            let output = runner
+                .run_with_timeout(shell, &[flag, command], timeout)
+                .map_err(|e| e.to_string())?;
+            if output.success {
+                Ok(())
+            } else {
+                Err(format!("exited unsuccessfully: {}", output.stderr.trim()))
+            }
+        }
+    }
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, String> {
+    let addresses: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .collect();
+    let mut error = format!("{} does not resolve", host);
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = e.to_string(),
+        }
+    }
+    Err(error)
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &std::path::Path) -> Result<(), String> {
+    std::os::unix::net::UnixStream::connect(path)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &std::path::Path) -> Result<(), String> {
+    Err("unix domain sockets are not supported on this platform".into())
+}
+
+/// Status code of the response to a GET of `path`
+fn http_status(host: &str, port: u16, path: &str, timeout: Duration) -> Result<u16, String> {
+    let mut stream = connect(host, port, timeout)?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| e.to_string())?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    // the status line is all that is needed, eg: `HTTP/1.1 200 OK`
+    let mut response = vec![];
+    let mut buffer = [0; 256];
+    while !response.contains(&b'\n') {
+        match stream.read(&mut buffer).map_err(|e| e.to_string())? {
+            0 => break,
+            read => response.extend_from_slice(&buffer[..read]),
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    response
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| format!("invalid HTTP response {:?}", response.lines().next()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::fake::FakeRunner;
+    use std::net::TcpListener;
+
+    const POLICY: Policy = Policy {
+        timeout: Duration::from_millis(500),
+        retries: 2,
+        interval: Duration::from_millis(10),
+    };
+
+    /// A local server answering every request with `status_line`
+    fn serve(status_line: &'static str) -> u16 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 512];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let _ = write!(stream, "{}\r\nContent-Length: 0\r\n\r\n", status_line);
+            }
+        });
+        port
+    }
+
+    fn closed_port() -> u16 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    fn tcp(port: u16) -> Probe {
+        Probe::Tcp {
+            host: "127.0.0.1".into(),
+            port,
+        }
+    }
+
+    fn http(port: u16, path: &str, status: u16) -> Probe {
+        Probe::Http {
+            host: "127.0.0.1".into(),
+            port,
+            path: path.into(),
+            status,
+        }
+    }
+
+    #[test]
+    fn passes_when_ready() {
+        let port = serve("HTTP/1.1 204 No Content");
+        let runner = FakeRunner::new().respond("sh -c redis-cli ping", "PONG");
+        let probes = vec![tcp(port), http(port, "/health", 204)];
+
+        assert_eq!(Ok(()), wait(&probes, POLICY, &runner));
+        if cfg!(unix) {
+            let command = [Probe::Command("redis-cli ping".into())];
+            assert_eq!(Ok(()), wait(&command, POLICY, &runner));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn connects_to_unix_sockets() {
+        let path = std::env::temp_dir().join("offsetup-readiness-test.sock");
+        let _ = std::fs::remove_file(&path);
+        let probe = Probe::Unix { path: path.clone() };
+        assert!(check(&probe, POLICY.timeout, &FakeRunner::new()).is_err());
+
+        let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        assert_eq!(Ok(()), check(&probe, POLICY.timeout, &FakeRunner::new()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_time_out() {
+        let probe = Probe::Command("sleep 10".into());
+        let started = std::time::Instant::now();
+        let error = check(
+            &probe,
+            Duration::from_millis(200),
+            &crate::runner::SystemRunner,
+        )
+        .unwrap_err();
+        assert!(error.contains("timed out"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let probe = Probe::Command("echo ready".into());
+        assert_eq!(
+            Ok(()),
+            check(&probe, Duration::from_secs(5), &crate::runner::SystemRunner)
+        );
+    }
+
+    #[test]
+    fn reports_the_failing_probe() {
+        let port = serve("HTTP/1.1 503 Service Unavailable");
+        let closed = closed_port();
+        let probes = vec![tcp(port), http(port, "/", 200), tcp(closed)];
+
+        let failure = wait(&probes, POLICY, &FakeRunner::new()).unwrap_err();
+        assert_eq!(probes[1], failure.probe);
+        assert_eq!(3, failure.attempts);
+        assert_eq!(
+            format!(
+                "http GET 127.0.0.1:{}/ returning 200 failed after 3 attempts: got status 503",
+                port
+            ),
+            failure.to_string()
+        );
+
+        let failure = check(&probes[2], POLICY.timeout, &FakeRunner::new());
+        assert!(failure.is_err(), "nothing listens on port {}", closed);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    io::{self, Read},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often `run_with_timeout` checks whether the program exited
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Captured result of running an external program
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// CommandRunner spawns external programs (package managers, service managers, ...).
+/// Code that shells out goes through it so it can be exercised in tests without touching the host.
+pub trait CommandRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput>;
+
+    /// Like `run`, but kills the program and fails with `TimedOut` once `timeout` elapsed.
+    /// Runners whose programs never block, like test fakes, need not override it.
+    fn run_with_timeout(
+        &self,
+        program: &str,
+        args: &[&str],
+        _timeout: Duration,
+    ) -> io::Result<CommandOutput> {
+        self.run(program, args)
+    }
+}
+
+/// Runs programs on the current host
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+        let output = Command::new(program).args(args).output()?;
+        Ok(CommandOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    fn run_with_timeout(
+        &self,
+        program: &str,
+        args: &[&str],
+        timeout: Duration,
+    ) -> io::Result<CommandOutput> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // read while waiting, so that a program filling a pipe does not block on it
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("`{}` timed out after {:?}", program, timeout),
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        Ok(CommandOutput {
+            success: status.success(),
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut content = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut content);
+        }
+        String::from_utf8_lossy(&content).into_owned()
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::{collections::HashMap, io, sync::Mutex};
+
+    use super::{CommandOutput, CommandRunner};
+
+    /// Replays canned output keyed by the full command line and records every call made.
+    /// Programs without a canned response behave as if they were not installed.
+    #[derive(Default)]
+    pub struct FakeRunner {
+        responses: HashMap<String, CommandOutput>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl FakeRunner {
+        pub fn new() -> FakeRunner {
+            FakeRunner::default()
+        }
+
+        pub fn respond(mut self, command_line: &str, stdout: &str) -> FakeRunner {
+            self.responses.insert(
+                command_line.into(),
+                CommandOutput {
+                    success: true,
+                    stdout: stdout.into(),
+                    stderr: String::new(),
+                },
+            );
+            self
+        }
+
+        pub fn fail(mut self, command_line: &str, stderr: &str) -> FakeRunner {
+            self.responses.insert(
+                command_line.into(),
+                CommandOutput {
+                    success: false,
+                    stdout: String::new(),
+                    stderr: stderr.into(),
+                },
+            );
+            self
+        }
+
+        pub fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+            let command_line = std::iter::once(program)
+                .chain(args.iter().cloned())
+                .collect::<Vec<&str>>()
+                .join(" ");
+            self.calls.lock().unwrap().push(command_line.clone());
+            self.responses.get(&command_line).cloned().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no such program: {}", program),
+                )
+            })
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::runner::CommandRunner;
+
+const DPKG_STATUS: &str = "var/lib/dpkg/status";
+const PACMAN_LOCAL: &str = "var/lib/pacman/local";
+const APK_INSTALLED: &str = "lib/apk/db/installed";
+const RPM_QUERY_FORMAT: &str = "%{NAME}\t%{VERSION}-%{RELEASE}\n";
+
+/// Native package database an installed package was found in
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PackageDatabase {
+    Apk,
+    Dpkg,
+    Pacman,
+    Rpm,
+}
+
+impl PackageDatabase {
+    /// The database the packages of `manager` are installed in, None when it is not scanned,
+    /// eg: brew
+    pub fn of(manager: &str) -> Option<PackageDatabase> {
+        match manager {
+            "apt" | "apt_get" | "aptitude" => Some(PackageDatabase::Dpkg),
+            "yum" | "dnf" => Some(PackageDatabase::Rpm),
+            "apk" => Some(PackageDatabase::Apk),
+            "pacman" => Some(PackageDatabase::Pacman),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    pub database: PackageDatabase,
+}
+
+/// Inventory of the packages already installed on a host, read straight from the native package
+/// databases so callers can skip satisfied packages without shelling out once per package
+#[derive(Debug, Default, PartialEq)]
+pub struct Inventory {
+    /// by database then name, as dpkg and rpm may both have a package of the same name
+    packages: BTreeMap<(PackageDatabase, String), InstalledPackage>,
+}
+
+impl Inventory {
+    /// Scan every package database found under `root` (usually `/`).
+    /// Databases that are absent are skipped, as is rpm when its CLI is not installed.
+    pub fn scan(root: &Path, runner: &dyn CommandRunner) -> io::Result<Inventory> {
+        let mut inventory = Inventory::default();
+
+        if let Some(status) = read_optional(&root.join(DPKG_STATUS))? {
+            inventory.extend(parse_dpkg_status(&status));
+        }
+        if let Some(installed) = read_optional(&root.join(APK_INSTALLED))? {
+            inventory.extend(parse_apk_installed(&installed));
+        }
+        inventory.extend(read_pacman_local(&root.join(PACMAN_LOCAL))?);
+        inventory.extend(query_rpm(root, runner));
+
+        Ok(inventory)
+    }
+
+    pub fn get(&self, database: PackageDatabase, name: &str) -> Option<&InstalledPackage> {
+        self.packages.get(&(database, name.to_string()))
+    }
+
+    pub fn packages(&self) -> impl Iterator<Item = &InstalledPackage> {
+        self.packages.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.packages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Whether a package spec is already installed. Specs are offsetup's own format, `name` or
+    /// `name=version` whatever the package manager (yum pins `redis-5.0.4`), and are never parsed
+    /// from config files: a pinned spec (`redis=5:5.0.4-1`) is only satisfied by that exact
+    /// version.
+    pub fn is_satisfied(&self, database: PackageDatabase, spec: &str) -> bool {
+        let (name, version) = match spec.find('=') {
+            Some(idx) => (&spec[..idx], Some(&spec[idx + 1..])),
+            None => (spec, None),
+        };
+        match (self.get(database, name), version) {
+            (Some(installed), Some(version)) => installed.version == version,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Package specs from `specs` that still need installing into `database`
+    pub fn missing(&self, database: PackageDatabase, specs: &[String]) -> Vec<String> {
+        specs
+            .iter()
+            .filter(|spec| !self.is_satisfied(database, spec))
+            .cloned()
+            .collect()
+    }
+
+    fn extend(&mut self, packages: Vec<InstalledPackage>) {
+        for package in packages {
+            self.packages
+                .insert((package.database, package.name.clone()), package);
+        }
+    }
+}
+
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Split a deb822/apk style database into `(key, value)` paragraphs
+fn paragraphs(content: &str, separator: char) -> Vec<Vec<(&str, &str)>> {
+    let mut result = vec![];
+    let mut current = vec![];
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                result.push(current);
+                current = vec![];
+            }
+            continue;
+        }
+        // continuation lines (eg: Description bodies) never carry fields we need
+        if line.starts_with(' ') || line.starts_with('\t') {
+            continue;
+        }
+        if let Some(idx) = line.find(separator) {
+            current.push((&line[..idx], line[idx + 1..].trim()));
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
+}
+
+fn field<'a>(paragraph: &[(&'a str, &'a str)], key: &str) -> Option<&'a str> {
+    paragraph.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// Parse `/var/lib/dpkg/status`, keeping only packages whose status is `installed`
+pub fn parse_dpkg_status(content: &str) -> Vec<InstalledPackage> {
+    paragraphs(content, ':')
+        .iter()
+        .filter(|p| field(p, "Status").is_some_and(|s| s.ends_with(" installed")))
+        .filter_map(|p| {
+            Some(InstalledPackage {
+                name: field(p, "Package")?.into(),
+                version: field(p, "Version")?.into(),
+                database: PackageDatabase::Dpkg,
+            })
+        })
+        .collect()
+}
+
+/// Parse `/lib/apk/db/installed`
+pub fn parse_apk_installed(content: &str) -> Vec<InstalledPackage> {
+    paragraphs(content, ':')
+        .iter()
+        .filter_map(|p| {
+            Some(InstalledPackage {
+                name: field(p, "P")?.into(),
+                version: field(p, "V")?.into(),
+                database: PackageDatabase::Apk,
+            })
+        })
+        .collect()
+}
+
+/// Parse a single `desc` file from `/var/lib/pacman/local/<pkg>/`
+pub fn parse_pacman_desc(content: &str) -> Option<InstalledPackage> {
+    let mut name = None;
+    let mut version = None;
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        match line.trim() {
+            "%NAME%" => name = lines.next().map(str::trim),
+            "%VERSION%" => version = lines.next().map(str::trim),
+            _ => {}
+        }
+    }
+    Some(InstalledPackage {
+        name: name?.into(),
+        version: version?.into(),
+        database: PackageDatabase::Pacman,
+    })
+}
+
+fn read_pacman_local(dir: &Path) -> io::Result<Vec<InstalledPackage>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut descs: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|e| e.path().join("desc"))
+        .filter(|p| p.is_file())
+        .collect();
+    descs.sort();
+
+    let mut packages = vec![];
+    for desc in descs {
+        if let Some(package) = parse_pacman_desc(&fs::read_to_string(desc)?) {
+            packages.push(package);
+        }
+    }
+    Ok(packages)
+}
+
+/// Parse the output of `rpm -qa --queryformat '%{NAME}\t%{VERSION}-%{RELEASE}\n'`
+pub fn parse_rpm_query(output: &str) -> Vec<InstalledPackage> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.splitn(2, '\t');
+            let name = columns.next()?.trim();
+            let version = columns.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some(InstalledPackage {
+                name: name.into(),
+                version: version.into(),
+                database: PackageDatabase::Rpm,
+            })
+        })
+        .collect()
+}
+
+/// The rpm database format differs between releases, so rather than reading it the rpm CLI is asked
+fn query_rpm(root: &Path, runner: &dyn CommandRunner) -> Vec<InstalledPackage> {
+    let root = root.to_string_lossy();
+    let mut args = vec![];
+    if root != "/" {
+        args.extend(&["--root", root.as_ref()]);
+    }
+    args.extend(&["-qa", "--queryformat", RPM_QUERY_FORMAT]);
+
+    match runner.run("rpm", &args) {
+        Ok(ref output) if output.success => parse_rpm_query(&output.stdout),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::fake::FakeRunner;
+
+    const FIXTURES: &str = "examples/scanner/inventory";
+
+    fn fixture(path: &str) -> String {
+        fs::read_to_string(Path::new(FIXTURES).join(path)).unwrap()
+    }
+
+    #[test]
+    fn can_parse_dpkg_status() {
+        let packages = parse_dpkg_status(&fixture(DPKG_STATUS));
+        let names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+
+        assert_eq!(vec!["redis-server", "curl"], names);
+        assert_eq!("5:5.0.4-1", packages[0].version);
+        assert_eq!(PackageDatabase::Dpkg, packages[0].database);
+    }
+
+    #[test]
+    fn can_parse_apk_installed() {
+        let packages = parse_apk_installed(&fixture(APK_INSTALLED));
+
+        assert_eq!(2, packages.len());
+        assert_eq!("musl", packages[0].name);
+        assert_eq!("1.1.22-r3", packages[0].version);
+        assert_eq!("redis", packages[1].name);
+    }
+
+    #[test]
+    fn can_parse_pacman_local() {
+        let packages = read_pacman_local(&Path::new(FIXTURES).join(PACMAN_LOCAL)).unwrap();
+
+        assert_eq!(1, packages.len());
+        assert_eq!("cmake", packages[0].name);
+        assert_eq!("3.14.5-1", packages[0].version);
+    }
+
+    #[test]
+    fn can_parse_rpm_query() {
+        let packages = parse_rpm_query("redis\t5.0.4-1.el8\nbash\t4.4.19-8.el8\n\n");
+
+        assert_eq!(2, packages.len());
+        assert_eq!("redis", packages[0].name);
+        assert_eq!("5.0.4-1.el8", packages[0].version);
+        assert_eq!(PackageDatabase::Rpm, packages[1].database);
+    }
+
+    #[test]
+    fn can_scan_inventory() {
+        let root = format!("{} -qa --queryformat {}", FIXTURES, RPM_QUERY_FORMAT);
+        let runner = FakeRunner::new().respond(&format!("rpm --root {}", root), "gcc\t8.2.1-3\n");
+        let inventory = Inventory::scan(Path::new(FIXTURES), &runner).unwrap();
+
+        assert_eq!(6, inventory.len());
+        assert_eq!(
+            "8.2.1-3",
+            inventory.get(PackageDatabase::Rpm, "gcc").unwrap().version
+        );
+        assert!(inventory.get(PackageDatabase::Dpkg, "gcc").is_none());
+        assert!(inventory.get(PackageDatabase::Pacman, "cmake").is_some());
+        assert!(
+            inventory.get(PackageDatabase::Dpkg, "vim").is_none(),
+            "vim was removed, not installed"
+        );
+    }
+
+    #[test]
+    fn missing_rpm_is_skipped() {
+        let inventory = Inventory::scan(Path::new(FIXTURES), &FakeRunner::new()).unwrap();
+        assert_eq!(5, inventory.len());
+
+        let query = format!(
+            "rpm --root {} -qa --queryformat {}",
+            FIXTURES, RPM_QUERY_FORMAT
+        );
+        let runner = FakeRunner::new().fail(&query, "error: cannot open Packages database");
+        let inventory = Inventory::scan(Path::new(FIXTURES), &runner).unwrap();
+        assert_eq!(5, inventory.len());
+        assert_eq!(vec![query], runner.calls());
+    }
+
+    #[test]
+    fn can_skip_satisfied_packages() {
+        let inventory = Inventory::scan(Path::new(FIXTURES), &FakeRunner::new()).unwrap();
+        let wanted: Vec<String> = vec![
+            "redis-server".into(),
+            "curl=7.58.0-2ubuntu3.7".into(),
+            "curl=7.64.0-4".into(),
+            "make".into(),
+        ];
+
+        assert_eq!(
+            vec!["curl=7.64.0-4".to_string(), "make".to_string()],
+            inventory.missing(PackageDatabase::Dpkg, &wanted)
+        );
+        assert_eq!(
+            vec!["redis-server".to_string(), "redis".to_string()],
+            inventory.missing(
+                PackageDatabase::Rpm,
+                &["redis-server".into(), "redis".into()]
+            ),
+            "installed with dpkg and apk, not with rpm"
+        );
+
+        assert_eq!(Some(PackageDatabase::Dpkg), PackageDatabase::of("apt_get"));
+        assert_eq!(Some(PackageDatabase::Rpm), PackageDatabase::of("dnf"));
+        assert_eq!(None, PackageDatabase::of("brew"));
+    }
+}
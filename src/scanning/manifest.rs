@@ -0,0 +1,226 @@
+use std::{fs, path::Path};
+
+use crate::scanning::platform::{LangDependency, LangDependencyName};
+
+/// Toolchain requirement read from a single manifest or version file
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestVersion {
+    pub dependency: LangDependency,
+    /// version files (`.nvmrc`, `rust-toolchain`, ...) pin an exact toolchain and take precedence
+    /// over the minimum versions declared in package manifests
+    pub pinned: bool,
+}
+
+impl ManifestVersion {
+    fn new(name: LangDependencyName, version: String, pinned: bool) -> ManifestVersion {
+        ManifestVersion {
+            dependency: LangDependency { name, version },
+            pinned,
+        }
+    }
+}
+
+/// Read the toolchain version declared by `path`, if it is a manifest offsetup understands
+pub fn read_manifest(path: &Path) -> Option<ManifestVersion> {
+    let file_name = path.file_name()?.to_str()?;
+    let parse: fn(&str) -> Option<ManifestVersion> = match file_name {
+        "Cargo.toml" => parse_cargo_toml,
+        "rust-toolchain" | "rust-toolchain.toml" => parse_rust_toolchain,
+        "go.mod" => parse_go_mod,
+        "package.json" => parse_package_json,
+        ".nvmrc" => parse_nvmrc,
+        "pyproject.toml" => parse_pyproject_toml,
+        ".python-version" => parse_python_version,
+        "runtime.txt" => parse_runtime_txt,
+        _ => return None,
+    };
+    parse(&fs::read_to_string(path).ok()?)
+}
+
+fn first_line(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+/// `rust-version` in `[package]` is the minimum supported Rust version
+pub fn parse_cargo_toml(content: &str) -> Option<ManifestVersion> {
+    let manifest: toml::Value = toml::from_str(content).ok()?;
+    let version = manifest.get("package")?.get("rust-version")?.as_str()?;
+    Some(ManifestVersion::new(
+        LangDependencyName::Rust,
+        format!(">={}", version),
+        false,
+    ))
+}
+
+/// Handles both the legacy single line `rust-toolchain` and the `[toolchain]` table form
+pub fn parse_rust_toolchain(content: &str) -> Option<ManifestVersion> {
+    let channel = match toml::from_str::<toml::Value>(content) {
+        Ok(toolchain) => toolchain.get("toolchain")?.get("channel")?.as_str()?.into(),
+        Err(_) => first_line(content)?.to_string(),
+    };
+    Some(ManifestVersion::new(
+        LangDependencyName::Rust,
+        channel,
+        true,
+    ))
+}
+
+/// The `go` directive is the minimum Go version the module is written for
+pub fn parse_go_mod(content: &str) -> Option<ManifestVersion> {
+    let version = content
+        .lines()
+        .map(str::trim)
+        .find(|l| l.starts_with("go "))?
+        .trim_start_matches("go ")
+        .trim();
+    Some(ManifestVersion::new(
+        LangDependencyName::Go,
+        format!(">={}", version),
+        false,
+    ))
+}
+
+/// `engines.node` is already a semver range, so it is used as is
+pub fn parse_package_json(content: &str) -> Option<ManifestVersion> {
+    let manifest: serde_json::Value = serde_json::from_str(content).ok()?;
+    let version = manifest.get("engines")?.get("node")?.as_str()?;
+    Some(ManifestVersion::new(
+        LangDependencyName::NodeJS,
+        version.into(),
+        false,
+    ))
+}
+
+pub fn parse_nvmrc(content: &str) -> Option<ManifestVersion> {
+    let version = first_line(content)?.trim_start_matches('v');
+    Some(ManifestVersion::new(
+        LangDependencyName::NodeJS,
+        version.into(),
+        true,
+    ))
+}
+
+/// PEP 621 `requires-python`, falling back to poetry's `python` dependency
+pub fn parse_pyproject_toml(content: &str) -> Option<ManifestVersion> {
+    let manifest: toml::Value = toml::from_str(content).ok()?;
+    let pep621 = manifest
+        .get("project")
+        .and_then(|p| p.get("requires-python"));
+    let poetry = || {
+        manifest
+            .get("tool")?
+            .get("poetry")?
+            .get("dependencies")?
+            .get("python")
+    };
+    let version = pep621.or_else(poetry)?.as_str()?;
+    Some(ManifestVersion::new(
+        LangDependencyName::Python,
+        version.into(),
+        false,
+    ))
+}
+
+pub fn parse_python_version(content: &str) -> Option<ManifestVersion> {
+    Some(ManifestVersion::new(
+        LangDependencyName::Python,
+        first_line(content)?.into(),
+        true,
+    ))
+}
+
+/// Heroku style `python-3.7.4`
+pub fn parse_runtime_txt(content: &str) -> Option<ManifestVersion> {
+    let line = first_line(content)?;
+    if !line.starts_with("python-") {
+        return None;
+    }
+    Some(ManifestVersion::new(
+        LangDependencyName::Python,
+        line.trim_start_matches("python-").into(),
+        true,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(manifest: Option<ManifestVersion>) -> (String, bool) {
+        let manifest = manifest.expect("failed to parse manifest");
+        (manifest.dependency.version, manifest.pinned)
+    }
+
+    #[test]
+    fn can_parse_rust_manifests() {
+        assert_eq!(
+            (">=1.56".to_string(), false),
+            version(parse_cargo_toml(
+                "[package]\nname = \"a\"\nrust-version = \"1.56\"\n"
+            ))
+        );
+        assert_eq!(None, parse_cargo_toml("[package]\nname = \"a\"\n"));
+        assert_eq!(
+            ("1.70.0".to_string(), true),
+            version(parse_rust_toolchain("[toolchain]\nchannel = \"1.70.0\"\n"))
+        );
+        assert_eq!(
+            ("nightly-2019-07-01".to_string(), true),
+            version(parse_rust_toolchain("nightly-2019-07-01\n"))
+        );
+    }
+
+    #[test]
+    fn can_parse_go_mod() {
+        let manifest =
+            parse_go_mod("module example.com/a\n\ngo 1.13\n\nrequire rsc.io/quote v1.5.2\n");
+        assert_eq!(
+            LangDependencyName::Go,
+            manifest.as_ref().unwrap().dependency.name
+        );
+        assert_eq!((">=1.13".to_string(), false), version(manifest));
+    }
+
+    #[test]
+    fn can_parse_node_manifests() {
+        assert_eq!(
+            (">=10.16".to_string(), false),
+            version(parse_package_json(
+                r#"{"name": "a", "engines": {"node": ">=10.16"}}"#
+            ))
+        );
+        assert_eq!(None, parse_package_json(r#"{"name": "a"}"#));
+        assert_eq!(
+            ("12.10.0".to_string(), true),
+            version(parse_nvmrc("v12.10.0\n"))
+        );
+    }
+
+    #[test]
+    fn can_parse_python_manifests() {
+        assert_eq!(
+            (">=3.7".to_string(), false),
+            version(parse_pyproject_toml(
+                "[project]\nname = \"a\"\nrequires-python = \">=3.7\"\n"
+            ))
+        );
+        assert_eq!(
+            ("^3.6".to_string(), false),
+            version(parse_pyproject_toml(
+                "[tool.poetry.dependencies]\npython = \"^3.6\"\n"
+            ))
+        );
+        assert_eq!(
+            ("3.7.4".to_string(), true),
+            version(parse_python_version("3.7.4\n"))
+        );
+        assert_eq!(
+            ("3.7.4".to_string(), true),
+            version(parse_runtime_txt("python-3.7.4\n"))
+        );
+        assert_eq!(None, parse_runtime_txt("ruby-2.6.3\n"));
+    }
+}
@@ -1,4 +1,8 @@
+pub mod inventory;
+pub mod manifest;
+pub mod native;
 pub mod platform;
+pub mod project;
 
 #[cfg(windows)]
 #[path = "windows/mod.rs"]
@@ -0,0 +1,348 @@
+use std::{fs, path::Path};
+
+use crate::scanning::{platform::PlatformName, project::walk_project};
+
+/// Package registry a language dependency comes from
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Ecosystem {
+    /// crates listed in Cargo.lock
+    Cargo,
+    /// packages listed in package.json
+    Npm,
+    /// packages listed in requirements.txt
+    PyPI,
+}
+
+/// System packages a language dependency needs to build or link against
+struct NativeRequirement {
+    ecosystem: Ecosystem,
+    dependency: &'static str,
+    apt: &'static [&'static str],
+    dnf: &'static [&'static str],
+    pacman: &'static [&'static str],
+    brew: &'static [&'static str],
+}
+
+/// Well-known language dependencies wrapping native libraries.
+/// Names are matched lowercase, with `_` and `-` treated alike.
+static KNOWLEDGE_BASE: &[NativeRequirement] = &[
+    NativeRequirement {
+        ecosystem: Ecosystem::Cargo,
+        dependency: "openssl-sys",
+        apt: &["libssl-dev", "pkg-config"],
+        dnf: &["openssl-devel", "pkgconf-pkg-config"],
+        pacman: &["openssl", "pkgconf"],
+        brew: &["openssl", "pkg-config"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Cargo,
+        dependency: "libsqlite3-sys",
+        apt: &["libsqlite3-dev"],
+        dnf: &["sqlite-devel"],
+        pacman: &["sqlite"],
+        brew: &["sqlite"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Cargo,
+        dependency: "pq-sys",
+        apt: &["libpq-dev"],
+        dnf: &["libpq-devel"],
+        pacman: &["postgresql-libs"],
+        brew: &["libpq"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Cargo,
+        dependency: "mysqlclient-sys",
+        apt: &["default-libmysqlclient-dev"],
+        dnf: &["mariadb-connector-c-devel"],
+        pacman: &["mariadb-libs"],
+        brew: &["mysql-client"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Cargo,
+        dependency: "libz-sys",
+        apt: &["zlib1g-dev"],
+        dnf: &["zlib-devel"],
+        pacman: &["zlib"],
+        brew: &["zlib"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Cargo,
+        dependency: "curl-sys",
+        apt: &["libcurl4-openssl-dev"],
+        dnf: &["libcurl-devel"],
+        pacman: &["curl"],
+        brew: &["curl"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::PyPI,
+        dependency: "psycopg2",
+        apt: &["libpq-dev", "python3-dev"],
+        dnf: &["libpq-devel", "python3-devel"],
+        pacman: &["postgresql-libs"],
+        brew: &["libpq"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::PyPI,
+        dependency: "mysqlclient",
+        apt: &["default-libmysqlclient-dev", "python3-dev"],
+        dnf: &["mariadb-connector-c-devel", "python3-devel"],
+        pacman: &["mariadb-libs"],
+        brew: &["mysql-client"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::PyPI,
+        dependency: "lxml",
+        apt: &["libxml2-dev", "libxslt1-dev"],
+        dnf: &["libxml2-devel", "libxslt-devel"],
+        pacman: &["libxml2", "libxslt"],
+        brew: &["libxml2", "libxslt"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::PyPI,
+        dependency: "pillow",
+        apt: &["libjpeg-dev", "zlib1g-dev"],
+        dnf: &["libjpeg-turbo-devel", "zlib-devel"],
+        pacman: &["libjpeg-turbo", "zlib"],
+        brew: &["jpeg", "zlib"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::PyPI,
+        dependency: "cryptography",
+        apt: &["libssl-dev", "libffi-dev"],
+        dnf: &["openssl-devel", "libffi-devel"],
+        pacman: &["openssl", "libffi"],
+        brew: &["openssl", "libffi"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Npm,
+        dependency: "sqlite3",
+        apt: &["libsqlite3-dev"],
+        dnf: &["sqlite-devel"],
+        pacman: &["sqlite"],
+        brew: &["sqlite"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Npm,
+        dependency: "pg-native",
+        apt: &["libpq-dev"],
+        dnf: &["libpq-devel"],
+        pacman: &["postgresql-libs"],
+        brew: &["libpq"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Npm,
+        dependency: "bcrypt",
+        apt: &["build-essential", "python3"],
+        dnf: &["gcc-c++", "make", "python3"],
+        pacman: &["base-devel", "python"],
+        brew: &[],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Npm,
+        dependency: "canvas",
+        apt: &[
+            "libcairo2-dev",
+            "libpango1.0-dev",
+            "libjpeg-dev",
+            "libgif-dev",
+            "librsvg2-dev",
+        ],
+        dnf: &[
+            "cairo-devel",
+            "pango-devel",
+            "libjpeg-turbo-devel",
+            "giflib-devel",
+            "librsvg2-devel",
+        ],
+        pacman: &["cairo", "pango", "libjpeg-turbo", "giflib", "librsvg"],
+        brew: &["pkg-config", "cairo", "pango", "jpeg", "giflib", "librsvg"],
+    },
+    NativeRequirement {
+        ecosystem: Ecosystem::Npm,
+        dependency: "sharp",
+        apt: &["libvips-dev"],
+        dnf: &["vips-devel"],
+        pacman: &["libvips"],
+        brew: &["vips"],
+    },
+];
+
+/// System packages to add to each manager's list in System, deduplicated, in discovery order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemPackages {
+    pub apt: Vec<String>,
+    pub dnf: Vec<String>,
+    pub pacman: Vec<String>,
+    pub brew: Vec<String>,
+}
+
+impl SystemPackages {
+    fn add(&mut self, requirement: &NativeRequirement) {
+        fn extend(list: &mut Vec<String>, packages: &[&str]) {
+            for package in packages {
+                if !list.iter().any(|p| p == package) {
+                    list.push(package.to_string());
+                }
+            }
+        }
+        extend(&mut self.apt, requirement.apt);
+        extend(&mut self.dnf, requirement.dnf);
+        extend(&mut self.pacman, requirement.pacman);
+        extend(&mut self.brew, requirement.brew);
+    }
+
+    /// the list for the package manager offsetup uses on `platform`
+    pub fn for_platform(&self, platform: &PlatformName) -> &[String] {
+        match platform {
+            PlatformName::Debian | PlatformName::Ubuntu => &self.apt,
+            PlatformName::CentOS | PlatformName::Redhat => &self.dnf,
+            PlatformName::Arch | PlatformName::Manjaro => &self.pacman,
+            PlatformName::MacOSX => &self.brew,
+            PlatformName::Unknown | PlatformName::Windows => &[],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.apt.is_empty() && self.dnf.is_empty() && self.pacman.is_empty() && self.brew.is_empty()
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace('_', "-")
+}
+
+/// Crate names from a Cargo.lock
+pub fn parse_cargo_lock(content: &str) -> Vec<String> {
+    let lock: toml::Value = match toml::from_str(content) {
+        Ok(lock) => lock,
+        Err(_) => return vec![],
+    };
+    lock.get("package")
+        .and_then(toml::Value::as_array)
+        .map(|packages| {
+            packages
+                .iter()
+                .filter_map(|p| p.get("name")?.as_str())
+                .map(normalize)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Distribution names from a pip requirements file, without versions, extras or markers
+pub fn parse_requirements_txt(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('-'))
+        .filter_map(|line| {
+            let end = line
+                .find(|c: char| "[<>=!~;@ ".contains(c))
+                .unwrap_or(line.len());
+            match &line[..end] {
+                "" => None,
+                name => Some(normalize(name)),
+            }
+        })
+        .collect()
+}
+
+/// Package names from the dependency tables of a package.json
+pub fn parse_package_json(content: &str) -> Vec<String> {
+    let manifest: serde_json::Value = match serde_json::from_str(content) {
+        Ok(manifest) => manifest,
+        Err(_) => return vec![],
+    };
+    ["dependencies", "devDependencies", "optionalDependencies"]
+        .iter()
+        .filter_map(|table| manifest.get(table)?.as_object())
+        .flat_map(|table| table.keys().map(|k| normalize(k)))
+        .collect()
+}
+
+/// System packages needed by the native language dependencies of the project in `dir`
+pub fn scan_native_requirements(dir: &Path) -> SystemPackages {
+    let mut packages = SystemPackages::default();
+    for path in walk_project(dir, None) {
+        let file_name = match path.file_name().and_then(|f| f.to_str()) {
+            Some(file_name) => file_name.to_string(),
+            None => continue,
+        };
+        let (ecosystem, parse): (Ecosystem, fn(&str) -> Vec<String>) = match file_name.as_str() {
+            "Cargo.lock" => (Ecosystem::Cargo, parse_cargo_lock),
+            "package.json" => (Ecosystem::Npm, parse_package_json),
+            f if f.starts_with("requirements") && f.ends_with(".txt") => {
+                (Ecosystem::PyPI, parse_requirements_txt)
+            }
+            _ => continue,
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        for dependency in parse(&content) {
+            KNOWLEDGE_BASE
+                .iter()
+                .filter(|r| r.ecosystem == ecosystem && r.dependency == dependency)
+                .for_each(|r| packages.add(r));
+        }
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_requirements_txt() {
+        let names = parse_requirements_txt(
+            "# comment\n-r base.txt\nPsycopg2==2.8.3\nlxml[html_clean]>=4.4 ; python_version > '3'\n\nflask\nPillow @ https://example.com/p.whl\n",
+        );
+        assert_eq!(vec!["psycopg2", "lxml", "flask", "pillow"], names);
+    }
+
+    #[test]
+    fn can_parse_cargo_lock() {
+        let names = parse_cargo_lock(
+            "[[package]]\nname = \"openssl-sys\"\nversion = \"0.9.48\"\n\n[[package]]\nname = \"libsqlite3_sys\"\nversion = \"0.12.0\"\n",
+        );
+        assert_eq!(vec!["openssl-sys", "libsqlite3-sys"], names);
+    }
+
+    #[test]
+    fn can_parse_package_json() {
+        let names = parse_package_json(
+            r#"{"dependencies": {"sqlite3": "^4.0.9"}, "devDependencies": {"jest": "^24"}}"#,
+        );
+        assert_eq!(vec!["sqlite3", "jest"], names);
+    }
+
+    #[test]
+    fn can_scan_native_requirements() {
+        let packages = scan_native_requirements(Path::new("examples/scanner/native"));
+
+        assert_eq!(
+            vec![
+                "libssl-dev",
+                "pkg-config",
+                "libpq-dev",
+                "python3-dev",
+                "libsqlite3-dev"
+            ],
+            packages.for_platform(&PlatformName::Ubuntu)
+        );
+        assert_eq!(
+            vec!["openssl", "pkg-config", "libpq", "sqlite"],
+            packages.for_platform(&PlatformName::MacOSX)
+        );
+        assert!(packages.for_platform(&PlatformName::Windows).is_empty());
+    }
+
+    #[test]
+    fn unknown_dependencies_need_nothing() {
+        let packages = scan_native_requirements(Path::new("examples/scanner/new/python_docs"));
+        assert!(packages.is_empty(), "flask is pure python: {:?}", packages);
+    }
+}
@@ -1,10 +1,13 @@
 use core::borrow::Borrow;
 
-use itertools::Itertools;
-use walkdir::WalkDir;
-
-use crate::scanning::os;
-use std::str::FromStr;
+use crate::runner::SystemRunner;
+use crate::scanning::{
+    inventory::Inventory,
+    native::{scan_native_requirements, SystemPackages},
+    os,
+    project::ProjectScan,
+};
+use std::{fmt, io, path::Path, str::FromStr};
 
 /// PlatformScanner retrieves information based on what platform the binary is running on.
 /// It is meant to be used for
@@ -30,30 +33,13 @@ fn get_architecture() -> Architecture {
 }
 
 impl PlatformScanner {
-    /// search given directory for specific language dependencies ie LangDependencyName
+    /// search given directory for specific language dependencies ie LangDependencyName,
+    /// heaviest first. See `scan_project` for what is skipped
     pub fn get_project_language_dependencies(dir: String) -> Option<Vec<LangDependencyName>> {
-        let files: Vec<LangDependencyName> = WalkDir::new(dir)
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter_map(|d| {
-                if let Some(ext) = d.path().extension() {
-                    match ext
-                        .to_str()
-                        .expect("failed to convert to string")
-                        .to_lowercase()
-                        .borrow()
-                    {
-                        "go" => Some(LangDependencyName::Go),
-                        "rs" => Some(LangDependencyName::Rust),
-                        "js" | "ts" => Some(LangDependencyName::NodeJS),
-                        "py" => Some(LangDependencyName::Python),
-                        _ => None,
-                    }
-                } else {
-                    None
-                }
-            })
-            .unique()
+        let files: Vec<LangDependencyName> = PlatformScanner::scan_project(dir, None)
+            .languages()
+            .iter()
+            .map(|l| l.name.clone())
             .collect();
         if files.is_empty() {
             None
@@ -62,6 +48,35 @@ impl PlatformScanner {
         }
     }
 
+    /// weigh the languages of the project in given directory by manifest presence and file count,
+    /// ignoring what .gitignore/.ignore files and the built-in SKIPPED_DIRS list exclude
+    pub fn scan_project(dir: String, max_depth: Option<usize>) -> ProjectScan {
+        ProjectScan::scan(Path::new(&dir), max_depth)
+    }
+
+    /// the toolchain versions the manifests (Cargo.toml, go.mod, package.json, pyproject.toml, ...)
+    /// of given directory declare, one LangDependency per language, as found by `scan_project`.
+    /// Pinned versions (.nvmrc, rust-toolchain, .python-version, runtime.txt) win over manifest minimums
+    pub fn get_project_language_versions(dir: String) -> Option<Vec<LangDependency>> {
+        let versions = PlatformScanner::scan_project(dir, None).versions();
+        if versions.is_empty() {
+            None
+        } else {
+            Some(versions)
+        }
+    }
+
+    /// system packages needed by the well-known native dependencies (openssl-sys, psycopg2,
+    /// sqlite3, ...) found in the Cargo.lock, requirements.txt and package.json files of given directory
+    pub fn get_native_requirements(dir: String) -> SystemPackages {
+        scan_native_requirements(Path::new(&dir))
+    }
+
+    /// list the packages already installed on this host from its native package databases
+    pub fn get_inventory() -> io::Result<Inventory> {
+        Inventory::scan(Path::new("/"), &SystemRunner)
+    }
+
     fn _get_unix_platform_info() -> (PlatformName, PlatformVersionAliases) {
         let os = os_type::current_platform();
         let name = match os.os_type {
@@ -72,7 +87,7 @@ impl PlatformScanner {
             os_type::OSType::Manjaro => PlatformName::Manjaro,
             os_type::OSType::Redhat => PlatformName::Redhat,
             os_type::OSType::Ubuntu => PlatformName::Ubuntu,
-            os_type::OSType::Unknown | _ => PlatformName::Unknown,
+            _ => PlatformName::Unknown,
         };
         (name, vec![os.version])
     }
@@ -85,16 +100,154 @@ impl PlatformScanner {
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum LangDependencyName {
+    /// C and C++
+    Cpp,
+    DotNet,
+    Elixir,
     Go,
+    /// Java and the other JVM languages built with maven or gradle, eg: Kotlin
+    Java,
     NodeJS,
+    PHP,
     Python,
+    Ruby,
     Rust,
 }
 
-#[derive(Debug, PartialEq)]
+impl LangDependencyName {
+    /// detect the language of a file, by its manifest name first and its extension otherwise
+    pub fn from_path(path: &Path) -> Option<LangDependencyName> {
+        LangDependencyName::from_manifest(path).or_else(|| LangDependencyName::from_extension(path))
+    }
+
+    /// detect the language a build or package manifest is for
+    pub fn from_manifest(path: &Path) -> Option<LangDependencyName> {
+        let file_name = path.file_name()?.to_str()?;
+        match file_name {
+            // a Makefile also drives Go or Python tasks, so `ProjectScan` only counts it with C sources
+            "CMakeLists.txt" | "meson.build" => Some(LangDependencyName::Cpp),
+            "mix.exs" => Some(LangDependencyName::Elixir),
+            "go.mod" => Some(LangDependencyName::Go),
+            "pom.xml" | "build.gradle" | "build.gradle.kts" | "settings.gradle" => {
+                Some(LangDependencyName::Java)
+            }
+            "package.json" => Some(LangDependencyName::NodeJS),
+            "composer.json" => Some(LangDependencyName::PHP),
+            "Pipfile" | "pyproject.toml" | "requirements.txt" | "setup.py" => {
+                Some(LangDependencyName::Python)
+            }
+            "Gemfile" => Some(LangDependencyName::Ruby),
+            "Cargo.toml" => Some(LangDependencyName::Rust),
+            _ => match path.extension()?.to_str()?.to_lowercase().borrow() {
+                "csproj" | "fsproj" | "sln" => Some(LangDependencyName::DotNet),
+                "gemspec" => Some(LangDependencyName::Ruby),
+                _ => None,
+            },
+        }
+    }
+
+    /// detect the language of a source file
+    pub fn from_extension(path: &Path) -> Option<LangDependencyName> {
+        match path.extension()?.to_str()?.to_lowercase().borrow() {
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" => Some(LangDependencyName::Cpp),
+            "cs" | "fs" => Some(LangDependencyName::DotNet),
+            "ex" | "exs" => Some(LangDependencyName::Elixir),
+            "go" => Some(LangDependencyName::Go),
+            "java" | "kt" | "kts" => Some(LangDependencyName::Java),
+            "js" | "ts" => Some(LangDependencyName::NodeJS),
+            "php" => Some(LangDependencyName::PHP),
+            "py" => Some(LangDependencyName::Python),
+            "rb" => Some(LangDependencyName::Ruby),
+            "rs" => Some(LangDependencyName::Rust),
+            _ => None,
+        }
+    }
+
+    /// system packages providing this language's toolchain on the given platform,
+    /// as they would be listed under the platform's package manager in System
+    pub fn system_packages(&self, platform: &PlatformName) -> &'static [&'static str] {
+        use LangDependencyName::*;
+        use PlatformName::*;
+
+        match (self, platform) {
+            (Cpp, Debian) | (Cpp, Ubuntu) => &["build-essential", "cmake"],
+            (Cpp, CentOS) | (Cpp, Redhat) => &["gcc", "gcc-c++", "make", "cmake"],
+            (Cpp, Arch) | (Cpp, Manjaro) => &["base-devel", "cmake"],
+            (Cpp, MacOSX) => &["cmake"],
+            (Cpp, Windows) => &["mingw", "cmake"],
+
+            (DotNet, Debian) | (DotNet, Ubuntu) => &["dotnet-sdk-8.0"],
+            (DotNet, CentOS) | (DotNet, Redhat) => &["dotnet-sdk-8.0"],
+            (DotNet, Arch) | (DotNet, Manjaro) => &["dotnet-sdk"],
+            (DotNet, MacOSX) => &["dotnet"],
+            (DotNet, Windows) => &["dotnet-sdk"],
+
+            (Elixir, Unknown) => &[],
+            (Elixir, _) => &["elixir"],
+
+            (Go, Debian) | (Go, Ubuntu) => &["golang-go"],
+            (Go, CentOS) | (Go, Redhat) | (Go, Windows) => &["golang"],
+            (Go, Arch) | (Go, Manjaro) | (Go, MacOSX) => &["go"],
+
+            (Java, Debian) | (Java, Ubuntu) => &["default-jdk"],
+            (Java, CentOS) | (Java, Redhat) => &["java-11-openjdk-devel"],
+            (Java, Arch) | (Java, Manjaro) => &["jdk-openjdk"],
+            (Java, MacOSX) | (Java, Windows) => &["openjdk"],
+
+            (NodeJS, MacOSX) => &["node"],
+            (NodeJS, Windows) => &["nodejs"],
+            (NodeJS, Unknown) => &[],
+            (NodeJS, _) => &["nodejs", "npm"],
+
+            (PHP, Debian) | (PHP, Ubuntu) | (PHP, CentOS) | (PHP, Redhat) => {
+                &["php-cli", "composer"]
+            }
+            (PHP, Unknown) => &[],
+            (PHP, _) => &["php", "composer"],
+
+            (Python, Debian) | (Python, Ubuntu) => &["python3", "python3-pip", "python3-venv"],
+            (Python, CentOS) | (Python, Redhat) => &["python3", "python3-pip"],
+            (Python, Arch) | (Python, Manjaro) => &["python", "python-pip"],
+            (Python, MacOSX) | (Python, Windows) => &["python"],
+
+            (Ruby, Debian) | (Ruby, Ubuntu) => &["ruby", "ruby-dev", "bundler"],
+            (Ruby, CentOS) | (Ruby, Redhat) => &["ruby", "ruby-devel", "rubygem-bundler"],
+            (Ruby, Unknown) => &[],
+            (Ruby, _) => &["ruby"],
+
+            (Rust, Debian) | (Rust, Ubuntu) => &["rustc", "cargo"],
+            (Rust, CentOS) | (Rust, Redhat) => &["rust", "cargo"],
+            (Rust, Unknown) => &[],
+            (Rust, _) => &["rust"],
+
+            (_, Unknown) => &[],
+        }
+    }
+}
+
+impl fmt::Display for LangDependencyName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LangDependencyName::Cpp => "cpp",
+            LangDependencyName::DotNet => "dotnet",
+            LangDependencyName::Elixir => "elixir",
+            LangDependencyName::Go => "go",
+            LangDependencyName::Java => "java",
+            LangDependencyName::NodeJS => "nodejs",
+            LangDependencyName::PHP => "php",
+            LangDependencyName::Python => "python",
+            LangDependencyName::Ruby => "ruby",
+            LangDependencyName::Rust => "rust",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A language toolchain and the version constraint the project declares for it
+#[derive(Clone, Debug, PartialEq)]
 pub struct LangDependency {
-    name: LangDependencyName,
-    version: String,
+    pub name: LangDependencyName,
+    pub version: String,
 }
 
 #[derive(Debug, PartialEq)]
@@ -133,9 +286,23 @@ impl FromStr for PlatformName {
     }
 }
 
-impl ToString for PlatformName {
-    fn to_string(&self) -> String {
+impl PlatformName {
+    /// the System package manager offsetup installs with on this platform
+    pub fn package_manager(&self) -> Option<&'static str> {
         match self {
+            PlatformName::Arch | PlatformName::Manjaro => Some("pacman"),
+            PlatformName::CentOS | PlatformName::Redhat => Some("yum"),
+            PlatformName::Debian | PlatformName::Ubuntu => Some("apt"),
+            PlatformName::MacOSX => Some("brew"),
+            PlatformName::Windows => Some("choco"),
+            PlatformName::Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for PlatformName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
             PlatformName::Arch => "arch",
             PlatformName::CentOS => "centos",
             PlatformName::Debian => "debian",
@@ -145,11 +312,12 @@ impl ToString for PlatformName {
             PlatformName::Ubuntu => "ubuntu",
             PlatformName::Unknown => "unknown",
             PlatformName::Windows => "windows",
-        }
-        .to_string()
+        };
+        write!(f, "{}", name)
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 pub enum Architecture {
     X86_32,
@@ -260,6 +428,126 @@ mod tests {
         assert_eq!(LangDependencyName::Rust, deps[0])
     }
 
+    fn assert_single_dependency(dir: &str, expected: LangDependencyName) {
+        let result = PlatformScanner::get_project_language_dependencies(dir.into());
+
+        assert!(result.is_some(), "Could not find dependency in {}", dir);
+        assert_eq!(vec![expected], result.unwrap());
+    }
+
+    #[test]
+    fn list_manifest_only_dependencies() {
+        assert_single_dependency("examples/scanner/new/java", LangDependencyName::Java);
+        assert_single_dependency("examples/scanner/new/ruby", LangDependencyName::Ruby);
+        assert_single_dependency("examples/scanner/new/php", LangDependencyName::PHP);
+        assert_single_dependency("examples/scanner/new/cpp", LangDependencyName::Cpp);
+        assert_single_dependency("examples/scanner/new/elixir", LangDependencyName::Elixir);
+    }
+
+    #[test]
+    fn list_dotnet_dependencies() {
+        assert_single_dependency("examples/scanner/new/dotnet", LangDependencyName::DotNet);
+    }
+
+    #[test]
+    fn can_detect_language_from_path() {
+        let detect = |p: &str| LangDependencyName::from_path(Path::new(p));
+
+        assert_eq!(
+            Some(LangDependencyName::Java),
+            detect("app/build.gradle.kts")
+        );
+        assert_eq!(Some(LangDependencyName::Java), detect("src/Main.kt"));
+        assert_eq!(Some(LangDependencyName::Cpp), detect("CMakeLists.txt"));
+        assert_eq!(Some(LangDependencyName::Cpp), detect("src/lib.HPP"));
+        assert_eq!(Some(LangDependencyName::DotNet), detect("Api/Api.csproj"));
+        assert_eq!(Some(LangDependencyName::Ruby), detect("Gemfile"));
+        assert_eq!(None, detect("Gemfile.lock"));
+        assert_eq!(None, detect("README.md"));
+    }
+
+    #[test]
+    fn can_map_languages_to_system_packages() {
+        assert_eq!(
+            &["build-essential", "cmake"],
+            LangDependencyName::Cpp.system_packages(&PlatformName::Ubuntu)
+        );
+        assert_eq!(
+            &["java-11-openjdk-devel"],
+            LangDependencyName::Java.system_packages(&PlatformName::CentOS)
+        );
+        assert_eq!(
+            &["elixir"],
+            LangDependencyName::Elixir.system_packages(&PlatformName::MacOSX)
+        );
+        assert!(LangDependencyName::Ruby
+            .system_packages(&PlatformName::Unknown)
+            .is_empty());
+    }
+
+    #[test]
+    fn list_rust_versions() {
+        let result =
+            PlatformScanner::get_project_language_versions("examples/scanner/manifest/rust".into());
+
+        assert_eq!(
+            Some(vec![LangDependency {
+                name: LangDependencyName::Rust,
+                version: ">=1.56".into()
+            }]),
+            result
+        );
+    }
+
+    #[test]
+    fn list_pinned_versions() {
+        let result = PlatformScanner::get_project_language_versions(
+            "examples/scanner/manifest/pinned".into(),
+        );
+
+        assert!(result.is_some(), "Could not find versions");
+        let deps = result.unwrap();
+        assert_eq!(2, deps.len(), "failed to find both versions");
+        assert!(deps.contains(&LangDependency {
+            name: LangDependencyName::Rust,
+            version: "1.36.0".into()
+        }));
+        assert!(deps.contains(&LangDependency {
+            name: LangDependencyName::NodeJS,
+            version: "10.16.0".into()
+        }));
+    }
+
+    #[test]
+    fn list_go_and_python_versions() {
+        let result = PlatformScanner::get_project_language_versions(
+            "examples/scanner/manifest/go_python".into(),
+        );
+
+        assert!(result.is_some(), "Could not find versions");
+        let deps = result.unwrap();
+        assert_eq!(
+            vec![
+                LangDependency {
+                    name: LangDependencyName::Go,
+                    version: ">=1.12".into()
+                },
+                LangDependency {
+                    name: LangDependencyName::Python,
+                    version: "3.7.4".into()
+                }
+            ],
+            deps
+        );
+    }
+
+    #[test]
+    fn no_versions_without_manifests() {
+        let result =
+            PlatformScanner::get_project_language_versions("examples/scanner/new/go".into());
+        assert!(result.is_none());
+    }
+
     #[test]
     fn can_get_platform() {
         let p = Platform::default();
@@ -274,7 +562,7 @@ mod tests {
                 PlatformName::Manjaro => println!("Found Manjaro platform"),
                 PlatformName::Redhat => println!("Found Redhat platform"),
                 PlatformName::Ubuntu => println!("Found Ubuntu platform"),
-                PlatformName::Unknown | _ => panic!("Found unsupported unix platform: {:?}", p),
+                _ => panic!("Found unsupported unix platform: {:?}", p),
             }
         }
         assert_ne!(
@@ -0,0 +1,274 @@
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+};
+
+use ignore::WalkBuilder;
+
+use crate::scanning::{
+    manifest::{read_manifest, ManifestVersion},
+    platform::{LangDependency, LangDependencyName},
+};
+
+/// Directories that hold vendored, generated or installed code rather than the project's own
+/// sources. They are skipped even when no .gitignore mentions them.
+pub const SKIPPED_DIRS: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    ".tox",
+    ".venv",
+    "__pycache__",
+    "bower_components",
+    "node_modules",
+    "target",
+    "vendor",
+    "venv",
+];
+
+/// Walk the files of the project in `dir`, in a stable order.
+/// Honors .gitignore and .ignore files (even outside of a git checkout, and those of parent
+/// directories) and SKIPPED_DIRS; `max_depth` counts the files directly inside `dir` as depth 1.
+pub fn walk_project(dir: &Path, max_depth: Option<usize>) -> impl Iterator<Item = PathBuf> {
+    WalkBuilder::new(dir)
+        .hidden(false)
+        .require_git(false)
+        .max_depth(max_depth)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| {
+            !(entry.file_type().is_some_and(|t| t.is_dir())
+                && SKIPPED_DIRS
+                    .iter()
+                    .any(|skipped| entry.file_name() == *skipped))
+        })
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+}
+
+/// How much evidence a project scan found for one language
+#[derive(Clone, Debug, PartialEq)]
+pub struct LanguageWeight {
+    pub name: LangDependencyName,
+    /// number of source files with one of the language's extensions
+    pub files: usize,
+    /// whether a manifest (Cargo.toml, pom.xml, Gemfile, ...) declares the language
+    pub manifest: bool,
+}
+
+/// Languages found in a project, heaviest first, with the toolchain versions its manifests declare
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProjectScan {
+    languages: Vec<LanguageWeight>,
+    versions: Vec<ManifestVersion>,
+}
+
+impl ProjectScan {
+    pub fn scan(dir: &Path, max_depth: Option<usize>) -> ProjectScan {
+        let mut languages: Vec<LanguageWeight> = vec![];
+        let mut versions: Vec<ManifestVersion> = vec![];
+        let mut makefile = false;
+        for path in walk_project(dir, max_depth) {
+            makefile |= path.file_name().is_some_and(|name| name == "Makefile");
+            if let Some(manifest) = read_manifest(&path) {
+                match versions
+                    .iter_mut()
+                    .find(|v| v.dependency.name == manifest.dependency.name)
+                {
+                    Some(existing) if manifest.pinned && !existing.pinned => *existing = manifest,
+                    Some(_) => {}
+                    None => versions.push(manifest),
+                }
+            }
+            let (name, manifest) = match LangDependencyName::from_manifest(&path) {
+                Some(name) => (name, true),
+                None => match LangDependencyName::from_extension(&path) {
+                    Some(name) => (name, false),
+                    None => continue,
+                },
+            };
+            let index = match languages.iter().position(|l| l.name == name) {
+                Some(index) => index,
+                None => {
+                    languages.push(LanguageWeight {
+                        name,
+                        files: 0,
+                        manifest: false,
+                    });
+                    languages.len() - 1
+                }
+            };
+            if manifest {
+                languages[index].manifest = true;
+            } else {
+                languages[index].files += 1;
+            }
+        }
+
+        if makefile {
+            for language in &mut languages {
+                if language.name == LangDependencyName::Cpp && language.files > 0 {
+                    language.manifest = true;
+                }
+            }
+        }
+
+        // a manifest outweighs any number of stray files, eg: the .js of a Python project's docs
+        languages.sort_by_key(|l| Reverse((l.manifest, l.files)));
+        ProjectScan {
+            languages,
+            versions,
+        }
+    }
+
+    pub fn languages(&self) -> &[LanguageWeight] {
+        &self.languages
+    }
+
+    /// the version of each language declared first, pinned versions winning over minimums
+    pub fn versions(&self) -> Vec<LangDependency> {
+        self.versions.iter().map(|v| v.dependency.clone()).collect()
+    }
+
+    /// the languages the project is written in rather than merely contains: those a manifest
+    /// declares, or without any manifest those with at least a quarter of the files of the
+    /// heaviest one. A stray .js in a Python project's docs is left out.
+    pub fn significant(&self) -> Vec<&LanguageWeight> {
+        let heaviest = self.languages.iter().map(|l| l.files).max().unwrap_or(0);
+        if self.languages.iter().any(|l| l.manifest) {
+            self.languages.iter().filter(|l| l.manifest).collect()
+        } else {
+            self.languages
+                .iter()
+                .filter(|l| l.files * 4 >= heaviest)
+                .collect()
+        }
+    }
+
+    /// the language the project is mostly written in
+    pub fn primary(&self) -> Option<&LangDependencyName> {
+        self.languages.first().map(|l| &l.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROJECT: &str = "examples/scanner/new/python_docs";
+
+    #[test]
+    fn skips_ignored_and_vendored_files() {
+        let files: Vec<PathBuf> = walk_project(Path::new(PROJECT), None)
+            .map(|p| p.strip_prefix(PROJECT).unwrap().to_path_buf())
+            .collect();
+
+        assert!(files.contains(&PathBuf::from("app/main.py")));
+        assert!(files.contains(&PathBuf::from(".gitignore")));
+        assert!(
+            !files.iter().any(|f| f.starts_with("node_modules")),
+            "should skip node_modules: {:?}",
+            files
+        );
+        assert!(!files.iter().any(|f| f.starts_with("vendor")));
+        assert!(
+            !files.iter().any(|f| f.starts_with("build")),
+            "should honor .gitignore: {:?}",
+            files
+        );
+    }
+
+    #[test]
+    fn honors_ignore_files_of_parent_directories() {
+        let files: Vec<PathBuf> = walk_project(&Path::new(PROJECT).join("app"), None).collect();
+        assert!(files.contains(&Path::new(PROJECT).join("app/main.py")));
+        assert!(
+            !files.iter().any(|f| f.ends_with("client.generated.py")),
+            "should honor the project's .gitignore: {:?}",
+            files
+        );
+    }
+
+    #[test]
+    fn can_weight_languages() {
+        let scan = ProjectScan::scan(Path::new(PROJECT), None);
+
+        assert_eq!(Some(&LangDependencyName::Python), scan.primary());
+        assert_eq!(
+            vec![
+                LanguageWeight {
+                    name: LangDependencyName::Python,
+                    files: 2,
+                    manifest: true,
+                },
+                LanguageWeight {
+                    name: LangDependencyName::NodeJS,
+                    files: 1,
+                    manifest: false,
+                },
+            ],
+            scan.languages()
+        );
+    }
+
+    #[test]
+    fn skips_vendored_manifests() {
+        let scan = ProjectScan::scan(Path::new("examples/scanner/manifest/vendored"), None);
+        assert_eq!(
+            vec![LangDependency {
+                name: LangDependencyName::NodeJS,
+                version: ">=12".into()
+            }],
+            scan.versions()
+        );
+    }
+
+    #[test]
+    fn leaves_out_stray_languages() {
+        let names = |dir: &str| -> Vec<LangDependencyName> {
+            ProjectScan::scan(Path::new(dir), None)
+                .significant()
+                .iter()
+                .map(|l| l.name.clone())
+                .collect()
+        };
+        assert_eq!(vec![LangDependencyName::Python], names(PROJECT));
+        assert_eq!(
+            vec![LangDependencyName::Go, LangDependencyName::Rust],
+            names("examples/scanner/new/mixed_go_rust")
+        );
+    }
+
+    #[test]
+    fn makefiles_need_c_sources() {
+        let go = ProjectScan::scan(Path::new("examples/scanner/new/go_makefile"), None);
+        assert_eq!(Some(&LangDependencyName::Go), go.primary());
+        assert_eq!(1, go.languages().len(), "{:?}", go.languages());
+
+        let c = ProjectScan::scan(Path::new("examples/scanner/new/c_makefile"), None);
+        assert_eq!(
+            vec![LanguageWeight {
+                name: LangDependencyName::Cpp,
+                files: 1,
+                manifest: true,
+            }],
+            c.languages()
+        );
+    }
+
+    #[test]
+    fn can_limit_depth() {
+        let scan = ProjectScan::scan(Path::new(PROJECT), Some(1));
+
+        assert_eq!(1, scan.languages().len());
+        assert_eq!(Some(&LangDependencyName::Python), scan.primary());
+        assert_eq!(0, scan.languages()[0].files);
+    }
+
+    #[test]
+    fn empty_project_has_no_primary_language() {
+        let scan = ProjectScan::scan(Path::new("examples/scanner/inventory"), None);
+        assert_eq!(None, scan.primary());
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Value};
+
+use crate::{strict::EXTENSION_PREFIX, OffSetup};
+
+/// Definitions deserialized from buffered input, through untagged enums or, for port and socket
+/// tables, read whole to tell their shape: they deny every unknown key, `x-` ones too
+const WITHOUT_EXTENSIONS: &[&str] = &["DetailedPackage", "PackageGroups", "Port", "Socket"];
+
+/// JSON Schema of offsetup.yml, generated from the configuration types.
+/// Like loading, it rejects unknown keys except those prefixed with `x-`.
+pub fn schema() -> Value {
+    let schema = SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<OffSetup>();
+    let mut schema = serde_json::to_value(schema).expect("schema is always representable as JSON");
+    allow_extensions(&mut schema);
+    for name in WITHOUT_EXTENSIONS {
+        if let Some(definition) = schema["definitions"][name].as_object_mut() {
+            definition.remove("patternProperties");
+        }
+    }
+    schema
+}
+
+/// `schema`, pretty printed as it is checked in
+pub fn schema_json() -> String {
+    format!(
+        "{}\n",
+        serde_json::to_string_pretty(&schema()).expect("schema is always representable as JSON")
+    )
+}
+
+/// Every key an environment variable can override, with the types of its value. Tables keyed by
+/// name, eg: `dependencies.platforms`, have a `<name>` segment in their keys.
+pub fn overridable_keys() -> Vec<(String, String)> {
+    let schema = schema();
+    let mut keys = BTreeMap::new();
+    collect_keys(&schema, &schema["definitions"], "", &mut keys);
+    // includes are resolved before the environment is read
+    keys.remove("include");
+    keys.into_iter()
+        .map(|(key, types)| (key, types.into_iter().collect::<Vec<_>>().join(" or ")))
+        .collect()
+}
+
+fn collect_keys(
+    schema: &Value,
+    definitions: &Value,
+    key: &str,
+    keys: &mut BTreeMap<String, BTreeSet<&'static str>>,
+) {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/definitions/");
+        return collect_keys(&definitions[name], definitions, key, keys);
+    }
+    if let Some(branches) = schema["anyOf"]
+        .as_array()
+        .or_else(|| schema["oneOf"].as_array())
+    {
+        for branch in branches {
+            collect_keys(branch, definitions, key, keys);
+        }
+        return;
+    }
+
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(kind) => vec![kind],
+        Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    let child = |name: &str| match key {
+        "" => name.to_string(),
+        key => format!("{}.{}", key, name),
+    };
+    for kind in types {
+        match kind {
+            "null" => {}
+            "object" => {
+                if let Some(properties) = schema["properties"].as_object() {
+                    for (name, property) in properties {
+                        let valid = name
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+                        if valid {
+                            collect_keys(property, definitions, &child(name), keys);
+                        }
+                    }
+                }
+                if schema["additionalProperties"].is_object() {
+                    let values = &schema["additionalProperties"];
+                    collect_keys(values, definitions, &child("<name>"), keys);
+                }
+            }
+            kind => {
+                let kind = match kind {
+                    "array" => "list",
+                    "boolean" => "boolean",
+                    "integer" => "integer",
+                    "number" => "number",
+                    _ => "string",
+                };
+                keys.entry(key.to_string()).or_default().insert(kind);
+            }
+        }
+    }
+}
+
+fn allow_extensions(schema: &mut Value) {
+    match schema {
+        Value::Object(object) => {
+            if object.get("additionalProperties") == Some(&Value::Bool(false)) {
+                object.insert(
+                    "patternProperties".into(),
+                    json!({ format!("^{}", EXTENSION_PREFIX): {} }),
+                );
+            }
+            object.values_mut().for_each(allow_extensions);
+        }
+        Value::Array(items) => items.iter_mut().for_each(allow_extensions),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Where the published schema is checked in, relative to the repository root
+    const SCHEMA_PATH: &str = "schema/offsetup.schema.json";
+
+    #[test]
+    fn checked_in_schema_is_up_to_date() {
+        let checked_in = fs::read_to_string(SCHEMA_PATH).unwrap_or_default();
+        assert!(
+            checked_in == schema_json(),
+            "{} is out of date with the configuration types, regenerate it with `offsetup schema > {}`",
+            SCHEMA_PATH,
+            SCHEMA_PATH
+        );
+    }
+
+    #[test]
+    fn lists_overridable_keys() {
+        let keys = overridable_keys();
+        let kind = |key: &str| {
+            keys.iter()
+                .find(|(other, _)| other == key)
+                .map(|(_, kind)| kind.as_str())
+        };
+
+        assert_eq!(
+            Some("boolean"),
+            kind("dependencies.platforms.<name>.skip_install")
+        );
+        assert_eq!(Some("list"), kind("exposes.ports.tcp"));
+        assert_eq!(Some("string"), kind("name"));
+        assert_eq!(
+            Some("string"),
+            kind("dependencies.platforms.<name>.source.download.uri")
+        );
+        assert_eq!(None, kind("include"));
+        assert_eq!(None, kind("dependencies"), "tables are not values");
+    }
+
+    #[test]
+    fn schema_describes_configuration() {
+        let schema = schema();
+        let system = &schema["definitions"]["System"];
+
+        assert_eq!(json!(["name", "version"]), schema["required"]);
+        assert_eq!(
+            "https://brew.sh",
+            system["properties"]["brew"]["description"]
+        );
+        assert_eq!(json!(false), system["additionalProperties"]);
+        assert_eq!(json!({ "^x-": {} }), system["patternProperties"]);
+    }
+}
@@ -0,0 +1,88 @@
+use super::{escape_xml, Restart, Service};
+
+/// Reverse domain labels are prefixed with
+const LABEL_PREFIX: &str = "io.offscale.offsetup";
+
+/// Label of the job running `service`, eg: io.offscale.offsetup.my-project
+pub fn label(service: &Service) -> String {
+    format!("{}.{}", LABEL_PREFIX, service.name)
+}
+
+/// The `.plist` of `service`, a daemon in /Library/LaunchDaemons or a `user` agent in
+/// ~/Library/LaunchAgents. launchd has no ordering between jobs,
+/// so `after` is left out: the service must wait for its applications itself. Agents run as
+/// the user who logged in, so they have no `UserName`.
+pub fn plist(service: &Service, user: bool) -> String {
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n",
+        "<dict>\n",
+    ));
+    let string = |out: &mut String, key: &str, value: &str| {
+        out.push_str(&format!(
+            "\t<key>{}</key>\n\t<string>{}</string>\n",
+            key,
+            escape_xml(value)
+        ))
+    };
+    string(&mut out, "Label", &label(service));
+
+    out.push_str("\t<key>ProgramArguments</key>\n\t<array>\n");
+    for arg in service.command(false) {
+        out.push_str(&format!("\t\t<string>{}</string>\n", escape_xml(&arg)));
+    }
+    out.push_str("\t</array>\n");
+    if let Some(dir) = &service.working_directory {
+        string(&mut out, "WorkingDirectory", dir);
+    }
+    if !service.environment.is_empty() {
+        out.push_str("\t<key>EnvironmentVariables</key>\n\t<dict>\n");
+        for (key, value) in &service.environment {
+            out.push_str(&format!(
+                "\t\t<key>{}</key>\n\t\t<string>{}</string>\n",
+                escape_xml(key),
+                escape_xml(value)
+            ));
+        }
+        out.push_str("\t</dict>\n");
+    }
+    if let (Some(name), false) = (&service.user, user) {
+        string(&mut out, "UserName", name);
+    }
+
+    out.push_str("\t<key>RunAtLoad</key>\n\t<true/>\n");
+    out.push_str("\t<key>KeepAlive</key>\n");
+    out.push_str(match service.restart {
+        Restart::No => "\t<false/>\n",
+        Restart::OnFailure => "\t<dict>\n\t\t<key>SuccessfulExit</key>\n\t\t<false/>\n\t</dict>\n",
+        Restart::Always => "\t<true/>\n",
+    });
+    out.push_str("</dict>\n</plist>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::tests::example;
+    use std::fs;
+
+    #[test]
+    fn renders_property_lists() {
+        let snapshot = |name: &str| fs::read_to_string(format!("examples/launchd/{}", name));
+        assert_eq!(
+            snapshot("io.offscale.offsetup.redis-config.plist").unwrap(),
+            plist(&example(), false)
+        );
+
+        let mut service = example();
+        service.restart = Restart::OnFailure;
+        service.shell = true;
+        service.args = vec!["redis-server --port 6379 > redis.log".into()];
+        assert_eq!(
+            snapshot("io.offscale.offsetup.redis-config.agent.plist").unwrap(),
+            plist(&service, true)
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::{fmt, io};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use super::systemd::systemctl;
+use crate::{
+    plan::{step, Plan, Step},
+    runner::CommandRunner,
+    scanning::platform::PlatformName,
+};
+
+/// Whether a service should be running once installed
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Started,
+    Stopped,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            State::Started => "started",
+            State::Stopped => "stopped",
+        })
+    }
+}
+
+/// How a service of the system packages should be left, what is not given is left as the
+/// package left it
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq)]
+#[schemars(deny_unknown_fields)]
+pub struct Desired {
+    /// Started on boot
+    pub enabled: Option<bool>,
+    pub state: Option<State>,
+}
+
+impl fmt::Display for Desired {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let enabled = self
+            .enabled
+            .map(|enabled| if enabled { "enabled" } else { "disabled" });
+        let state = self.state.map(|state| state.to_string());
+        let parts: Vec<&str> = enabled.into_iter().chain(state.as_deref()).collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// What the service manager reports of a service
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Status {
+    pub enabled: bool,
+    pub running: bool,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}, {}",
+            if self.enabled { "enabled" } else { "disabled" },
+            if self.running { "running" } else { "stopped" }
+        )
+    }
+}
+
+impl Status {
+    /// Whether `desired` holds
+    pub fn satisfies(&self, desired: &Desired) -> bool {
+        desired
+            .enabled
+            .is_none_or(|enabled| enabled == self.enabled)
+            && desired
+                .state
+                .is_none_or(|state| (state == State::Started) == self.running)
+    }
+}
+
+/// Enables and starts the daemons system packages install. Reading a status runs commands, the
+/// rest are steps of a plan.
+pub trait ServiceManager {
+    /// eg: systemd
+    fn name(&self) -> &'static str;
+    fn status(&self, service: &str, runner: &dyn CommandRunner) -> io::Result<Status>;
+    fn enable(&self, service: &str) -> Step;
+    fn disable(&self, service: &str) -> Step;
+    fn start(&self, service: &str) -> Step;
+    fn stop(&self, service: &str) -> Step;
+}
+
+/// Directory runit supervises the services linked into
+const RUNIT_SERVICES: &str = "/var/service";
+
+/// Runlevel sysvinit starts services of on Debian
+const SYSV_RUNLEVEL_DIR: &str = "/etc/rc2.d";
+
+pub struct Systemd;
+pub struct OpenRc;
+pub struct Runit;
+/// Services enabled with chkconfig on Red Hat, update-rc.d elsewhere
+pub struct SysVinit {
+    pub chkconfig: bool,
+}
+/// Services are the labels of the property lists in /Library/LaunchDaemons
+pub struct Launchctl;
+pub struct Sc;
+
+/// The service manager of `platform`. Linux distributions may run any of several, so the one
+/// running is found by the directory it creates at boot.
+pub fn detect(
+    platform: &PlatformName,
+    runner: &dyn CommandRunner,
+) -> Option<Box<dyn ServiceManager>> {
+    let exists = |dir: &str| {
+        runner
+            .run("test", &["-d", dir])
+            .is_ok_and(|output| output.success)
+    };
+    match platform {
+        PlatformName::MacOSX => Some(Box::new(Launchctl)),
+        PlatformName::Windows => Some(Box::new(Sc)),
+        PlatformName::Unknown => None,
+        _ if exists("/run/systemd/system") => Some(Box::new(Systemd)),
+        _ if exists("/run/openrc") => Some(Box::new(OpenRc)),
+        _ if exists("/run/runit") => Some(Box::new(Runit)),
+        PlatformName::CentOS | PlatformName::Redhat => Some(Box::new(SysVinit { chkconfig: true })),
+        _ => Some(Box::new(SysVinit { chkconfig: false })),
+    }
+}
+
+/// Steps leaving each of `services` as desired, from the status `manager` reports
+pub fn converge(
+    manager: &dyn ServiceManager,
+    services: &[(&str, &Desired)],
+    runner: &dyn CommandRunner,
+) -> io::Result<Plan> {
+    let mut plan = Plan::default();
+    for (service, desired) in services {
+        let status = manager.status(service, runner)?;
+        match desired.enabled {
+            Some(true) if !status.enabled => plan.steps.push(manager.enable(service)),
+            Some(false) if status.enabled => plan.steps.push(manager.disable(service)),
+            _ => {}
+        }
+        match desired.state {
+            Some(State::Started) if !status.running => plan.steps.push(manager.start(service)),
+            Some(State::Stopped) if status.running => plan.steps.push(manager.stop(service)),
+            _ => {}
+        }
+    }
+    Ok(plan)
+}
+
+/// Whether running `program args` succeeds. Failing to run it at all is an error, as the
+/// manager is missing.
+fn succeeds(runner: &dyn CommandRunner, program: &str, args: &[&str]) -> io::Result<bool> {
+    Ok(runner.run(program, args)?.success)
+}
+
+/// Output of `program args`, empty when it fails
+fn stdout(runner: &dyn CommandRunner, program: &str, args: &[&str]) -> io::Result<String> {
+    let output = runner.run(program, args)?;
+    Ok(if output.success {
+        output.stdout
+    } else {
+        String::new()
+    })
+}
+
+impl ServiceManager for Systemd {
+    fn name(&self) -> &'static str {
+        "systemd"
+    }
+
+    fn status(&self, service: &str, runner: &dyn CommandRunner) -> io::Result<Status> {
+        Ok(Status {
+            enabled: stdout(runner, "systemctl", &["is-enabled", service])?.trim() == "enabled",
+            running: stdout(runner, "systemctl", &["is-active", service])?.trim() == "active",
+        })
+    }
+
+    fn enable(&self, service: &str) -> Step {
+        systemctl(false, &["enable", service], false)
+    }
+
+    fn disable(&self, service: &str) -> Step {
+        systemctl(false, &["disable", service], false)
+    }
+
+    fn start(&self, service: &str) -> Step {
+        systemctl(false, &["start", service], false)
+    }
+
+    fn stop(&self, service: &str) -> Step {
+        systemctl(false, &["stop", service], false)
+    }
+}
+
+impl ServiceManager for OpenRc {
+    fn name(&self) -> &'static str {
+        "openrc"
+    }
+
+    fn status(&self, service: &str, runner: &dyn CommandRunner) -> io::Result<Status> {
+        // lines of `rc-update show` are "  redis | default"
+        let enabled = stdout(runner, "rc-update", &["show", "default"])?
+            .lines()
+            .any(|line| line.split('|').next().map(str::trim) == Some(service));
+        Ok(Status {
+            enabled,
+            running: succeeds(runner, "rc-service", &[service, "status"])?,
+        })
+    }
+
+    fn enable(&self, service: &str) -> Step {
+        step(&["rc-update", "add", service, "default"], false)
+    }
+
+    fn disable(&self, service: &str) -> Step {
+        step(&["rc-update", "del", service, "default"], false)
+    }
+
+    fn start(&self, service: &str) -> Step {
+        step(&["rc-service", service, "start"], false)
+    }
+
+    fn stop(&self, service: &str) -> Step {
+        step(&["rc-service", service, "stop"], false)
+    }
+}
+
+impl ServiceManager for Runit {
+    fn name(&self) -> &'static str {
+        "runit"
+    }
+
+    /// Services are enabled by linking them into the supervised directory
+    fn status(&self, service: &str, runner: &dyn CommandRunner) -> io::Result<Status> {
+        let link = format!("{}/{}", RUNIT_SERVICES, service);
+        Ok(Status {
+            enabled: succeeds(runner, "test", &["-e", &link])?,
+            running: stdout(runner, "sv", &["status", service])?.starts_with("run:"),
+        })
+    }
+
+    fn enable(&self, service: &str) -> Step {
+        let link = format!("{}/{}", RUNIT_SERVICES, service);
+        step(&["ln", "-s", &format!("/etc/sv/{}", service), &link], false)
+    }
+
+    fn disable(&self, service: &str) -> Step {
+        step(&["rm", &format!("{}/{}", RUNIT_SERVICES, service)], false)
+    }
+
+    fn start(&self, service: &str) -> Step {
+        step(&["sv", "up", service], false)
+    }
+
+    fn stop(&self, service: &str) -> Step {
+        step(&["sv", "down", service], false)
+    }
+}
+
+impl ServiceManager for SysVinit {
+    fn name(&self) -> &'static str {
+        "sysvinit"
+    }
+
+    /// update-rc.d has no query, so links starting the service in the default runlevel are looked
+    /// for instead, eg: S01redis-server
+    fn status(&self, service: &str, runner: &dyn CommandRunner) -> io::Result<Status> {
+        let enabled = if self.chkconfig {
+            succeeds(runner, "chkconfig", &[service])?
+        } else {
+            stdout(runner, "ls", &[SYSV_RUNLEVEL_DIR])?
+                .split_whitespace()
+                .any(|link| {
+                    link.strip_prefix('S')
+                        .map(|rest| rest.trim_start_matches(|c: char| c.is_ascii_digit()))
+                        == Some(service)
+                })
+        };
+        Ok(Status {
+            enabled,
+            running: succeeds(runner, "service", &[service, "status"])?,
+        })
+    }
+
+    fn enable(&self, service: &str) -> Step {
+        if self.chkconfig {
+            step(&["chkconfig", service, "on"], false)
+        } else {
+            step(&["update-rc.d", service, "enable"], false)
+        }
+    }
+
+    fn disable(&self, service: &str) -> Step {
+        if self.chkconfig {
+            step(&["chkconfig", service, "off"], false)
+        } else {
+            step(&["update-rc.d", service, "disable"], false)
+        }
+    }
+
+    fn start(&self, service: &str) -> Step {
+        step(&["service", service, "start"], false)
+    }
+
+    fn stop(&self, service: &str) -> Step {
+        step(&["service", service, "stop"], false)
+    }
+}
+
+impl ServiceManager for Launchctl {
+    fn name(&self) -> &'static str {
+        "launchctl"
+    }
+
+    /// `print-disabled` lists overrides as `"label" => disabled`, or `=> true` before macOS 11
+    fn status(&self, service: &str, runner: &dyn CommandRunner) -> io::Result<Status> {
+        let label = format!("\"{}\" =>", service);
+        let disabled = stdout(runner, "launchctl", &["print-disabled", "system"])?
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix(label.as_str()))
+            .any(|value| ["disabled", "true"].contains(&value.trim()));
+        let target = format!("system/{}", service);
+        Ok(Status {
+            enabled: !disabled,
+            running: stdout(runner, "launchctl", &["print", &target])?.contains("state = running"),
+        })
+    }
+
+    fn enable(&self, service: &str) -> Step {
+        step(
+            &["launchctl", "enable", &format!("system/{}", service)],
+            false,
+        )
+    }
+
+    fn disable(&self, service: &str) -> Step {
+        step(
+            &["launchctl", "disable", &format!("system/{}", service)],
+            false,
+        )
+    }
+
+    fn start(&self, service: &str) -> Step {
+        let plist = format!("/Library/LaunchDaemons/{}.plist", service);
+        step(&["launchctl", "bootstrap", "system", &plist], false)
+    }
+
+    fn stop(&self, service: &str) -> Step {
+        step(
+            &["launchctl", "bootout", &format!("system/{}", service)],
+            false,
+        )
+    }
+}
+
+impl ServiceManager for Sc {
+    fn name(&self) -> &'static str {
+        "sc"
+    }
+
+    fn status(&self, service: &str, runner: &dyn CommandRunner) -> io::Result<Status> {
+        Ok(Status {
+            enabled: stdout(runner, "sc.exe", &["qc", service])?.contains("AUTO_START"),
+            running: stdout(runner, "sc.exe", &["query", service])?.contains("RUNNING"),
+        })
+    }
+
+    fn enable(&self, service: &str) -> Step {
+        step(&["sc.exe", "config", service, "start=", "auto"], false)
+    }
+
+    /// Left to be started by hand rather than disabled, so `state: started` still works
+    fn disable(&self, service: &str) -> Step {
+        step(&["sc.exe", "config", service, "start=", "demand"], false)
+    }
+
+    fn start(&self, service: &str) -> Step {
+        step(&["sc.exe", "start", service], false)
+    }
+
+    fn stop(&self, service: &str) -> Step {
+        step(&["sc.exe", "stop", service], false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::fake::FakeRunner;
+
+    fn started() -> Desired {
+        Desired {
+            enabled: Some(true),
+            state: Some(State::Started),
+        }
+    }
+
+    fn steps(plan: &Plan) -> Vec<String> {
+        plan.steps.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn detects_managers() {
+        let systemd = FakeRunner::new().respond("test -d /run/systemd/system", "");
+        let name = |platform: PlatformName, runner: &FakeRunner| {
+            detect(&platform, runner).map(|manager| manager.name())
+        };
+        assert_eq!(Some("systemd"), name(PlatformName::Debian, &systemd));
+        let openrc = FakeRunner::new()
+            .fail("test -d /run/systemd/system", "")
+            .respond("test -d /run/openrc", "");
+        assert_eq!(Some("openrc"), name(PlatformName::Ubuntu, &openrc));
+        assert_eq!(
+            Some("sysvinit"),
+            name(PlatformName::CentOS, &FakeRunner::new())
+        );
+        assert_eq!(Some("launchctl"), name(PlatformName::MacOSX, &systemd));
+        assert_eq!(Some("sc"), name(PlatformName::Windows, &systemd));
+        assert_eq!(None, name(PlatformName::Unknown, &systemd));
+    }
+
+    #[test]
+    fn starts_what_debian_started_and_rhel_did_not() {
+        let services = [("redis-server", &started())];
+        let debian = FakeRunner::new()
+            .respond("systemctl is-enabled redis-server", "enabled\n")
+            .respond("systemctl is-active redis-server", "active\n");
+        assert_eq!(
+            Vec::<String>::new(),
+            steps(&converge(&Systemd, &services, &debian).unwrap())
+        );
+
+        let rhel = FakeRunner::new()
+            .fail("systemctl is-enabled redis-server", "disabled")
+            .fail("systemctl is-active redis-server", "inactive");
+        assert_eq!(
+            vec![
+                "systemctl enable redis-server",
+                "systemctl start redis-server"
+            ],
+            steps(&converge(&Systemd, &services, &rhel).unwrap())
+        );
+
+        let stopped = Desired {
+            enabled: Some(false),
+            state: Some(State::Stopped),
+        };
+        assert_eq!(
+            vec![
+                "systemctl disable redis-server",
+                "systemctl stop redis-server"
+            ],
+            steps(&converge(&Systemd, &[("redis-server", &stopped)], &debian).unwrap())
+        );
+        assert!(converge(&Systemd, &services, &FakeRunner::new()).is_err());
+    }
+
+    #[test]
+    fn reads_the_status_of_every_manager() {
+        let runner = FakeRunner::new()
+            .respond("rc-update show default", "  local | default\n  redis | default\n")
+            .fail("rc-service redis status", " * status: stopped")
+            .fail("test -e /var/service/redis", "")
+            .respond("sv status redis", "run: redis: (pid 123) 4s\n")
+            .respond("chkconfig redis", "")
+            .respond("ls /etc/rc2.d", "README\nS01redis-server\nS02ssh\n")
+            .respond("service redis status", "redis is running")
+            .respond("service redis-server status", "")
+            .respond(
+                "launchctl print-disabled system",
+                "disabled services = {\n\t\"com.openssh.sshd\" => disabled\n\t\"redis\" => enabled\n}\n",
+            )
+            .respond("launchctl print system/redis", "\tstate = running\n")
+            .respond("sc.exe qc redis", "        START_TYPE         : 3   DEMAND_START\n")
+            .respond("sc.exe query redis", "        STATE              : 4  RUNNING\n");
+        let status = |manager: &dyn ServiceManager, service: &str| {
+            manager.status(service, &runner).unwrap().to_string()
+        };
+        assert_eq!("enabled, stopped", status(&OpenRc, "redis"));
+        assert_eq!("disabled, running", status(&Runit, "redis"));
+        assert_eq!(
+            "enabled, running",
+            status(&SysVinit { chkconfig: true }, "redis")
+        );
+        assert_eq!(
+            "enabled, running",
+            status(&SysVinit { chkconfig: false }, "redis-server")
+        );
+        assert_eq!("enabled, running", status(&Launchctl, "redis"));
+        assert_eq!("disabled, running", status(&Sc, "redis"));
+
+        assert_eq!(
+            "ln -s /etc/sv/redis /var/service/redis",
+            Runit.enable("redis").to_string()
+        );
+        assert_eq!(
+            "update-rc.d redis enable",
+            SysVinit { chkconfig: false }.enable("redis").to_string()
+        );
+        assert_eq!(
+            "launchctl bootstrap system /Library/LaunchDaemons/redis.plist",
+            Launchctl.start("redis").to_string()
+        );
+        assert_eq!(
+            "sc.exe config redis start= demand",
+            Sc.disable("redis").to_string()
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub mod launchd;
+pub mod manager;
+pub mod systemd;
+pub mod windows;
+
+/// When the service manager starts the service again after it exited
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Restart {
+    No,
+    #[default]
+    OnFailure,
+    Always,
+}
+
+impl fmt::Display for Restart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Restart::No => "no",
+            Restart::OnFailure => "on-failure",
+            Restart::Always => "always",
+        })
+    }
+}
+
+/// A long running process of the project, as service managers run it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Service {
+    /// Of the unit, eg: my-project
+    pub name: String,
+    pub description: String,
+    /// Program and arguments, or the command line alone when run through the shell
+    pub args: Vec<String>,
+    pub shell: bool,
+    pub working_directory: Option<String>,
+    pub environment: Vec<(String, String)>,
+    pub user: Option<String>,
+    pub restart: Restart,
+    /// Services started before this one, eg: postgresql
+    pub after: Vec<String>,
+}
+
+impl Service {
+    /// Program and arguments on Windows or on unix-like platforms
+    pub fn command(&self, windows: bool) -> Vec<String> {
+        match (self.shell, windows) {
+            (true, true) => ["cmd.exe", "/C"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .chain(self.args.iter().cloned())
+                .collect(),
+            (true, false) => ["/bin/sh", "-c"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .chain(self.args.iter().cloned())
+                .collect(),
+            (false, _) => self.args.clone(),
+        }
+    }
+}
+
+/// `text` with the characters XML gives a meaning to escaped
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// `project` reduced to the characters service names allow, eg: my-project
+pub fn name(project: &str) -> String {
+    let name: String = project
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    name.split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// The service of the examples
+    pub fn example() -> Service {
+        Service {
+            name: "redis-config".into(),
+            description: "redis-config 5.0.4".into(),
+            args: vec![
+                "/usr/bin/redis-server".into(),
+                "--protected-mode".into(),
+                "no".into(),
+                "--requirepass".into(),
+                "p@ss word%".into(),
+            ],
+            shell: false,
+            working_directory: Some("/var/lib/redis".into()),
+            environment: vec![
+                ("GREETING".into(), "hello \"world\"".into()),
+                (
+                    "RDBMS_URI".into(),
+                    "postgresql://app@localhost:5432/app".into(),
+                ),
+            ],
+            user: Some("redis".into()),
+            restart: Restart::Always,
+            after: vec!["postgresql".into(), "redis".into()],
+        }
+    }
+
+    #[test]
+    fn names_services_after_projects() {
+        assert_eq!("my-project-2", name("My Project (2)"));
+        assert_eq!("redis-config", name("redis-config"));
+    }
+
+    #[test]
+    fn runs_shell_commands_with_the_platform_shell() {
+        let mut service = example();
+        assert_eq!(service.args, service.command(true));
+        service.shell = true;
+        service.args = vec!["redis-server > redis.log".into()];
+        assert_eq!(
+            vec!["/bin/sh", "-c", "redis-server > redis.log"],
+            service.command(false)
+        );
+        assert_eq!(
+            vec!["cmd.exe", "/C", "redis-server > redis.log"],
+            service.command(true)
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use super::Service;
+use crate::plan::{step, Plan, Step};
+
+/// File name of the unit running `service`
+pub fn unit_name(service: &Service) -> String {
+    format!("{}.service", service.name)
+}
+
+/// Where units are installed: system wide, or for the current user only
+pub fn unit_dir(user: bool) -> PathBuf {
+    if !user {
+        return PathBuf::from("/etc/systemd/system");
+    }
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default()
+            .join(".config"),
+    }
+    .join("systemd/user")
+}
+
+/// File of the environment of `service`, next to its unit in `dir`
+pub fn environment_path(service: &Service, dir: &Path) -> PathBuf {
+    dir.join(format!("{}.env", service.name))
+}
+
+/// The `.service` unit of `service`, installed in `dir`. User managers neither order against
+/// system units nor switch users, so user units leave out `After=` services and `User=`.
+pub fn unit(service: &Service, user: bool, dir: &Path) -> String {
+    let mut out = String::from("[Unit]\n");
+    out.push_str(&format!("Description={}\n", service.description));
+    if !user {
+        let after: Vec<String> = ["network.target".to_string()]
+            .iter()
+            .cloned()
+            .chain(service.after.iter().map(|name| format!("{}.service", name)))
+            .collect();
+        out.push_str(&format!("After={}\n", after.join(" ")));
+    }
+
+    out.push_str("\n[Service]\n");
+    let args: Vec<String> = service
+        .command(false)
+        .iter()
+        .map(|arg| quote(arg))
+        .collect();
+    out.push_str(&format!("ExecStart={}\n", args.join(" ")));
+    if let Some(dir) = &service.working_directory {
+        out.push_str(&format!("WorkingDirectory={}\n", escape(dir)));
+    }
+    // values may be secrets, which the world readable unit would show
+    if !service.environment.is_empty() {
+        out.push_str(&format!(
+            "EnvironmentFile={}\n",
+            quote(&environment_path(service, dir).to_string_lossy())
+        ));
+    }
+    if let (Some(name), false) = (&service.user, user) {
+        out.push_str(&format!("User={}\n", name));
+    }
+    out.push_str(&format!("Restart={}\n", service.restart));
+
+    out.push_str("\n[Install]\n");
+    let target = if user {
+        "default.target"
+    } else {
+        "multi-user.target"
+    };
+    out.push_str(&format!("WantedBy={}\n", target));
+    out
+}
+
+/// `KEY="value"` lines of the environment of `service`, as `EnvironmentFile=` reads them
+pub fn environment(service: &Service) -> String {
+    service
+        .environment
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('$', "\\$")
+                .replace('`', "\\`");
+            format!("{}=\"{}\"\n", key, value)
+        })
+        .collect()
+}
+
+/// Write the unit of `service` and its environment to `dir`, then enable it so it starts on boot
+pub fn install(service: &Service, user: bool, dir: &Path) -> Plan {
+    let unit_name = unit_name(service);
+    let private_files = if service.environment.is_empty() {
+        vec![]
+    } else {
+        vec![(environment_path(service, dir), environment(service))]
+    };
+    Plan {
+        files: vec![(dir.join(&unit_name), unit(service, user, dir))],
+        private_files,
+        steps: vec![
+            systemctl(user, &["daemon-reload"], false),
+            systemctl(user, &["enable", &unit_name], false),
+        ],
+    }
+}
+
+/// Disable and delete the unit `install` wrote
+pub fn uninstall(service: &Service, user: bool, dir: &Path) -> Plan {
+    let unit_name = unit_name(service);
+    let path = dir.join(&unit_name);
+    let environment = environment_path(service, dir);
+    Plan {
+        files: vec![],
+        private_files: vec![],
+        steps: vec![
+            systemctl(user, &["disable", &unit_name], true),
+            step(
+                &[
+                    "rm",
+                    "-f",
+                    &path.to_string_lossy(),
+                    &environment.to_string_lossy(),
+                ],
+                false,
+            ),
+            systemctl(user, &["daemon-reload"], false),
+        ],
+    }
+}
+
+/// `systemctl args`, on the user's manager when `user`
+pub(super) fn systemctl(user: bool, args: &[&str], may_fail: bool) -> Step {
+    let mut all = vec!["systemctl"];
+    if user {
+        all.push("--user");
+    }
+    all.extend(args);
+    step(&all, may_fail)
+}
+
+/// `%` starts a specifier and `$` a variable in unit files, so both are doubled
+fn escape(value: &str) -> String {
+    value.replace('%', "%%").replace('$', "$$")
+}
+
+/// A word of a command line or an assignment, quoted when it has to be
+fn quote(word: &str) -> String {
+    let word = escape(word);
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c)) {
+        return word;
+    }
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::tests::example;
+    use std::fs;
+
+    #[test]
+    fn renders_units() {
+        let snapshot = |name: &str| fs::read_to_string(format!("examples/systemd/{}", name));
+        assert_eq!(
+            snapshot("redis-config.service").unwrap(),
+            unit(&example(), false, Path::new("/etc/systemd/system"))
+        );
+        assert_eq!(
+            snapshot("redis-config.user.service").unwrap(),
+            unit(
+                &example(),
+                true,
+                Path::new("/home/dev/.config/systemd/user")
+            )
+        );
+        assert_eq!(
+            snapshot("redis-config.env").unwrap(),
+            environment(&example())
+        );
+
+        let mut service = example();
+        service.environment = vec![("PASSWORD".into(), "$ecret`\\".into())];
+        assert_eq!("PASSWORD=\"\\$ecret\\`\\\\\"\n", environment(&service));
+        service.environment = vec![];
+        assert!(
+            !unit(&service, false, Path::new("/etc/systemd/system")).contains("EnvironmentFile")
+        );
+    }
+
+    #[test]
+    fn installs_and_enables_units() {
+        let dir = Path::new("/etc/systemd/system");
+        let plan = install(&example(), false, dir);
+        assert_eq!(
+            vec![(
+                dir.join("redis-config.service"),
+                unit(&example(), false, dir)
+            )],
+            plan.files
+        );
+        assert_eq!(
+            vec![(dir.join("redis-config.env"), environment(&example()))],
+            plan.private_files
+        );
+        let steps =
+            |plan: &Plan| -> Vec<String> { plan.steps.iter().map(ToString::to_string).collect() };
+        assert_eq!(
+            vec![
+                "systemctl daemon-reload",
+                "systemctl enable redis-config.service"
+            ],
+            steps(&plan)
+        );
+
+        let dir = Path::new("/home/dev/.config/systemd/user");
+        assert_eq!(
+            vec![
+                "systemctl --user disable redis-config.service",
+                "rm -f /home/dev/.config/systemd/user/redis-config.service /home/dev/.config/systemd/user/redis-config.env",
+                "systemctl --user daemon-reload"
+            ],
+            steps(&uninstall(&example(), true, dir))
+        );
+    }
+}
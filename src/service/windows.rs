@@ -0,0 +1,249 @@
+use super::{escape_xml, Restart, Service};
+
+/// Delay before the service control manager starts a failed service again, in milliseconds
+const RESTART_DELAY: u32 = 5000;
+
+/// `sc.exe` commands creating `service`, as a batch script. The service control manager has no
+/// working directory or environment of its own: the environment is written to the service's
+/// registry key, and the working directory needs the wrapper of `winsw_xml`.
+pub fn sc_script(service: &Service) -> String {
+    let name = &service.name;
+    let mut out = String::from("@echo off\n");
+    let mut create = vec![
+        "sc.exe".to_string(),
+        "create".into(),
+        name.clone(),
+        "binPath=".into(),
+        quote(&command_line(&service.command(true))),
+        "start=".into(),
+        "auto".into(),
+        "DisplayName=".into(),
+        quote(&service.description),
+    ];
+    if !service.after.is_empty() {
+        create.extend(vec!["depend=".into(), quote(&service.after.join("/"))]);
+    }
+    if let Some(user) = &service.user {
+        create.extend(vec!["obj=".into(), quote(&account(user))]);
+    }
+    out.push_str(&batch(&create));
+    out.push_str(&batch(&[
+        "sc.exe".into(),
+        "description".into(),
+        name.clone(),
+        quote(&service.description),
+    ]));
+    if service.restart != Restart::No {
+        let action = format!("restart/{}", RESTART_DELAY);
+        out.push_str(&batch(&[
+            "sc.exe".into(),
+            "failure".into(),
+            name.clone(),
+            "reset=".into(),
+            "86400".into(),
+            "actions=".into(),
+            vec![action; 3].join("/"),
+        ]));
+    }
+    if service.restart == Restart::Always {
+        // also restart after exiting with an error, rather than crashing
+        out.push_str(&batch(&[
+            "sc.exe".into(),
+            "failureflag".into(),
+            name.clone(),
+            "1".into(),
+        ]));
+    }
+    if !service.environment.is_empty() {
+        let variables: Vec<String> = service
+            .environment
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        out.push_str(&batch(&[
+            "reg.exe".into(),
+            "add".into(),
+            quote(&format!(
+                "HKLM\\SYSTEM\\CurrentControlSet\\Services\\{}",
+                name
+            )),
+            "/v".into(),
+            "Environment".into(),
+            "/t".into(),
+            "REG_MULTI_SZ".into(),
+            "/d".into(),
+            quote(&variables.join("\\0")),
+            "/f".into(),
+        ]));
+    }
+    if let Some(dir) = &service.working_directory {
+        out.push_str(&format!(
+            "rem sc.exe cannot set the working directory {}, install with the XML wrapper instead\n",
+            dir.replace('%', "%%")
+        ));
+    }
+    out
+}
+
+/// The XML of service wrappers such as WinSW, which run any command as a service
+pub fn winsw_xml(service: &Service) -> String {
+    let command = service.command(true);
+    let mut out = String::from("<service>\n");
+    let element = |out: &mut String, name: &str, value: &str| {
+        out.push_str(&format!("  <{}>{}</{}>\n", name, escape_xml(value), name))
+    };
+    element(&mut out, "id", &service.name);
+    element(&mut out, "name", &service.name);
+    element(&mut out, "description", &service.description);
+    element(&mut out, "executable", &command[0]);
+    if command.len() > 1 {
+        element(&mut out, "arguments", &command_line(&command[1..]));
+    }
+    if let Some(dir) = &service.working_directory {
+        element(&mut out, "workingdirectory", dir);
+    }
+    for (key, value) in &service.environment {
+        out.push_str(&format!(
+            "  <env name=\"{}\" value=\"{}\"/>\n",
+            escape_xml(key),
+            escape_xml(value)
+        ));
+    }
+    if let Some(user) = &service.user {
+        out.push_str("  <serviceaccount>\n");
+        out.push_str(&format!(
+            "    <username>{}</username>\n",
+            escape_xml(&account(user))
+        ));
+        out.push_str("  </serviceaccount>\n");
+    }
+    for name in &service.after {
+        element(&mut out, "depend", name);
+    }
+    element(&mut out, "startmode", "Automatic");
+    if service.restart != Restart::No {
+        out.push_str(&format!(
+            "  <onfailure action=\"restart\" delay=\"{} sec\"/>\n",
+            RESTART_DELAY / 1000
+        ));
+    }
+    out.push_str("</service>\n");
+    out
+}
+
+/// Local accounts are written .\name, domain accounts as given
+fn account(user: &str) -> String {
+    if user.contains('\\') || user.contains('@') {
+        user.into()
+    } else {
+        format!(".\\{}", user)
+    }
+}
+
+/// Arguments joined as Windows programs split them back
+fn command_line(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"') {
+                return arg.clone();
+            }
+            let mut quoted = String::from("\"");
+            let mut backslashes = 0;
+            for c in arg.chars() {
+                match c {
+                    '\\' => backslashes += 1,
+                    '"' => {
+                        quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                        backslashes = 0;
+                    }
+                    _ => {
+                        quoted.push_str(&"\\".repeat(backslashes));
+                        backslashes = 0;
+                    }
+                }
+                if c != '\\' {
+                    quoted.push(c);
+                }
+            }
+            quoted.push_str(&"\\".repeat(backslashes * 2));
+            quoted.push('"');
+            quoted
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One argument of a batch line, quoted with inner quotes escaped
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('"', "\\\""))
+}
+
+/// A batch script line, with `%` doubled so variables are not expanded
+fn batch(args: &[String]) -> String {
+    format!("{}\n", args.join(" ").replace('%', "%%"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::tests;
+    use std::fs;
+
+    fn snapshot(name: &str) -> String {
+        fs::read_to_string(format!("examples/windows/{}", name)).unwrap()
+    }
+
+    fn example() -> Service {
+        let mut service = tests::example();
+        service.args = vec![
+            "C:\\Program Files\\Redis\\redis-server.exe".into(),
+            "--requirepass".into(),
+            "p@ss word%".into(),
+        ];
+        service.working_directory = Some("C:\\Redis".into());
+        service
+    }
+
+    #[test]
+    fn renders_sc_commands() {
+        assert_eq!(snapshot("redis-config.cmd"), sc_script(&example()));
+        let mut service = example();
+        service.restart = Restart::No;
+        service.environment = vec![];
+        service.working_directory = None;
+        service.after = vec![];
+        service.user = None;
+        assert_eq!(
+            concat!(
+                "@echo off\n",
+                "sc.exe create redis-config binPath= \"\\\"C:\\Program Files\\Redis\\redis-server.exe\\\" --requirepass \\\"p@ss word%%\\\"\" start= auto DisplayName= \"redis-config 5.0.4\"\n",
+                "sc.exe description redis-config \"redis-config 5.0.4\"\n",
+            ),
+            sc_script(&service)
+        );
+    }
+
+    #[test]
+    fn renders_wrapper_xml() {
+        assert_eq!(snapshot("redis-config.xml"), winsw_xml(&example()));
+    }
+
+    #[test]
+    fn quotes_arguments_as_windows_splits_them() {
+        let args: Vec<String> = vec![
+            "C:\\Program Files\\Redis\\redis-server.exe".into(),
+            "say \"hi\"".into(),
+            "C:\\dir\\".into(),
+            "".into(),
+            "plain".into(),
+        ];
+        assert_eq!(
+            r#""C:\Program Files\Redis\redis-server.exe" "say \"hi\"" C:\dir\ "" plain"#,
+            command_line(&args)
+        );
+        assert_eq!(
+            r#""C:\dir with space\\""#,
+            command_line(&["C:\\dir with space\\".into()])
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Where offsetup keeps what it needs between runs: `$OFFSETUP_STATE_DIR`, defaulting to `~/.offsetup`
+pub fn state_dir() -> PathBuf {
+    match env::var_os("OFFSETUP_STATE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(PathBuf::from)
+            .unwrap_or_default()
+            .join(".offsetup"),
+    }
+}
+
+/// Sharable system packages installed by offsetup, with the projects using each one, so that
+/// uninstalling a project only removes the shared packages no other project still needs
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SharedPackages {
+    /// names of the projects using each package, by package manager then package name
+    managers: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl SharedPackages {
+    /// `shared.json` in the `state_dir`
+    pub fn default_path() -> PathBuf {
+        state_dir().join("shared.json")
+    }
+
+    /// Read the store at `path`, empty when nothing was installed yet
+    pub fn load(path: &Path) -> io::Result<SharedPackages> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(SharedPackages::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, content)
+    }
+
+    /// Record that `project` uses `package`
+    pub fn register(&mut self, manager: &str, package: &str, project: &str) {
+        self.managers
+            .entry(manager.into())
+            .or_default()
+            .entry(package.into())
+            .or_default()
+            .insert(project.into());
+    }
+
+    /// Whether some project uses `package`, so offsetup installed it
+    pub fn is_registered(&self, manager: &str, package: &str) -> bool {
+        self.managers
+            .get(manager)
+            .is_some_and(|packages| packages.contains_key(package))
+    }
+
+    /// Forget that `project` uses `package`, returning how many projects still use it.
+    /// None when `project` was not recorded as using it, eg: it was installed before offsetup.
+    pub fn release(&mut self, manager: &str, package: &str, project: &str) -> Option<usize> {
+        let packages = self.managers.get_mut(manager)?;
+        let projects = packages.get_mut(package)?;
+        if !projects.remove(project) {
+            return None;
+        }
+        let remaining = projects.len();
+        if remaining == 0 {
+            packages.remove(package);
+        }
+        if packages.is_empty() {
+            self.managers.remove(manager);
+        }
+        Some(remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_count_package_users() {
+        let mut shared = SharedPackages::default();
+        shared.register("apt", "cmake", "redis");
+        shared.register("apt", "cmake", "postgres");
+        shared.register("apt", "cmake", "redis");
+
+        assert_eq!(None, shared.release("brew", "cmake", "redis"));
+        assert_eq!(Some(1), shared.release("apt", "cmake", "redis"));
+        assert_eq!(None, shared.release("apt", "cmake", "redis"));
+        assert_eq!(Some(0), shared.release("apt", "cmake", "postgres"));
+        assert_eq!(SharedPackages::default(), shared);
+    }
+
+    #[test]
+    fn can_save_and_load() {
+        let path = env::temp_dir()
+            .join("offsetup-shared-test")
+            .join("shared.json");
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            SharedPackages::default(),
+            SharedPackages::load(&path).unwrap()
+        );
+
+        let mut shared = SharedPackages::default();
+        shared.register("brew", "python@2", "simple");
+        shared.save(&path).unwrap();
+
+        assert_eq!(shared, SharedPackages::load(&path).unwrap());
+    }
+}
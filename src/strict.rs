@@ -0,0 +1,162 @@
+use config::{Config, ConfigError};
+use serde::de::DeserializeOwned;
+use serde_ignored::Path;
+use yaml_rust::{parser::Parser, Event};
+
+/// Keys starting with this prefix hold custom metadata. They are accepted anywhere and ignored.
+pub const EXTENSION_PREFIX: &str = "x-";
+
+/// A key present in the configuration that no field of the schema accepts
+#[derive(Debug, PartialEq)]
+pub struct UnknownKey {
+    pub path: Vec<String>,
+    /// 1-based line and column of the key, when the YAML source is known
+    pub location: Option<(usize, usize)>,
+}
+
+fn segments(path: &Path) -> Vec<String> {
+    match path {
+        Path::Root => vec![],
+        Path::Seq { parent, index } => {
+            let mut segments = segments(parent);
+            segments.push(index.to_string());
+            segments
+        }
+        Path::Map { parent, key } => {
+            let mut segments = segments(parent);
+            segments.push(key.clone());
+            segments
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => segments(parent),
+    }
+}
+
+/// Deserialize the merged configuration, rejecting every key the schema would silently drop.
+/// `yaml_source` is the text of the configuration file, used to report where unknown keys are.
+pub fn deserialize<T: DeserializeOwned>(
+    config: Config,
+    origin: &str,
+    yaml_source: Option<&str>,
+) -> Result<T, ConfigError> {
+    let mut unknown: Vec<UnknownKey> = vec![];
+    let value: T = serde_ignored::deserialize(config, |path| {
+        let path = segments(&path);
+        if !path.iter().any(|s| s.starts_with(EXTENSION_PREFIX)) {
+            let location = yaml_source.and_then(|source| locate(source, &path));
+            unknown.push(UnknownKey { path, location });
+        }
+    })?;
+
+    if unknown.is_empty() {
+        return Ok(value);
+    }
+    let report: Vec<String> = unknown
+        .iter()
+        .map(|key| match key.location {
+            Some((line, column)) => format!(
+                "unknown key `{}` at {}:{}:{}",
+                key.path.join("."),
+                origin,
+                line,
+                column
+            ),
+            None => format!("unknown key `{}` in {}", key.path.join("."), origin),
+        })
+        .collect();
+    Err(ConfigError::Message(format!(
+        "{}\nprefix custom metadata keys with `{}` to keep them",
+        report.join("\n"),
+        EXTENSION_PREFIX
+    )))
+}
+
+enum Frame {
+    Mapping { key: Option<String> },
+    Sequence { index: usize },
+}
+
+fn value_done(stack: &mut [Frame]) {
+    match stack.last_mut() {
+        Some(Frame::Mapping { key }) => *key = None,
+        Some(Frame::Sequence { index }) => *index += 1,
+        None => {}
+    }
+}
+
+/// Find the 1-based line and column of the key at `path` in a YAML document.
+/// Keys are compared case-insensitively as the configuration loader lowercases them.
+pub fn locate(source: &str, path: &[String]) -> Option<(usize, usize)> {
+    let mut parser = Parser::new(source.chars());
+    let mut stack: Vec<Frame> = vec![];
+    loop {
+        let (event, marker) = parser.next().ok()?;
+        match event {
+            Event::StreamEnd => return None,
+            Event::MappingStart(_) => stack.push(Frame::Mapping { key: None }),
+            Event::SequenceStart(_) => stack.push(Frame::Sequence { index: 0 }),
+            Event::MappingEnd | Event::SequenceEnd => {
+                stack.pop();
+                value_done(&mut stack);
+            }
+            Event::Scalar(scalar, ..) => {
+                if let Some(Frame::Mapping { key: key @ None }) = stack.last_mut() {
+                    *key = Some(scalar);
+                    let current: Vec<String> = stack
+                        .iter()
+                        .map(|frame| match frame {
+                            Frame::Mapping { key } => key.clone().unwrap_or_default(),
+                            Frame::Sequence { index } => index.to_string(),
+                        })
+                        .collect();
+                    let found = current.len() == path.len()
+                        && current
+                            .iter()
+                            .zip(path)
+                            .all(|(a, b)| a.eq_ignore_ascii_case(b));
+                    if found {
+                        return Some((marker.line(), marker.col() + 1));
+                    }
+                } else {
+                    value_done(&mut stack);
+                }
+            }
+            Event::Alias(_) => value_done(&mut stack),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_locate_keys() {
+        let source = "name: a\ndependencies:\n  platforms:\n    ubuntu:\n      versions:\n        - '18.04'\n      install_prefix: /opt\n";
+        let path = |p: &str| p.split('.').map(String::from).collect::<Vec<String>>();
+
+        assert_eq!(Some((1, 1)), locate(source, &path("name")));
+        assert_eq!(
+            Some((7, 7)),
+            locate(
+                source,
+                &path("dependencies.platforms.ubuntu.install_prefix")
+            )
+        );
+        assert_eq!(
+            Some((4, 5)),
+            locate(source, &path("dependencies.platforms.UBUNTU"))
+        );
+        assert_eq!(None, locate(source, &path("dependencies.install_prefix")));
+    }
+
+    #[test]
+    fn can_locate_keys_in_sequences() {
+        let source = "download:\n  - uri: a\n  - uri: b\n    filename: c\n";
+        let path: Vec<String> = vec!["download".into(), "1".into(), "filename".into()];
+
+        assert_eq!(Some((4, 5)), locate(source, &path));
+    }
+}
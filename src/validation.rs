@@ -0,0 +1,545 @@
+use std::{fmt, net::IpAddr, path::Path};
+
+use config::{Config, ConfigError, Value};
+use validator::{Validate, ValidationErrors};
+
+use crate::{
+    Application, CommandLine, Download, Downloads, Exposes, Install, OffSetup, Package,
+    PackageGroups, Packages, Platform, Source, Start, System,
+};
+
+/// URI schemes downloads can be fetched with
+const DOWNLOAD_SCHEMES: &[&str] = &["file", "ftp", "http", "https"];
+const VERSION_OPERATORS: &[&str] = &[">=", "<=", "==", "!=", ">", "<", "=", "^", "~"];
+
+/// A rule broken by the configuration, at the dotted key path of the offending value
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+struct Violations(Vec<Violation>);
+
+impl Violations {
+    fn push(&mut self, path: &str, message: String) {
+        self.0.push(Violation {
+            path: path.into(),
+            message,
+        });
+    }
+}
+
+/// Check `offsetup` against every rule, collecting all violations rather than stopping at the
+/// first one. `raw` is the merged configuration it was deserialized from, used to resolve `$ref`s.
+pub fn validate(offsetup: &OffSetup, raw: &Config) -> Vec<Violation> {
+    let mut violations = Violations(vec![]);
+
+    if let Some(dependencies) = &offsetup.dependencies {
+        if let Some(platforms) = &dependencies.platforms {
+            let mut names: Vec<&String> = platforms.keys().collect();
+            names.sort();
+            for name in names {
+                let path = format!("dependencies.platforms.{}", name);
+                check_platform(&mut violations, &path, &platforms[name], raw);
+            }
+        }
+        if let Some(applications) = &dependencies.applications {
+            let mut names: Vec<&String> = applications.keys().collect();
+            names.sort();
+            for name in names {
+                let path = format!("dependencies.applications.{}", name);
+                check_application(&mut violations, &path, &applications[name]);
+                for (idx, dependency) in applications[name].depends_on.iter().flatten().enumerate()
+                {
+                    if !applications.contains_key(dependency) {
+                        violations.push(
+                            &format!("{}.depends_on.{}", path, idx),
+                            format!("unknown application {:?}", dependency),
+                        );
+                    }
+                }
+            }
+            if let Err(cycle) = dependencies.application_order() {
+                violations.push(
+                    &format!("dependencies.applications.{}.depends_on", cycle[0]),
+                    format!("depends on itself: {}", cycle.join(" -> ")),
+                );
+            }
+        }
+    }
+    if let Some(exposes) = &offsetup.exposes {
+        check_exposes(&mut violations, "exposes", exposes);
+    }
+    if let Some(start) = &offsetup.start {
+        check_start(&mut violations, "start", start);
+    }
+
+    violations.0
+}
+
+/// `validate`, as a load error listing every violation
+pub fn check(offsetup: &OffSetup, raw: &Config) -> Result<(), ConfigError> {
+    let violations = validate(offsetup, raw);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let report: Vec<String> = violations.iter().map(ToString::to_string).collect();
+    Err(ConfigError::Message(report.join("\n")))
+}
+
+fn check_platform(violations: &mut Violations, path: &str, platform: &Platform, raw: &Config) {
+    for (idx, version) in platform.versions.iter().enumerate() {
+        check_version(violations, &format!("{}.versions.{}", path, idx), version);
+    }
+    if let Some(system) = &platform.system {
+        check_system(violations, &format!("{}.system", path), system);
+    }
+    if let Some(source) = &platform.source {
+        check_source(violations, &format!("{}.source", path), source, raw);
+    }
+    if let Err(errors) = platform.validate() {
+        validator_messages(violations, path, &errors);
+    }
+    if let Some(downloads) = &platform.download {
+        check_downloads(violations, &format!("{}.download", path), downloads);
+    }
+}
+
+fn check_application(violations: &mut Violations, path: &str, application: &Application) {
+    if let Some(version) = &application.version {
+        check_version(violations, &format!("{}.version", path), version);
+    }
+    if let Some(environment) = &application.environment {
+        check_env(violations, &format!("{}.environment", path), environment);
+    }
+    if let Some(exposes) = &application.exposes {
+        check_exposes(violations, &format!("{}.exposes", path), exposes);
+    }
+}
+
+fn check_source(violations: &mut Violations, path: &str, source: &Source, raw: &Config) {
+    if let Err(errors) = source.validate() {
+        validator_messages(violations, path, &errors);
+    }
+    if let Some(downloads) = &source.download {
+        check_downloads(violations, &format!("{}.download", path), downloads);
+    }
+    if let Some(system) = &source.system {
+        check_system(violations, &format!("{}.system", path), system);
+    }
+    if let Some(Install::Ref { reference }) = &source.install {
+        check_reference(
+            violations,
+            &format!("{}.install.$ref", path),
+            reference,
+            raw,
+        );
+    }
+}
+
+fn validator_messages(violations: &mut Violations, path: &str, errors: &ValidationErrors) {
+    let mut fields: Vec<(&str, _)> = errors.field_errors().into_iter().collect();
+    fields.sort_by_key(|(field, _)| *field);
+    for (field, errors) in fields {
+        // schema level rules are reported by the validator under `__all__`
+        let path = match field {
+            "__all__" => path.to_string(),
+            field => format!("{}.{}", path, field),
+        };
+        for error in errors {
+            violations.push(&path, error.code.replace('_', " "));
+        }
+    }
+}
+
+fn check_downloads(violations: &mut Violations, path: &str, downloads: &Downloads) {
+    match downloads {
+        Downloads::One(download) => check_download(violations, path, download),
+        Downloads::Many(downloads) => {
+            for (idx, download) in downloads.iter().enumerate() {
+                check_download(violations, &format!("{}.{}", path, idx), download);
+            }
+        }
+    }
+}
+
+fn check_download(violations: &mut Violations, path: &str, download: &Download) {
+    if download.sha512.len() != 128 || !download.sha512.chars().all(|c| c.is_ascii_hexdigit()) {
+        violations.push(
+            &format!("{}.sha512", path),
+            format!(
+                "expected 128 hexadecimal characters, got {:?}",
+                download.sha512
+            ),
+        );
+    }
+    let scheme = download.uri.scheme.to_lowercase();
+    if !DOWNLOAD_SCHEMES.contains(&scheme.as_str()) {
+        violations.push(
+            &format!("{}.uri", path),
+            format!(
+                "unsupported scheme {:?}, expected one of {}",
+                download.uri.scheme,
+                DOWNLOAD_SCHEMES.join(", ")
+            ),
+        );
+    }
+}
+
+fn check_start(violations: &mut Violations, path: &str, start: &Start) {
+    let empty = match &start.command {
+        CommandLine::Shell(command) => command.trim().is_empty(),
+        CommandLine::Args(args) => args.is_empty(),
+    };
+    if empty {
+        violations.push(&format!("{}.command", path), "command is empty".into());
+    }
+    if let Some(env) = &start.env {
+        check_env(violations, &format!("{}.env", path), env);
+    }
+}
+
+fn check_env(violations: &mut Violations, path: &str, env: &[String]) {
+    for (idx, variable) in env.iter().enumerate() {
+        if variable
+            .split_once('=')
+            .is_none_or(|(key, _)| key.is_empty())
+        {
+            violations.push(
+                &format!("{}.{}", path, idx),
+                format!("invalid variable {:?}, expected KEY=value", variable),
+            );
+        }
+    }
+}
+
+fn check_exposes(violations: &mut Violations, path: &str, exposes: &Exposes) {
+    match exposes {
+        Exposes::Ports {
+            tcp,
+            udp,
+            unix,
+            port_range,
+            readiness,
+            firewall,
+        } => {
+            let mut names = vec![];
+            for (protocol, ports) in &[("tcp", tcp), ("udp", udp)] {
+                for (idx, port) in ports.iter().flatten().enumerate() {
+                    let path = format!("{}.ports.{}.{}", path, protocol, idx);
+                    if port.port == 0 {
+                        violations.push(&path, "port must be between 1 and 65535".into());
+                    }
+                    if let Some(end) = port.end {
+                        if end < port.port {
+                            violations.push(
+                                &path,
+                                format!(
+                                    "invalid range {}-{}, expected start <= end",
+                                    port.port, end
+                                ),
+                            );
+                        }
+                    }
+                    if let Some(bind) = &port.bind {
+                        if bind.parse::<IpAddr>().is_err() {
+                            violations.push(
+                                &format!("{}.bind", path),
+                                format!(
+                                    "invalid bind address {:?}, expected eg: 127.0.0.1 or 0.0.0.0",
+                                    bind
+                                ),
+                            );
+                        }
+                    }
+                    check_name(violations, &mut names, &path, port.name.as_deref());
+                }
+            }
+            for (idx, socket) in unix.iter().flatten().enumerate() {
+                let path = format!("{}.ports.unix.{}", path, idx);
+                if !Path::new(&socket.path).is_absolute() {
+                    violations.push(
+                        &path,
+                        format!("socket path {:?} must be absolute", socket.path),
+                    );
+                }
+                check_name(violations, &mut names, &path, socket.name.as_deref());
+            }
+            if let Some(range) = port_range {
+                if range.start == 0 || range.start > range.end {
+                    violations.push(
+                        &format!("{}.ports.port_range", path),
+                        format!(
+                            "invalid range {}-{}, expected 1 <= start <= end",
+                            range.start, range.end
+                        ),
+                    );
+                }
+            }
+            if let Some(firewall) = firewall {
+                for (idx, source) in firewall.sources.iter().flatten().enumerate() {
+                    if !is_cidr(source) {
+                        violations.push(
+                            &format!("{}.ports.firewall.sources.{}", path, idx),
+                            format!(
+                                "invalid source {:?}, expected an address or CIDR, eg: 10.0.0.0/8",
+                                source
+                            ),
+                        );
+                    }
+                }
+            }
+            if let Some(readiness) = readiness {
+                let path = format!("{}.ports.readiness", path);
+                if readiness.timeout == Some(0) {
+                    violations.push(
+                        &format!("{}.timeout", path),
+                        "timeout must be at least 1 second".into(),
+                    );
+                }
+                if let Some(http) = &readiness.http {
+                    if !http.path.starts_with('/') {
+                        violations.push(
+                            &format!("{}.http.path", path),
+                            format!("path {:?} must start with /", http.path),
+                        );
+                    }
+                    if http.port.is_none() && tcp.as_ref().is_none_or(Vec::is_empty) {
+                        violations.push(
+                            &format!("{}.http.port", path),
+                            "no port to probe, set one or declare a tcp port".into(),
+                        );
+                    }
+                    if let Some(status) = http.status {
+                        if !(100..=599).contains(&status) {
+                            violations.push(
+                                &format!("{}.http.status", path),
+                                format!("invalid HTTP status {}", status),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Names of ports and sockets are unique, so that other settings can refer to them
+fn check_name<'a>(
+    violations: &mut Violations,
+    names: &mut Vec<&'a str>,
+    path: &str,
+    name: Option<&'a str>,
+) {
+    if let Some(name) = name {
+        if names.contains(&name) {
+            violations.push(path, format!("name {:?} is already used", name));
+        }
+        names.push(name);
+    }
+}
+
+/// An IP address, optionally followed by a prefix length its family allows
+fn is_cidr(source: &str) -> bool {
+    let mut parts = source.splitn(2, '/');
+    let address: IpAddr = match parts.next().and_then(|address| address.parse().ok()) {
+        Some(address) => address,
+        None => return false,
+    };
+    let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+    match parts.next() {
+        Some(prefix) => prefix
+            .parse::<u8>()
+            .is_ok_and(|prefix| prefix <= max_prefix),
+        None => true,
+    }
+}
+
+fn check_version(violations: &mut Violations, path: &str, constraint: &str) {
+    if !is_version_constraint(constraint) {
+        violations.push(
+            path,
+            format!(
+                "invalid version constraint {:?}, expected eg: '1.2', '>=10.14' or '>9.6, <11'",
+                constraint
+            ),
+        );
+    }
+}
+
+/// One or more comma separated constraints, each an optional operator followed by a dotted
+/// version whose parts may be `*` or `x` wildcards
+pub fn is_version_constraint(constraint: &str) -> bool {
+    constraint.split(',').all(|part| {
+        let part = part.trim();
+        let version = VERSION_OPERATORS
+            .iter()
+            .find(|op| part.starts_with(*op))
+            .map_or(part, |op| part[op.len()..].trim_start());
+        !version.is_empty()
+            && version.split('.').all(|segment| {
+                !segment.is_empty()
+                    && (segment == "*"
+                        || segment
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-'))
+            })
+            && version.starts_with(|c: char| c.is_ascii_digit() || c == '*')
+    })
+}
+
+fn check_system(violations: &mut Violations, path: &str, system: &System) {
+    for (manager, packages) in system.managers() {
+        let path = format!("{}.{}", path, manager);
+        match packages {
+            Packages::List(list) => check_packages(violations, &path, manager, list),
+            Packages::Grouped(PackageGroups {
+                sharable,
+                exclusive,
+            }) => {
+                for (group, list) in &[("sharable", sharable), ("exclusive", exclusive)] {
+                    if let Some(list) = list {
+                        let path = format!("{}.{}", path, group);
+                        check_packages(violations, &path, manager, list);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn check_packages(violations: &mut Violations, path: &str, manager: &str, packages: &[Package]) {
+    for (idx, package) in packages.iter().enumerate() {
+        let spec = package.resolve(true).spec();
+        if !is_package_spec(manager, &spec) {
+            violations.push(
+                &format!("{}.{}", path, idx),
+                format!("invalid {} package name {:?}", manager, spec),
+            );
+        }
+    }
+}
+
+/// Whether `spec`, a package name optionally pinned with `=version`, is valid for `manager`
+pub fn is_package_spec(manager: &str, spec: &str) -> bool {
+    let (name, version) = match spec.find('=') {
+        Some(idx) => (&spec[..idx], Some(&spec[idx + 1..])),
+        None => (spec, None),
+    };
+    if version.is_some_and(|v| v.is_empty() || v.contains(char::is_whitespace)) {
+        return false;
+    }
+    let first = match name.chars().next() {
+        Some(first) => first,
+        None => return false,
+    };
+    let chars = |allowed: &str| {
+        name.chars()
+            .all(|c| c.is_ascii_alphanumeric() || allowed.contains(c))
+    };
+    match manager {
+        // Debian policy: lowercase, at least two characters, optionally qualified by `:arch`
+        "apt" | "apt_get" | "aptitude" => {
+            let (name, arch) = match name.find(':') {
+                Some(idx) => (&name[..idx], Some(&name[idx + 1..])),
+                None => (name, None),
+            };
+            name.len() >= 2
+                && first.is_ascii_alphanumeric()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c))
+                && arch.is_none_or(|a| {
+                    !a.is_empty() && a.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                })
+        }
+        "pacman" => !name.starts_with(['-', '.']) && name.to_lowercase() == name && chars("@._+-/"),
+        // formulae may be qualified by their tap: `user/repo/formula`
+        "brew" => {
+            first.is_ascii_alphanumeric()
+                && name.to_lowercase() == name
+                && name.matches('/').count() <= 2
+                && chars("@._+-/")
+        }
+        "yum" | "dnf" | "up2date" | "urpmi" => first.is_ascii_alphanumeric() && chars("._+-:"),
+        "choco" => first.is_ascii_alphanumeric() && chars("._-"),
+        _ => !name.contains(char::is_whitespace),
+    }
+}
+
+/// `$ref`s are JSON pointers into the configuration itself, eg: `#/x-shared/source/install`
+fn check_reference(violations: &mut Violations, path: &str, reference: &str, raw: &Config) {
+    let pointer = match reference.strip_prefix("#/") {
+        Some(pointer) if !pointer.is_empty() => pointer,
+        _ => {
+            violations.push(
+                path,
+                format!(
+                    "invalid reference {:?}, expected a pointer like '#/x-shared/install'",
+                    reference
+                ),
+            );
+            return;
+        }
+    };
+    let key = pointer
+        .split('/')
+        .map(|segment| match segment.parse::<usize>() {
+            Ok(idx) => format!("[{}]", idx),
+            Err(_) => format!(".{}", segment.to_lowercase()),
+        })
+        .collect::<String>();
+    if raw.get::<Value>(key.trim_start_matches('.')).is_err() {
+        violations.push(
+            path,
+            format!("reference {:?} does not point to any key", reference),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_check_version_constraints() {
+        for valid in &[
+            "14.04", ">16.04", ">=10.14", ">9.6.4", ">5", "^1.2", "1.x", ">=9, <11",
+        ] {
+            assert!(is_version_constraint(valid), "{} should be valid", valid);
+        }
+        for invalid in &["", ">", "latest", "1..2", ">= 9 <11", "v1.2"] {
+            assert!(
+                !is_version_constraint(invalid),
+                "{} should be invalid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn can_check_package_names() {
+        assert!(is_package_spec("apt", "build-essential"));
+        assert!(is_package_spec("apt", "libc6:i386"));
+        assert!(is_package_spec("apt", "redis-server=5:5.0.4-1"));
+        assert!(!is_package_spec("apt", "Redis"));
+        assert!(!is_package_spec("apt", "r"));
+        assert!(!is_package_spec("apt", "redis="));
+        assert!(is_package_spec("brew", "python@2"));
+        assert!(is_package_spec("brew", "homebrew/cask/firefox"));
+        assert!(!is_package_spec("brew", "a/b/c/d"));
+        assert!(is_package_spec("pacman", "base-devel"));
+        assert!(!is_package_spec("pacman", "-Syu"));
+        assert!(is_package_spec("choco", "7zip.install"));
+        assert!(!is_package_spec("choco", "7zip install"));
+        assert!(is_package_spec("yum", "gcc-c++"));
+        assert!(!is_package_spec("nix", ""));
+    }
+}